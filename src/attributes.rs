@@ -0,0 +1,517 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    config::Config,
+    objects::{blob::Blob, is_text, tree::Tree},
+    paths::repository_root_path,
+};
+
+pub const ATTRIBUTES_FILE_NAME: &str = ".rygitattributes";
+
+// attributes format, one rule per line:
+// <pattern> <attribute> <attribute>...
+//
+// Attributes are `text`, `-text`, `binary` (same as `-text`), `eol=lf|crlf`,
+// `diff=<driver>`, `filter=<driver>` and `merge=<driver>`. A pattern without
+// a slash matches file names anywhere, otherwise the path from the repository
+// root. `*` and `?` don't match `/`, `**` does. Later rules override earlier
+// ones.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Attributes {
+    rules: Vec<(String, PathAttributes)>,
+}
+
+/// The attributes that apply to one path. `None` means unspecified.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PathAttributes {
+    pub text: Option<bool>,
+    pub eol: Option<Eol>,
+    /// The `[diff "<driver>"]` config section used when diffing the path.
+    pub diff: Option<String>,
+    /// The `[filter "<driver>"]` config section whose `clean` and `smudge`
+    /// commands convert the path's content on the way in and out.
+    pub filter: Option<String>,
+    /// The `[merge "<driver>"]` config section whose `driver` command merges
+    /// the path when both sides changed it.
+    pub merge: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+}
+
+/// The `core.autocrlf` setting, which converts line endings of files that
+/// look like text when attributes don't say otherwise. `True` stores LF and
+/// checks out CRLF, `Input` only normalizes to LF on the way in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AutoCrlf {
+    True,
+    Input,
+    #[default]
+    False,
+}
+
+impl AutoCrlf {
+    pub fn load() -> Result<Self> {
+        let config = Config::load()?;
+        match config
+            .get("core.autocrlf")
+            .map(|v| v.to_lowercase())
+            .as_deref()
+        {
+            Some("input") => Ok(AutoCrlf::Input),
+            _ => match config.get_bool("core.autocrlf")? {
+                Some(true) => Ok(AutoCrlf::True),
+                _ => Ok(AutoCrlf::False),
+            },
+        }
+    }
+}
+
+impl Attributes {
+    /// Loads the attributes file at the root of the working tree, if any.
+    pub fn load() -> Result<Self> {
+        let path = repository_root_path().join(ATTRIBUTES_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path).context("Unable to read attributes")?;
+        Ok(Self::parse(&contents))
+    }
+
+    /// Loads the attributes file stored at the root of `tree`, if any.
+    pub fn from_tree(tree: &Tree) -> Result<Self> {
+        match tree.files().get(&PathBuf::from(ATTRIBUTES_FILE_NAME)) {
+            Some(hash) => {
+                let body = Blob::load(hash)
+                    .body()
+                    .context("Unable to read attributes")?;
+                Ok(Self::parse(&String::from_utf8_lossy(&body)))
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut rules = vec![];
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let Some(pattern) = parts.next() else {
+                continue;
+            };
+            let mut attributes = PathAttributes::default();
+            for attribute in parts {
+                match attribute {
+                    "text" => attributes.text = Some(true),
+                    "-text" | "binary" => attributes.text = Some(false),
+                    "eol=lf" => attributes.eol = Some(Eol::Lf),
+                    "eol=crlf" => attributes.eol = Some(Eol::Crlf),
+                    _ => {
+                        if let Some(driver) = attribute.strip_prefix("diff=") {
+                            attributes.diff = Some(driver.to_string());
+                        } else if let Some(driver) = attribute.strip_prefix("filter=") {
+                            attributes.filter = Some(driver.to_string());
+                        } else if let Some(driver) = attribute.strip_prefix("merge=") {
+                            attributes.merge = Some(driver.to_string());
+                        }
+                    }
+                }
+            }
+            rules.push((pattern.to_string(), attributes));
+        }
+
+        Self { rules }
+    }
+
+    /// The attributes for `path`, relative to the repository root.
+    pub fn for_path(&self, path: &Path) -> PathAttributes {
+        let path = path.to_string_lossy();
+        let file_name = path.rsplit('/').next().unwrap_or(&path);
+
+        let mut attributes = PathAttributes::default();
+        for (pattern, rule) in &self.rules {
+            let matched = match pattern.strip_prefix('/') {
+                Some(anchored) => glob_match(anchored, &path),
+                None if pattern.contains('/') => glob_match(pattern, &path),
+                None => glob_match(pattern, file_name),
+            };
+            if matched {
+                attributes.text = rule.text.or(attributes.text);
+                attributes.eol = rule.eol.or(attributes.eol);
+                if rule.diff.is_some() {
+                    attributes.diff = rule.diff.clone();
+                }
+                if rule.filter.is_some() {
+                    attributes.filter = rule.filter.clone();
+                }
+                if rule.merge.is_some() {
+                    attributes.merge = rule.merge.clone();
+                }
+            }
+        }
+
+        attributes
+    }
+}
+
+impl PathAttributes {
+    /// Whether the attributes mark the path as text, if they say either way.
+    /// Setting `eol` implies `text`.
+    pub fn is_text(&self) -> Option<bool> {
+        self.text.or(self.eol.map(|_| true))
+    }
+}
+
+/// Converts working tree content for storage. The clean command of the
+/// path's filter runs first, then CRLF is normalized to LF for text paths or,
+/// without attributes, for text content when `core.autocrlf` is on. `path` is
+/// relative to the repository root.
+pub fn to_stored(path: &Path, content: Vec<u8>) -> Result<Vec<u8>> {
+    let attributes = Attributes::load()?.for_path(path);
+    let content = apply_filter(&attributes, "clean", path, content)?;
+    let converts = match attributes.is_text() {
+        Some(text) => text,
+        None => AutoCrlf::load()? != AutoCrlf::False && is_text(&content),
+    };
+    if !converts {
+        return Ok(content);
+    }
+
+    Ok(crlf_to_lf(&content))
+}
+
+/// Converts stored content to text for diffing by piping it through the
+/// `diff.<driver>.textconv` command of the path's `diff` attribute. Content
+/// is returned unchanged when no textconv is configured for the path.
+pub fn textconv(path: &Path, content: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(driver) = Attributes::load()?.for_path(path).diff else {
+        return Ok(content);
+    };
+    let config = Config::load()?;
+    let Some(command) = config.get(&format!("diff.{driver}.textconv")) else {
+        return Ok(content);
+    };
+
+    run_filter(command, &content).with_context(|| {
+        format!(
+            "Unable to convert {} for diffing. Unable to run textconv `{command}`",
+            path.display()
+        )
+    })
+}
+
+/// Pipes `content` through the `filter.<driver>.<kind>` command of the
+/// path's filter. Content is returned unchanged when the path has no filter
+/// or the filter has no such command.
+fn apply_filter(
+    attributes: &PathAttributes,
+    kind: &str,
+    path: &Path,
+    content: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let Some(driver) = &attributes.filter else {
+        return Ok(content);
+    };
+    let config = Config::load()?;
+    let Some(command) = config.get(&format!("filter.{driver}.{kind}")) else {
+        return Ok(content);
+    };
+
+    run_filter(command, &content).with_context(|| {
+        format!(
+            "Unable to {kind} {}. Unable to run filter `{command}`",
+            path.display()
+        )
+    })
+}
+
+/// Runs `command` through the shell with `input` on stdin and returns what
+/// it writes to stdout.
+fn run_filter(command: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().context("Unable to open stdin")?;
+    // Writing from another thread keeps a filter that fills its stdout pipe
+    // before reading all of stdin from deadlocking.
+    let output = thread::scope(|scope| {
+        scope.spawn(move || stdin.write_all(input));
+        child.wait_with_output()
+    })?;
+    if !output.status.success() {
+        bail!(
+            "It exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+/// Converts stored content for the working tree, using CRLF line endings for
+/// text paths with `eol=crlf`, or when `core.autocrlf` is `true` and the
+/// attributes don't set `eol`. The smudge command of the path's filter runs
+/// last.
+pub fn to_worktree(
+    attributes: &Attributes,
+    autocrlf: AutoCrlf,
+    path: &Path,
+    content: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let attributes = attributes.for_path(path);
+    let text = match attributes.is_text() {
+        Some(text) => text,
+        None => is_text(&content),
+    };
+    let eol = match attributes.eol {
+        Some(eol) => eol,
+        None if autocrlf == AutoCrlf::True => Eol::Crlf,
+        None => Eol::Lf,
+    };
+    let content = if text && eol == Eol::Crlf {
+        lf_to_crlf(&content)
+    } else {
+        content
+    };
+
+    apply_filter(&attributes, "smudge", path, content)
+}
+
+pub fn crlf_to_lf(content: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(content.len());
+    for (i, &byte) in content.iter().enumerate() {
+        if byte == b'\r' && content.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        converted.push(byte);
+    }
+
+    converted
+}
+
+pub fn lf_to_crlf(content: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(content.len());
+    for (i, &byte) in content.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || content[i - 1] != b'\r') {
+            converted.push(b'\r');
+        }
+        converted.push(byte);
+    }
+
+    converted
+}
+
+/// Matches `text` against a glob pattern where `*` and `?` stop at `/` and
+/// `**` matches across directories.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[u8], text: &[u8]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            [b'*', b'*', rest @ ..] => {
+                let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+                (0..=text.len()).any(|i| matches(rest, &text[i..]))
+            }
+            [b'*', rest @ ..] => (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != b'/')
+                .any(|i| matches(rest, &text[i..])),
+            [b'?', rest @ ..] => text
+                .first()
+                .is_some_and(|&c| c != b'/' && matches(rest, &text[1..])),
+            [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+        }
+    }
+
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        hash::Hash, objects::commit::Commit, paths::head_ref_path, test_utils::TestRepo, worktree,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_for_path() {
+        assert!(glob_match("*.txt", "a.txt"));
+        assert!(!glob_match("*.txt", "dir/a.txt"));
+        assert!(glob_match("docs/**/*.md", "docs/a/b/c.md"));
+        assert!(glob_match("docs/**/*.md", "docs/c.md"));
+        assert!(glob_match("?.rs", "a.rs"));
+
+        let attributes = Attributes::parse(
+            "# line endings\n*.txt text eol=lf\n*.bat eol=crlf\n/docs/*.txt -text\n*.png binary\n*.pdf binary diff=pdf\n",
+        );
+        assert_eq!(
+            PathAttributes {
+                text: Some(true),
+                eol: Some(Eol::Lf),
+                diff: None,
+                filter: None,
+                merge: None,
+            },
+            attributes.for_path(Path::new("src/a.txt"))
+        );
+        assert_eq!(
+            PathAttributes {
+                text: Some(false),
+                eol: Some(Eol::Lf),
+                diff: None,
+                filter: None,
+                merge: None,
+            },
+            attributes.for_path(Path::new("docs/a.txt"))
+        );
+        assert_eq!(
+            Some(true),
+            attributes.for_path(Path::new("run.bat")).is_text()
+        );
+        assert_eq!(
+            Some(false),
+            attributes.for_path(Path::new("image.png")).is_text()
+        );
+        assert_eq!(
+            Some("pdf".to_string()),
+            attributes.for_path(Path::new("docs/manual.pdf")).diff
+        );
+        assert_eq!(
+            PathAttributes::default(),
+            attributes.for_path(Path::new("README"))
+        );
+    }
+
+    #[test]
+    fn test_line_endings_round_trip() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file(
+            ATTRIBUTES_FILE_NAME,
+            "*.txt text eol=lf\n*.bat text eol=crlf\n",
+        )?
+        .file("a.txt", "a\r\nb\r\n")?
+        .file("run.bat", "echo a\r\necho b\r\n")?
+        .file("raw.dat", "raw\r\n")?
+        .stage(".")?
+        .commit("Add files")?;
+
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        let tree = commit.tree()?;
+        let stored = |path: &str| Blob::load(&tree.files()[&PathBuf::from(path)]).body();
+        assert_eq!(b"a\nb\n".to_vec(), stored("a.txt")?);
+        assert_eq!(b"echo a\necho b\n".to_vec(), stored("run.bat")?);
+        assert_eq!(b"raw\r\n".to_vec(), stored("raw.dat")?);
+
+        worktree::checkout(&tree)?;
+        assert_eq!(b"a\nb\n".to_vec(), fs::read(repo.path().join("a.txt"))?);
+        assert_eq!(
+            b"echo a\r\necho b\r\n".to_vec(),
+            fs::read(repo.path().join("run.bat"))?
+        );
+        assert_eq!(b"raw\r\n".to_vec(), fs::read(repo.path().join("raw.dat"))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_autocrlf() -> Result<()> {
+        for (autocrlf, stored, checked_out) in [
+            ("true", "a\nb\n", "a\r\nb\r\n"),
+            ("input", "a\nb\n", "a\nb\n"),
+            ("false", "a\r\nb\r\n", "a\r\nb\r\n"),
+        ] {
+            let repo = TestRepo::new()?;
+            let mut config = Config::load()?;
+            config.set("core.autocrlf", autocrlf)?;
+            config.save()?;
+            repo.file(ATTRIBUTES_FILE_NAME, "*.bin binary\n")?
+                .file("a.txt", "a\r\nb\r\n")?
+                .file("b.bin", "a\r\nb\r\n")?
+                .stage(".")?
+                .commit("Add files")?;
+
+            let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+            let tree = commit.tree()?;
+            let stored_body = |path: &str| Blob::load(&tree.files()[&PathBuf::from(path)]).body();
+            assert_eq!(
+                stored.as_bytes(),
+                stored_body("a.txt")?,
+                "autocrlf={autocrlf}"
+            );
+            assert_eq!(b"a\r\nb\r\n".to_vec(), stored_body("b.bin")?);
+
+            worktree::checkout(&tree)?;
+            assert_eq!(
+                checked_out.as_bytes(),
+                fs::read(repo.path().join("a.txt"))?,
+                "autocrlf={autocrlf}"
+            );
+            assert_eq!(b"a\r\nb\r\n".to_vec(), fs::read(repo.path().join("b.bin"))?);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clean_and_smudge_filters() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let mut config = Config::load()?;
+        config.set("filter.case.clean", "tr a-z A-Z")?;
+        config.set("filter.case.smudge", "tr A-Z a-z")?;
+        config.set("filter.broken.clean", "echo oops >&2; exit 3")?;
+        config.save()?;
+        repo.file(
+            ATTRIBUTES_FILE_NAME,
+            "*.txt filter=case\n*.bad filter=broken\n",
+        )?
+        .file("a.txt", "hello\n")?
+        .file("b.md", "hello\n")?
+        .stage(".")?
+        .commit("Initial commit")?
+        .branch("first")?
+        .file("a.txt", "bye\n")?
+        .stage(".")?
+        .commit("Second commit")?;
+
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        let tree = commit.tree()?;
+        let stored = |path: &str| Blob::load(&tree.files()[&PathBuf::from(path)]).body();
+        assert_eq!(b"BYE\n".to_vec(), stored("a.txt")?);
+        assert_eq!(b"hello\n".to_vec(), stored("b.md")?);
+
+        repo.switch("first")?;
+        assert_eq!("hello\n", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        repo.file("c.bad", "c\n")?;
+        let Err(error) = repo.stage("c.bad") else {
+            panic!("Staging c.bad should fail");
+        };
+        let message = format!("{error:#}");
+        assert!(message.contains("Unable to clean c.bad"), "{message}");
+        assert!(message.contains("oops"), "{message}");
+
+        Ok(())
+    }
+}
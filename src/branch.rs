@@ -1,81 +1,134 @@
 use std::fs;
 
 use anyhow::{Context, Ok, Result, bail};
-use walkdir::WalkDir;
 
 use crate::{
+    config::Config,
     hash::Hash,
-    objects::{blob::Blob, commit::Commit},
-    paths::{head_path, head_ref_path, refs_path, repository_root_path, rygit_path},
+    index::Index,
+    objects::commit::Commit,
+    paths::{WORKTREES_DIR, common_path, ensure_work_tree, head_path, refs_path, rygit_path},
+    refs::{list_refs, read_ref, read_symbolic, read_symbolic_in, ref_exists, update_ref},
+    revparse, worktree,
 };
 
 pub struct Branch {
     name: String,
-    commit_hash: Hash,
+    /// `None` until the branch's first commit, like `master` right after
+    /// init or a new orphan branch.
+    commit_hash: Option<Hash>,
 }
 
 impl Branch {
+    /// The name of the checked out branch, or `None` when HEAD is detached.
+    /// Unlike [`Branch::current`] this works before the branch's first
+    /// commit.
+    pub fn current_name() -> Result<Option<String>> {
+        let head = read_symbolic("HEAD").context("Unable to read head")?;
+        let name = head.and_then(|head| {
+            head.strip_prefix("refs/heads/")
+                .map(|name| name.to_string())
+        });
+
+        Ok(name)
+    }
+
     pub fn current() -> Result<Self> {
-        let head = fs::read_to_string(head_path()).context("Unable to read head")?;
-        let name = head
-            .strip_prefix("ref: refs/heads/")
-            .with_context(|| format!("Invalid head ref {head}"))?
-            .to_string();
-        let head_ref = fs::read_to_string(head_ref_path()).context("Unable to read head ref")?;
-        let commit_hash = Hash::from_hex(&head_ref)
-            .context("Unable to determine branch commit hash. Invalid format")?;
+        let name = Self::current_name()?.context("Invalid head ref. HEAD is detached")?;
+        let commit_hash = read_ref(&format!("refs/heads/{name}"))
+            .context("Unable to determine branch commit hash")?;
         let branch = Self { name, commit_hash };
 
         Ok(branch)
     }
 
-    pub fn create(name: impl Into<String>) -> Result<Self> {
+    /// Creates a branch at `start_point`, a revision such as `HEAD~2` or
+    /// another branch's name, or at the current branch's tip when omitted.
+    pub fn create(name: impl Into<String>, start_point: Option<&str>) -> Result<Self> {
+        let commit_hash = match start_point {
+            Some(start_point) => revparse::resolve(start_point)
+                .context("Unable to create branch. Invalid start point")?,
+            None => Branch::current()?
+                .tip()
+                .context("Unable to create branch")?,
+        };
+        Branch::create_at(name, commit_hash)
+    }
+
+    /// Creates a branch pointing at `commit_hash` rather than at the current
+    /// branch's tip.
+    pub fn create_at(name: impl Into<String>, commit_hash: Hash) -> Result<Self> {
         let name = name.into();
-        let commit_hash = Branch::current()?.commit_hash;
-        // TODO: What to do if branch already exists?
-        let ref_file_path = refs_path().join("heads").join(&name);
-        if ref_file_path.exists() {
+        if ref_exists(&format!("refs/heads/{name}"))? {
             bail!("Branch \"{name}\" already exists");
         }
-        fs::write(ref_file_path, commit_hash.to_hex())
-            .context("Unable to create branch. Unable to write ref file")?;
-        let branch = Self { name, commit_hash };
+        update_ref(&format!("refs/heads/{name}"), &commit_hash, None)
+            .context("Unable to create branch")?;
+        let branch = Self {
+            name,
+            commit_hash: Some(commit_hash),
+        };
+        Ok(branch)
+    }
+
+    /// Creates a local branch at a remote-tracking branch such as
+    /// `origin/feature` and records it as the local branch's upstream. The
+    /// local branch is named after the remote branch unless `name` is given.
+    pub fn track(remote_branch: &str, name: Option<String>) -> Result<Self> {
+        let (remote, remote_branch_name) = remote_branch.split_once('/').with_context(|| {
+            format!("Unable to track \"{remote_branch}\". Expected <remote>/<branch>")
+        })?;
+        let tracking = Branch::list_remotes()?
+            .into_iter()
+            .find(|b| b.name == remote_branch)
+            .with_context(|| format!("{remote_branch} is not a remote-tracking branch"))?;
+
+        let name = name.unwrap_or_else(|| remote_branch_name.to_string());
+        let branch = Branch::create_at(name, tracking.tip()?)?;
+
+        let mut config = Config::load()?;
+        config.set(&format!("branch.{}.remote", branch.name), remote)?;
+        config.set(
+            &format!("branch.{}.merge", branch.name),
+            format!("refs/heads/{remote_branch_name}"),
+        )?;
+        config.save()?;
+
         Ok(branch)
     }
 
     pub fn find_by_name(name: impl Into<String>) -> Result<Self> {
         let name = name.into();
-        let ref_path = refs_path().join("heads").join(&name);
-        if !ref_path.exists() {
+        let ref_name = format!("refs/heads/{name}");
+        if !ref_exists(&ref_name)? {
             bail!("{name} not a branch");
         }
 
-        let commit_hash = fs::read_to_string(&ref_path).context("Unable to read branch ref")?;
-        let commit_hash = Hash::from_hex(&commit_hash)
-            .context("Unable to load branch. Commit hash is not a valid hash")?;
+        let commit_hash = read_ref(&ref_name).context("Unable to load branch")?;
 
         Ok(Self { name, commit_hash })
     }
 
     pub fn list() -> Result<Vec<Branch>> {
-        let branches_path = refs_path().join("heads");
-        let branches: Vec<_> = WalkDir::new(&branches_path)
-            .min_depth(1)
+        Branch::list_in("refs/heads/")
+    }
+
+    /// Lists the remote-tracking branches under `refs/remotes`, named
+    /// `<remote>/<branch>`.
+    pub fn list_remotes() -> Result<Vec<Branch>> {
+        Branch::list_in("refs/remotes/")
+    }
+
+    /// The branches with commits under the ref directory `prefix`. A branch
+    /// without commits (e.g. master right after init) is left out.
+    fn list_in(prefix: &str) -> Result<Vec<Branch>> {
+        let branches = list_refs(prefix)?
             .into_iter()
-            .filter_entry(|e| e.path().is_file())
-            .map(|e| {
-                let e = e?;
-                let path = e.path();
-                let name = path
-                    .strip_prefix(&branches_path)?
-                    .to_string_lossy()
-                    .to_string();
-                let commit_hash = fs::read_to_string(path)?;
-                let commit_hash = Hash::from_hex(&commit_hash)?;
-
-                Ok(Self { name, commit_hash })
+            .map(|(name, commit_hash)| Self {
+                name: name[prefix.len()..].to_string(),
+                commit_hash: Some(commit_hash),
             })
-            .collect::<Result<_, _>>()?;
+            .collect();
 
         Ok(branches)
     }
@@ -84,48 +137,115 @@ impl Branch {
         &self.name
     }
 
+    /// The commit the branch points to, or `None` before its first commit.
+    pub fn commit_hash(&self) -> Option<&Hash> {
+        self.commit_hash.as_ref()
+    }
+
+    /// Like [`Branch::commit_hash`], but errors before the branch's first
+    /// commit.
+    pub fn tip(&self) -> Result<Hash> {
+        self.commit_hash
+            .with_context(|| format!("Branch {} does not have any commits yet", self.name))
+    }
+
+    /// Moves the branch to `commit_hash`. The working tree is untouched.
+    pub fn update(&mut self, commit_hash: Hash) -> Result<()> {
+        update_ref(
+            &format!("refs/heads/{}", self.name),
+            &commit_hash,
+            self.commit_hash.as_ref(),
+        )
+        .with_context(|| format!("Unable to update branch {}", self.name))?;
+        self.commit_hash = Some(commit_hash);
+
+        Ok(())
+    }
+
+    /// The remote-tracking branch recorded as this branch's upstream, e.g.
+    /// `origin/feature`.
+    pub fn upstream(&self) -> Result<Option<String>> {
+        let config = Config::load()?;
+        let remote = config.get(&format!("branch.{}.remote", self.name));
+        let merge = config.get(&format!("branch.{}.merge", self.name));
+        let upstream = match (remote, merge.and_then(|m| m.strip_prefix("refs/heads/"))) {
+            (Some(remote), Some(branch)) => Some(format!("{remote}/{branch}")),
+            _ => None,
+        };
+
+        Ok(upstream)
+    }
+
     pub fn switch(name: impl Into<String>) -> Result<()> {
+        ensure_work_tree().context("Unable to switch branches")?;
         let name = name.into();
         let branch = Branch::find_by_name(&name)?;
+        if branch.is_checked_out_elsewhere()? {
+            bail!("Unable to switch branches. {name} is checked out in another work tree");
+        }
         let commit = branch.commit()?;
         let tree = commit.tree()?;
 
-        let directory_contents =
-            fs::read_dir(repository_root_path()).context("Unable to read repository contents")?;
-        let rygit_path = rygit_path();
-        for entry in directory_contents {
-            let entry = entry.context("Unable to read repository contents")?;
-            let path = entry.path();
-            if path.starts_with(&rygit_path) {
-                continue;
-            }
+        worktree::checkout(&tree)?;
+        fs::write(head_path(), format!("ref: refs/heads/{name}"))?;
 
-            if path.is_file() {
-                fs::remove_file(&path)
-                    .with_context(|| format!("Unable to remove file {}", path.display()))?;
-            } else if path.is_dir() {
-                fs::remove_dir_all(&path)
-                    .with_context(|| format!("Unable to remove directory {}", path.display()))?;
-            }
-        }
+        Ok(())
+    }
 
-        for (entry_path, entry_hash) in tree.entries_flattened() {
-            let blob = Blob::load(entry_hash.object_path())?;
-            let body = blob.body()?;
-            if let Some(parent) = entry_path.parent() {
-                fs::create_dir_all(parent)
-                    .with_context(|| format!("unable to create file {}", entry_path.display()))?;
-            }
-            fs::write(entry_path, body)?;
+    /// Checks out `commit_hash` without a branch, leaving HEAD detached.
+    pub fn switch_detached(commit_hash: &Hash) -> Result<()> {
+        ensure_work_tree().context("Unable to check out commit")?;
+        let tree = Commit::load(commit_hash)?.tree()?;
+
+        worktree::checkout(&tree)?;
+        fs::write(head_path(), commit_hash.to_hex())?;
+
+        Ok(())
+    }
+
+    /// Creates a branch with no history and switches to it. Like `master`
+    /// right after `init`, its ref stays empty until the first commit, which
+    /// becomes a new root commit. The index and working tree are cleared.
+    pub fn create_orphan(name: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        if ref_exists(&format!("refs/heads/{name}"))? {
+            bail!("Branch \"{name}\" already exists");
         }
+        fs::write(refs_path().join("heads").join(&name), "")
+            .context("Unable to create orphan branch. Unable to write ref file")?;
 
+        Index::load()?.clear()?;
+        worktree::clear()?;
         fs::write(head_path(), format!("ref: refs/heads/{name}"))?;
 
         Ok(())
     }
 
+    /// Whether another work tree of the repository has this branch checked
+    /// out. A branch can only be checked out in one work tree at a time.
+    pub fn is_checked_out_elsewhere(&self) -> Result<bool> {
+        let common_path = common_path();
+        let mut rygit_dirs = vec![common_path.clone()];
+        let linked_path = common_path.join(WORKTREES_DIR);
+        if linked_path.is_dir() {
+            for entry in fs::read_dir(&linked_path).context("Unable to list work trees")? {
+                rygit_dirs.push(entry.context("Unable to list work trees")?.path());
+            }
+        }
+
+        let current = rygit_path();
+        let branch_ref = format!("refs/heads/{}", self.name);
+        for rygit_dir in rygit_dirs.iter().filter(|dir| **dir != current) {
+            if read_symbolic_in(rygit_dir, "HEAD")?.as_deref() == Some(branch_ref.as_str()) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
     fn commit(&self) -> Result<Commit> {
-        Commit::load(&self.commit_hash)
+        Commit::load(&self.tip()?)
     }
 }
 
@@ -133,15 +253,19 @@ impl Branch {
 mod tests {
     use anyhow::Ok;
 
-    use crate::test_utils::TestRepo;
+    use std::path::Path;
+
+    use crate::{paths::head_ref_path, remote::Remote, test_utils::TestRepo};
 
     use super::*;
 
     #[test]
     fn test_current() -> Result<()> {
         let repo = TestRepo::new()?;
-        let branch = Branch::current();
-        assert!(branch.is_err());
+        let branch = Branch::current()?;
+        assert_eq!("master", branch.name);
+        assert_eq!(None, branch.commit_hash());
+        assert!(branch.tip().is_err());
 
         repo.file("a.txt", "a")?
             .stage(".")?
@@ -153,10 +277,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_current_name() -> Result<()> {
+        let repo = TestRepo::new()?;
+        assert_eq!(Some("master".to_string()), Branch::current_name()?);
+
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        assert_eq!(Some("master".to_string()), Branch::current_name()?);
+
+        let head_hash = fs::read_to_string(head_ref_path())?;
+        fs::write(head_path(), head_hash)?;
+        assert_eq!(None, Branch::current_name()?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_create() -> Result<()> {
         let repo = TestRepo::new()?;
-        let branch = Branch::create("test");
+        let branch = Branch::create("test", None);
         assert!(branch.is_err());
 
         repo.file("a.txt", "a")?
@@ -174,11 +315,11 @@ mod tests {
 
         let test_branch = Branch::find_by_name("test")?;
         assert_eq!("test", test_branch.name);
-        assert_eq!(initial_commit_hash, test_branch.commit_hash);
+        assert_eq!(Some(initial_commit_hash), test_branch.commit_hash);
 
         let master_branch = Branch::find_by_name("master")?;
         assert_eq!("master", master_branch.name);
-        assert_eq!(second_commit_hash, master_branch.commit_hash);
+        assert_eq!(Some(second_commit_hash), master_branch.commit_hash);
 
         let branches = Branch::list()?;
         assert_eq!(2, branches.len());
@@ -194,6 +335,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_create_at_start_point() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let initial_commit_hash = Hash::from_hex(&fs::read_to_string(head_ref_path())?)?;
+        repo.file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+
+        let previous = Branch::create("previous", Some("HEAD~1"))?;
+        assert_eq!(Some(initial_commit_hash), previous.commit_hash);
+
+        let copy = Branch::create("copy", Some("previous"))?;
+        assert_eq!(
+            Some(initial_commit_hash),
+            Branch::find_by_name(copy.name())?.commit_hash
+        );
+
+        assert!(Branch::create("invalid", Some("HEAD~5")).is_err());
+        assert!(Branch::find_by_name("invalid").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_switch() -> Result<()> {
         let repo = TestRepo::new()?;
@@ -229,9 +396,128 @@ mod tests {
         assert!(file_b_path.exists());
         assert_eq!("b", fs::read_to_string(&file_b_path)?);
         assert_eq!("a", fs::read_to_string(repo.path().join("a.txt"))?);
+        let index = Index::load()?;
+        let indexed: Vec<_> = index.files().iter().map(|f| f.path()).collect();
+        assert_eq!(
+            vec![
+                Path::new("a/a.txt"),
+                Path::new("a.txt"),
+                Path::new("b/b.txt"),
+                Path::new("b.txt")
+            ],
+            indexed
+        );
 
         // TODO: Test for handling uncommitted files
 
         Ok(())
     }
+
+    #[test]
+    fn test_switch_case_collision() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("other")?
+            .switch("other")?
+            .file("README", "upper")?
+            .file("readme", "lower")?
+            .stage(".")?
+            .commit("Add both readmes")?
+            .switch("master")?;
+
+        let mut config = Config::load()?;
+        config.set("core.ignorecase", "true")?;
+        config.save()?;
+        let error = Branch::switch("other").unwrap_err();
+        assert!(format!("{error:#}").contains("README and readme differ only in case"));
+        assert_eq!("master", Branch::current()?.name);
+        assert_eq!("a", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_orphan() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+
+        Branch::create_orphan("pages")?;
+        assert_eq!(None, Branch::current()?.commit_hash());
+        assert!(!repo.path().join("a.txt").exists());
+        assert!(Index::load()?.files().is_empty());
+        assert!(Branch::create_orphan("master").is_err());
+
+        repo.file("index.html", "hello")?
+            .stage(".")?
+            .commit("Root commit")?;
+        let branch = Branch::current()?;
+        assert_eq!("pages", branch.name());
+        let commit = branch.commit()?;
+        assert!(commit.parent_hashes().is_empty());
+        assert_eq!(1, commit.tree()?.entries().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_remotes() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?;
+
+        let _repo = TestRepo::new()?;
+        assert!(Branch::list()?.is_empty());
+        assert!(Branch::list_remotes()?.is_empty());
+
+        Remote::add("origin", origin.path())?.fetch()?;
+        let names: Vec<_> = Branch::list_remotes()?
+            .iter()
+            .map(|b| b.name().to_string())
+            .collect();
+        assert_eq!(vec!["origin/feature", "origin/master"], names);
+        assert!(Branch::list()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_track() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .switch("feature")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Commit on feature")?;
+        let feature_hash = Hash::from_hex(&fs::read_to_string(head_ref_path())?)?;
+
+        let repo = TestRepo::new()?;
+        Remote::add("origin", origin.path())?.fetch()?;
+        assert!(Branch::track("origin/missing", None).is_err());
+
+        repo.switch(Branch::track("origin/feature", None)?.name())?;
+        let branch = Branch::current()?;
+        assert_eq!("feature", branch.name());
+        assert_eq!(Some(&feature_hash), branch.commit_hash());
+        assert_eq!("b", fs::read_to_string(repo.path().join("b.txt"))?);
+
+        let config = Config::load()?;
+        assert_eq!(Some("origin"), config.get("branch.feature.remote"));
+        assert_eq!(
+            Some("refs/heads/feature"),
+            config.get("branch.feature.merge")
+        );
+
+        Ok(())
+    }
 }
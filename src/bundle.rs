@@ -0,0 +1,197 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    hash::Hash,
+    objects::locate,
+    pack::{serialize_pack, unpack},
+    refs::{ref_exists, resolve},
+    transport::reachable_objects,
+};
+
+// bundle format:
+// # v2 rygit bundle
+// <hash> <ref name>...
+// <empty line>
+// <pack with every object reachable from the refs>
+const SIGNATURE: &str = "# v2 rygit bundle\n";
+
+/// Full ref names and the commits they point to, in bundle order.
+pub type BundleRefs = Vec<(String, Hash)>;
+
+/// Writes the refs named in `refs`, such as `master`, `v1.0` or `HEAD`, and
+/// every object reachable from them to a bundle at `path`, so history can
+/// move between repositories without a shared filesystem.
+pub fn create(path: impl AsRef<Path>, refs: &[String]) -> Result<()> {
+    let path = path.as_ref();
+    if refs.is_empty() {
+        bail!("Unable to create bundle. No refs given");
+    }
+
+    let mut header = SIGNATURE.to_string();
+    let mut tips = vec![];
+    for name in refs {
+        let full_name = full_ref_name(name).context("Unable to create bundle")?;
+        let hash = resolve(&full_name)?
+            .with_context(|| format!("Unable to create bundle. {name} has no commits"))?;
+        header.push_str(&format!("{hash} {full_name}\n"));
+        tips.push(hash);
+    }
+    header.push('\n');
+
+    let objects = reachable_objects(&tips).context("Unable to create bundle")?;
+    let pack = serialize_pack(&objects).context("Unable to create bundle")?;
+    let mut bundle = header.into_bytes();
+    bundle.extend_from_slice(&pack);
+    fs::write(path, bundle).with_context(|| {
+        format!(
+            "Unable to create bundle. Unable to write {}",
+            path.display()
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Stores the objects in the bundle at `path` in this repository and returns
+/// the refs it carries. No refs are created or moved.
+pub fn unbundle(path: impl AsRef<Path>) -> Result<BundleRefs> {
+    let path = path.as_ref();
+    let bundle = fs::read(path)
+        .with_context(|| format!("Unable to unbundle. Unable to read {}", path.display()))?;
+    let (refs, pack) = parse(&bundle).context("Unable to unbundle. Invalid bundle")?;
+
+    unpack(pack).context("Unable to unbundle")?;
+    for (name, hash) in &refs {
+        if locate(hash)?.is_none() {
+            bail!("Unable to unbundle. The bundle is missing {name} ({hash})");
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Splits a bundle into the refs in its header and its pack.
+fn parse(bundle: &[u8]) -> Result<(BundleRefs, &[u8])> {
+    let mut rest = bundle
+        .strip_prefix(SIGNATURE.as_bytes())
+        .context("Missing bundle signature")?;
+    let mut refs = vec![];
+    loop {
+        let line_end = rest
+            .iter()
+            .position(|&c| c == b'\n')
+            .context("Missing end of ref list")?;
+        let line = std::str::from_utf8(&rest[..line_end]).context("Invalid ref line")?;
+        rest = &rest[line_end + 1..];
+        if line.is_empty() {
+            break;
+        }
+
+        let (hash, name) = line
+            .split_once(' ')
+            .with_context(|| format!("Invalid ref line \"{line}\""))?;
+        refs.push((name.to_string(), Hash::from_hex(hash)?));
+    }
+
+    Ok((refs, rest))
+}
+
+/// Expands a short ref name the way a user would type it to the full name
+/// stored in the bundle.
+fn full_ref_name(name: &str) -> Result<String> {
+    if name == "HEAD" {
+        return Ok(name.to_string());
+    }
+
+    let candidates = if name.starts_with("refs/") {
+        vec![name.to_string()]
+    } else {
+        vec![format!("refs/heads/{name}"), format!("refs/tags/{name}")]
+    };
+    for candidate in candidates {
+        if ref_exists(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+
+    bail!("{name} is not a branch or tag")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{
+        commands::init::init,
+        objects::{commit::Commit, tree::Tree},
+        paths::reset_repository_root_path,
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_bundle_and_unbundle() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("dir/b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let tip = resolve("HEAD")?.unwrap();
+        let bundle_dir = TempDir::new()?;
+        let bundle_path = bundle_dir.path().join("repo.bundle");
+        create(&bundle_path, &["master".to_string(), "HEAD".to_string()])?;
+        assert!(create(bundle_dir.path().join("other"), &["missing".to_string()]).is_err());
+
+        let destination = TempDir::new()?;
+        init(destination.path(), false)?;
+        std::env::set_current_dir(destination.path())?;
+        reset_repository_root_path();
+        assert!(locate(&tip)?.is_none());
+
+        let refs = unbundle(&bundle_path)?;
+        assert_eq!(
+            vec![
+                ("refs/heads/master".to_string(), tip),
+                ("HEAD".to_string(), tip)
+            ],
+            refs
+        );
+        let commit = Commit::load(&tip)?;
+        assert_eq!("Second commit", commit.message());
+        let parent = Commit::load(&commit.parent_hashes()[0])?;
+        assert_eq!("Initial commit", parent.message());
+        let files = Tree::load(commit.tree_hash())?.files();
+        assert_eq!(2, files.len());
+        for hash in files.values() {
+            assert!(locate(hash)?.is_some());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unbundle_rejects_corrupt_bundle() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let bundle_dir = TempDir::new()?;
+        let bundle_path = bundle_dir.path().join("repo.bundle");
+        create(&bundle_path, &["master".to_string()])?;
+
+        let mut bundle = fs::read(&bundle_path)?;
+        let last = bundle.len() - 1;
+        bundle[last] ^= 0xff;
+        fs::write(&bundle_path, bundle)?;
+        assert!(unbundle(&bundle_path).is_err());
+        assert!(unbundle(bundle_dir.path().join("missing")).is_err());
+
+        Ok(())
+    }
+}
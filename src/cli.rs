@@ -1,57 +1,547 @@
-use std::{env, path::Path};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::{Context, Ok, Result, bail};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use regex::Regex;
 
 use crate::{
     branch::Branch,
-    commands::{self},
-    paths::discover_repository_root_from,
+    commands::{
+        self,
+        commit::CommitOptions,
+        grep::GrepSource,
+        log::{LogFormat, LogOptions},
+        merge::MergeOptions,
+    },
+    diff::{DEFAULT_SIMILARITY_THRESHOLD, RenameOptions},
+    error::RygitError,
+    lockfile::RepositoryLock,
+    output::{self, OutputFormat},
+    paths::{discover_repository_root_from, rygit_dir_override, set_rygit_dir},
+    progress,
+    repository_status::UntrackedMode,
+    rev_list::{Limits, parse_date},
 };
 
 #[derive(Parser)]
 #[command(name = "rygit")]
 #[command(about = "Ryan's git clone", long_about = None)]
 pub struct Cli {
+    /// Run as if rygit was started in <PATH>
+    #[clap(short = 'C', value_name = "PATH")]
+    pub directory: Option<PathBuf>,
+    /// Keep the repository metadata at <PATH> instead of .rygit
+    #[clap(long, value_name = "PATH", env = "RYGIT_DIR")]
+    pub git_dir: Option<PathBuf>,
+    /// Only print errors and the output the command was run for
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    Init,
+    Init {
+        #[clap(long)]
+        bare: bool,
+    },
+    Clone {
+        source: PathBuf,
+        directory: Option<PathBuf>,
+        #[clap(long, value_name = "DEPTH")]
+        depth: Option<usize>,
+    },
     Commit {
+        #[clap(short, long, required_unless_present_any = ["fixup", "squash", "no_edit"])]
+        message: Option<String>,
+        #[clap(long)]
+        allow_empty_message: bool,
         #[clap(short, long)]
-        message: String,
+        verbose: bool,
+        #[clap(long, value_name = "COMMIT", conflicts_with = "squash")]
+        fixup: Option<String>,
+        #[clap(long, value_name = "COMMIT")]
+        squash: Option<String>,
+        #[clap(short, long, alias = "force")]
+        no_verify: bool,
+        #[clap(long, conflicts_with = "no_progress")]
+        progress: bool,
+        #[clap(long)]
+        no_progress: bool,
+        #[clap(short, long)]
+        all: bool,
+        #[clap(short, long)]
+        signoff: bool,
+        #[clap(long, value_name = "TRAILER")]
+        trailer: Vec<String>,
+        #[clap(long, conflicts_with_all = ["fixup", "squash"])]
+        amend: bool,
+        #[clap(long, requires = "amend", conflicts_with = "message")]
+        no_edit: bool,
+    },
+    Log {
+        #[clap(long, value_name = "FORMAT", default_value = "medium")]
+        pretty: String,
+        #[clap(long, value_name = "FORMAT", default_value = "human")]
+        format: String,
+        #[clap(flatten)]
+        limits: LimitArgs,
+        #[clap(long)]
+        reverse: bool,
+        #[clap(long)]
+        all: bool,
     },
-    Log,
     Add {
         #[clap()]
         path: String,
+        #[clap(long, conflicts_with = "no_progress")]
+        progress: bool,
+        #[clap(long)]
+        no_progress: bool,
+    },
+    Status {
+        #[clap(
+            short = 'u',
+            long = "untracked-files",
+            value_name = "MODE",
+            default_value = "normal"
+        )]
+        untracked_files: String,
+        #[clap(long, value_name = "FORMAT", default_value = "human")]
+        format: String,
     },
-    Status,
     Branch {
         name: Option<String>,
+        start_point: Option<String>,
+        #[clap(short, long)]
+        remotes: bool,
+        #[clap(short, long)]
+        all: bool,
+        #[clap(long, conflicts_with_all = ["name", "remotes", "all"])]
+        show_current: bool,
     },
     Switch {
-        name: String,
+        #[clap(required_unless_present_any = ["track", "orphan"])]
+        name: Option<String>,
         #[clap(short, long)]
         create: bool,
+        #[clap(short, long, value_name = "REMOTE/BRANCH", conflicts_with = "create")]
+        track: Option<String>,
+        #[clap(requires = "create")]
+        start_point: Option<String>,
+        #[clap(long, value_name = "NAME", conflicts_with_all = ["name", "create", "track"])]
+        orphan: Option<String>,
+    },
+    CountObjects {
+        #[clap(short = 'H', long)]
+        human_readable: bool,
+    },
+    VerifyPack {
+        index_path: PathBuf,
+    },
+    Repack,
+    PackRefs,
+    WriteTree,
+    LsFiles {
+        #[clap(short, long)]
+        unmerged: bool,
+    },
+    CheckoutIndex {
+        #[clap(required_unless_present = "all", conflicts_with = "all")]
+        paths: Vec<PathBuf>,
+        #[clap(short, long)]
+        all: bool,
+        #[clap(short, long)]
+        force: bool,
+    },
+    UpdateIndex {
+        #[clap(long, required_unless_present = "no_assume_unchanged")]
+        assume_unchanged: bool,
+        #[clap(long, conflicts_with = "assume_unchanged")]
+        no_assume_unchanged: bool,
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    ReadTree {
+        #[clap(value_name = "TREE-ISH")]
+        tree_ish: String,
+    },
+    CommitTree {
+        tree: String,
+        #[clap(short, value_name = "PARENT")]
+        parents: Vec<String>,
+        #[clap(short, value_name = "MESSAGE")]
+        message: String,
+    },
+    Remote {
+        #[command(subcommand)]
+        command: Option<RemoteCommands>,
+        #[clap(short, long)]
+        verbose: bool,
+    },
+    Fetch {
+        #[clap(default_value = "origin")]
+        remote: String,
+    },
+    Config {
+        #[clap(required_unless_present = "list", conflicts_with = "list")]
+        key: Option<String>,
+        value: Option<String>,
+        #[clap(short, long)]
+        list: bool,
+        #[clap(long)]
+        global: bool,
+    },
+    Tag {
+        name: Option<String>,
+        #[clap(requires = "name")]
+        revision: Option<String>,
+    },
+    RevList {
+        #[clap(value_name = "REVISION|A..B")]
+        spec: String,
+        #[clap(long)]
+        count: bool,
+        #[clap(flatten)]
+        limits: LimitArgs,
+    },
+    Merge {
+        #[clap(required_unless_present_any = ["abort", "continue_"])]
+        revision: Option<String>,
+        #[clap(long, conflicts_with_all = ["revision", "continue_"])]
+        abort: bool,
+        #[clap(long = "continue", conflicts_with = "revision")]
+        continue_: bool,
+        #[clap(long)]
+        no_ff: bool,
+        #[clap(long, conflicts_with = "no_ff")]
+        squash: bool,
+    },
+    Grep {
+        pattern: String,
+        revision: Option<String>,
+        #[clap(short = 'F', long)]
+        fixed_strings: bool,
+        #[clap(long, conflicts_with = "revision")]
+        cached: bool,
+    },
+    Diff {
+        #[clap(value_name = "COMMIT")]
+        old_revision: String,
+        #[clap(value_name = "COMMIT")]
+        new_revision: Option<String>,
+        #[clap(long, conflicts_with = "no_textconv")]
+        textconv: bool,
+        #[clap(long)]
+        no_textconv: bool,
+        #[clap(long, requires = "new_revision", conflicts_with = "textconv")]
+        no_index: bool,
+        #[clap(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            require_equals = true,
+            value_parser = clap::value_parser!(u8).range(0..=100),
+            requires = "new_revision",
+            conflicts_with = "no_index"
+        )]
+        find_renames: Option<Option<u8>>,
+        #[clap(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            require_equals = true,
+            value_parser = clap::value_parser!(u8).range(0..=100),
+            requires = "new_revision",
+            conflicts_with = "no_index"
+        )]
+        find_copies: Option<Option<u8>>,
+    },
+    Reset {
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    Restore {
+        #[clap(short, long, default_value = "HEAD")]
+        source: String,
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    Rebase {
+        #[clap(required_unless_present_any = ["abort", "continue_"])]
+        upstream: Option<String>,
+        #[clap(long, conflicts_with_all = ["upstream", "continue_"])]
+        abort: bool,
+        #[clap(long = "continue", conflicts_with = "upstream")]
+        continue_: bool,
+    },
+    Describe {
+        #[clap(default_value = "HEAD")]
+        revision: String,
+    },
+    #[command(hide = true)]
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommands,
+    },
+    Push {
+        #[clap(default_value = "origin")]
+        remote: String,
+        #[clap(short, long)]
+        force: bool,
+    },
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommands,
+    },
+    Notes {
+        #[command(subcommand)]
+        command: NotesCommands,
+    },
+    Bisect {
+        #[command(subcommand)]
+        command: BisectCommands,
+    },
+    Worktree {
+        #[command(subcommand)]
+        command: WorktreeCommands,
+    },
+    SparseCheckout {
+        #[command(subcommand)]
+        command: SparseCheckoutCommands,
+    },
+}
+
+impl Commands {
+    /// Whether the command may change the index, refs or work tree, and so
+    /// must hold the repository lock.
+    fn modifies_repository(&self) -> bool {
+        match self {
+            Commands::Commit { .. }
+            | Commands::Add { .. }
+            | Commands::Switch { .. }
+            | Commands::Repack
+            | Commands::PackRefs
+            | Commands::ReadTree { .. }
+            | Commands::CheckoutIndex { .. }
+            | Commands::UpdateIndex { .. }
+            | Commands::Fetch { .. }
+            | Commands::Merge { .. }
+            | Commands::Reset { .. }
+            | Commands::Restore { .. }
+            | Commands::Rebase { .. }
+            | Commands::Push { .. }
+            | Commands::Bisect { .. }
+            | Commands::Worktree { .. }
+            | Commands::SparseCheckout { .. } => true,
+            Commands::Branch { name, .. } | Commands::Tag { name, .. } => name.is_some(),
+            Commands::Remote { command, .. } => command.is_some(),
+            Commands::Bundle { command } => matches!(command, BundleCommands::Unbundle { .. }),
+            Commands::Notes { command } => matches!(command, NotesCommands::Add { .. }),
+            Commands::Config { value, global, .. } => value.is_some() && !global,
+            Commands::Init { .. }
+            | Commands::Clone { .. }
+            | Commands::Log { .. }
+            | Commands::Status { .. }
+            | Commands::CountObjects { .. }
+            | Commands::VerifyPack { .. }
+            | Commands::RevList { .. }
+            | Commands::Grep { .. }
+            | Commands::Diff { .. }
+            | Commands::Describe { .. }
+            | Commands::WriteTree
+            | Commands::LsFiles { .. }
+            | Commands::CommitTree { .. }
+            | Commands::Debug { .. } => false,
+        }
+    }
+}
+
+/// Options limiting which commits `log` and `rev-list` show.
+#[derive(Args)]
+pub struct LimitArgs {
+    #[clap(short = 'n', long, value_name = "NUMBER")]
+    max_count: Option<usize>,
+    #[clap(long, value_name = "DATE")]
+    since: Option<String>,
+    #[clap(long, value_name = "DATE")]
+    until: Option<String>,
+    #[clap(long, value_name = "PATTERN")]
+    author: Option<String>,
+    #[clap(long, value_name = "PATTERN")]
+    grep: Option<String>,
+    #[clap(long)]
+    first_parent: bool,
+}
+
+impl LimitArgs {
+    fn parse(&self) -> Result<Limits> {
+        Ok(Limits {
+            max_count: self.max_count,
+            since: self.since.as_deref().map(parse_date).transpose()?,
+            until: self.until.as_deref().map(parse_date).transpose()?,
+            author: self.author.as_deref().map(parse_pattern).transpose()?,
+            grep: self.grep.as_deref().map(parse_pattern).transpose()?,
+            first_parent: self.first_parent,
+        })
+    }
+}
+
+#[derive(Subcommand)]
+pub enum RemoteCommands {
+    Add { name: String, url: String },
+}
+
+#[derive(Subcommand)]
+pub enum BundleCommands {
+    Create {
+        file: PathBuf,
+        #[clap(required = true)]
+        refs: Vec<String>,
+    },
+    Unbundle {
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NotesCommands {
+    Add {
+        #[clap(default_value = "HEAD")]
+        revision: String,
+        #[clap(short, long)]
+        message: String,
+        #[clap(short, long)]
+        force: bool,
+    },
+    Show {
+        #[clap(default_value = "HEAD")]
+        revision: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BisectCommands {
+    Start {
+        bad: Option<String>,
+        #[clap(requires = "bad")]
+        good: Vec<String>,
+    },
+    Good {
+        revision: Option<String>,
+    },
+    Bad {
+        revision: Option<String>,
     },
+    Reset,
+}
+
+#[derive(Subcommand)]
+pub enum WorktreeCommands {
+    Add { path: PathBuf, branch: String },
+}
+
+#[derive(Subcommand)]
+pub enum SparseCheckoutCommands {
+    Set {
+        #[clap(required = true)]
+        patterns: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DebugCommands {
+    DumpObject { hash: String },
 }
 
 pub fn run(cli: Cli) -> Result<()> {
+    if let Some(directory) = &cli.directory {
+        env::set_current_dir(directory)
+            .with_context(|| format!("Cannot change to \"{}\"", directory.display()))?;
+    }
+    output::set_quiet(cli.quiet);
     let current_dir = env::current_dir().context("Unable to determine current directory")?;
+    if let Some(git_dir) = &cli.git_dir {
+        set_rygit_dir(current_dir.join(git_dir));
+    }
 
     match cli.command {
-        Commands::Init => {}
+        Commands::Init { .. }
+        | Commands::Clone { .. }
+        | Commands::Config { global: true, .. }
+        | Commands::Diff { no_index: true, .. } => {}
         _ => ensure_rygit_repository(&current_dir)?,
     }
+    let _lock = if cli.command.modifies_repository() {
+        Some(RepositoryLock::acquire()?)
+    } else {
+        None
+    };
     match &cli.command {
-        Commands::Init => commands::init::run(current_dir)?,
-        Commands::Commit { message } => commands::commit::run(message)?,
-        Commands::Log => commands::log::run()?,
-        Commands::Add { path } => {
+        Commands::Init { bare } => commands::init::run(current_dir, *bare)?,
+        Commands::Clone {
+            source,
+            directory,
+            depth,
+        } => commands::clone::run(source, directory.as_deref(), *depth)?,
+        Commands::Commit {
+            message,
+            allow_empty_message,
+            verbose,
+            fixup,
+            squash,
+            no_verify,
+            progress,
+            no_progress,
+            all,
+            signoff,
+            trailer,
+            amend,
+            no_edit,
+        } => {
+            let options = CommitOptions {
+                allow_empty_message: *allow_empty_message,
+                verbose: *verbose,
+                fixup: fixup.clone(),
+                squash: squash.clone(),
+                no_verify: *no_verify,
+                progress: progress::enabled(*progress, *no_progress),
+                all: *all,
+                signoff: *signoff,
+                trailers: trailer.clone(),
+                amend: *amend,
+                no_edit: *no_edit,
+            };
+            commands::commit::run(message.clone().unwrap_or_default(), options)?
+        }
+        Commands::Log {
+            pretty,
+            format,
+            limits,
+            reverse,
+            all,
+        } => {
+            let options = LogOptions {
+                limits: limits.parse()?,
+                reverse: *reverse,
+                all: *all,
+            };
+            commands::log::run(
+                &LogFormat::parse(pretty)?,
+                parse_output_format(format)?,
+                &options,
+            )?
+        }
+        Commands::Add {
+            path,
+            progress,
+            no_progress,
+        } => {
             let mut path = Path::new(&path).to_path_buf();
             if path.is_relative() {
                 let current_dir = env::current_dir()
@@ -61,32 +551,255 @@ pub fn run(cli: Cli) -> Result<()> {
             if !path.exists() {
                 bail!("Cannot add \"{}\", not a valid path", path.display());
             }
-            commands::add::run(path)?;
+            let mut progress = progress::reporter("Adding files", *progress, *no_progress);
+            commands::add::run(path, progress.as_mut())?;
         }
-        Commands::Status => commands::status::run()?,
-        Commands::Branch { name } => {
-            if let Some(name) = name {
-                Branch::create(name)?;
+        Commands::Status {
+            untracked_files,
+            format,
+        } => {
+            let mode = UntrackedMode::from_str(untracked_files).with_context(|| {
+                format!(
+                    "Invalid untracked files mode \"{untracked_files}\". Expected no, normal or all"
+                )
+            })?;
+            commands::status::run(mode, parse_output_format(format)?)?
+        }
+        Commands::Branch {
+            name,
+            start_point,
+            remotes,
+            all,
+            show_current,
+        } => {
+            if *show_current {
+                commands::branch::show_current()?;
+            } else if let Some(name) = name {
+                Branch::create(name, start_point.as_deref())?;
             } else {
-                commands::branch::list()?;
+                commands::branch::list(*remotes, *all)?;
             }
         }
-        Commands::Switch { name, create } => {
-            if *create {
-                Branch::create(name)?;
+        Commands::Switch {
+            name,
+            create,
+            track,
+            start_point,
+            orphan,
+        } => {
+            if let Some(orphan) = orphan {
+                Branch::create_orphan(orphan)?;
+                return Ok(());
             }
 
+            let name = match track {
+                Some(remote_branch) => Branch::track(remote_branch, name.clone())?
+                    .name()
+                    .to_string(),
+                None => {
+                    let name = name.clone().context("A branch name is required")?;
+                    if *create {
+                        Branch::create(&name, start_point.as_deref())?;
+                    }
+                    name
+                }
+            };
+
             Branch::switch(name)?;
         }
+        Commands::CountObjects { human_readable } => commands::count_objects::run(*human_readable)?,
+        Commands::VerifyPack { index_path } => commands::verify_pack::run(index_path)?,
+        Commands::Repack => commands::repack::run()?,
+        Commands::PackRefs => commands::pack_refs::run()?,
+        Commands::WriteTree => commands::write_tree::run()?,
+        Commands::LsFiles { unmerged } => commands::ls_files::run(*unmerged)?,
+        Commands::CheckoutIndex { paths, all, force } => {
+            let paths: Vec<_> = paths.iter().map(|path| current_dir.join(path)).collect();
+            commands::checkout_index::run((!all).then_some(&paths[..]), *force)?
+        }
+        Commands::UpdateIndex {
+            assume_unchanged,
+            paths,
+            ..
+        } => {
+            let paths: Vec<_> = paths.iter().map(|path| current_dir.join(path)).collect();
+            commands::update_index::run(&paths, *assume_unchanged)?
+        }
+        Commands::ReadTree { tree_ish } => commands::read_tree::run(tree_ish)?,
+        Commands::CommitTree {
+            tree,
+            parents,
+            message,
+        } => commands::commit_tree::run(tree, parents, message)?,
+        Commands::Debug { command } => match command {
+            DebugCommands::DumpObject { hash } => commands::debug::dump_object(hash)?,
+        },
+        Commands::Remote { command, verbose } => match command {
+            Some(RemoteCommands::Add { name, url }) => commands::remote::add(name, url)?,
+            None => commands::remote::list(*verbose)?,
+        },
+        Commands::Fetch { remote } => commands::fetch::run(remote)?,
+        Commands::Config {
+            key,
+            value,
+            list,
+            global,
+        } => match key {
+            Some(key) if !list => commands::config::run(key, value.as_deref(), *global)?,
+            _ => commands::config::run_list(*global)?,
+        },
+        Commands::Tag { name, revision } => match name {
+            Some(name) => commands::tag::create(name, revision.as_deref())?,
+            None => commands::tag::list()?,
+        },
+        Commands::RevList {
+            spec,
+            count,
+            limits,
+        } => commands::rev_list::run(spec, *count, &limits.parse()?)?,
+        Commands::Merge {
+            revision,
+            abort,
+            continue_,
+            no_ff,
+            squash,
+        } => match revision {
+            _ if *abort => commands::merge::abort()?,
+            _ if *continue_ => {
+                commands::merge::continue_merge()?;
+            }
+            Some(revision) => {
+                let options = MergeOptions {
+                    no_ff: *no_ff,
+                    squash: *squash,
+                };
+                commands::merge::run(revision, options)?
+            }
+            None => {}
+        },
+        Commands::Rebase {
+            upstream,
+            abort,
+            continue_,
+        } => match upstream {
+            _ if *abort => commands::rebase::abort()?,
+            _ if *continue_ => {
+                commands::rebase::continue_rebase()?;
+            }
+            Some(upstream) => commands::rebase::run(upstream)?,
+            None => {}
+        },
+        Commands::Grep {
+            pattern,
+            revision,
+            fixed_strings,
+            cached,
+        } => {
+            let source = match revision {
+                Some(revision) => GrepSource::Revision(revision.clone()),
+                None if *cached => GrepSource::Cached,
+                None => GrepSource::WorkTree,
+            };
+            commands::grep::run(pattern, *fixed_strings, &source)?
+        }
+        Commands::Diff {
+            old_revision,
+            new_revision,
+            no_textconv,
+            no_index,
+            find_renames,
+            find_copies,
+            ..
+        } => match new_revision {
+            Some(new_path) if *no_index => {
+                commands::diff::run_no_index(Path::new(old_revision), Path::new(new_path))?
+            }
+            _ => {
+                let threshold = |value: &Option<u8>| value.unwrap_or(DEFAULT_SIMILARITY_THRESHOLD);
+                let copy_threshold = find_copies.as_ref().map(threshold);
+                // Finding copies finds renames too, like git's -C
+                let rename_threshold = find_renames
+                    .as_ref()
+                    .map(threshold)
+                    .or(copy_threshold.map(|_| DEFAULT_SIMILARITY_THRESHOLD));
+                let renames = RenameOptions {
+                    rename_threshold,
+                    copy_threshold,
+                };
+                commands::diff::run(old_revision, new_revision.as_deref(), !no_textconv, renames)?
+            }
+        },
+        Commands::Reset { paths } => {
+            let paths: Vec<_> = paths.iter().map(|path| current_dir.join(path)).collect();
+            commands::reset::run(&paths)?
+        }
+        Commands::Restore { source, paths } => {
+            let paths: Vec<_> = paths.iter().map(|path| current_dir.join(path)).collect();
+            commands::restore::run(source, &paths)?
+        }
+        Commands::Describe { revision } => commands::describe::run(revision)?,
+        Commands::Push { remote, force } => commands::push::run(remote, *force)?,
+        Commands::Bundle { command } => match command {
+            BundleCommands::Create { file, refs } => {
+                commands::bundle::create(&current_dir.join(file), refs)?
+            }
+            BundleCommands::Unbundle { file } => {
+                commands::bundle::unbundle(&current_dir.join(file))?
+            }
+        },
+        Commands::Notes { command } => match command {
+            NotesCommands::Add {
+                revision,
+                message,
+                force,
+            } => commands::notes::add(revision, message, *force)?,
+            NotesCommands::Show { revision } => commands::notes::show(revision)?,
+        },
+        Commands::Bisect { command } => match command {
+            BisectCommands::Start { bad, good } => {
+                commands::bisect::run_start(bad.as_deref(), good)?
+            }
+            BisectCommands::Good { revision } => {
+                commands::bisect::run_mark(revision.as_deref(), true)?
+            }
+            BisectCommands::Bad { revision } => {
+                commands::bisect::run_mark(revision.as_deref(), false)?
+            }
+            BisectCommands::Reset => commands::bisect::run_reset()?,
+        },
+        Commands::Worktree { command } => match command {
+            WorktreeCommands::Add { path, branch } => {
+                commands::worktree::run_add(&current_dir.join(path), branch)?
+            }
+        },
+        Commands::SparseCheckout { command } => match command {
+            SparseCheckoutCommands::Set { patterns } => {
+                commands::sparse_checkout::run_set(patterns)?
+            }
+        },
     };
 
     Ok(())
 }
 
+fn parse_pattern(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("Invalid pattern \"{pattern}\""))
+}
+
+fn parse_output_format(format: &str) -> Result<OutputFormat> {
+    OutputFormat::from_str(format)
+        .with_context(|| format!("Invalid format \"{format}\". Expected human or json"))
+}
+
 fn ensure_rygit_repository(path: impl AsRef<Path>) -> Result<()> {
-    let repo_root = discover_repository_root_from(path);
-    if repo_root.is_err() {
-        bail!("Not inside a repository")
+    match rygit_dir_override() {
+        Some(rygit_dir) if !rygit_dir.join("HEAD").is_file() => {
+            return Err(RygitError::NotARepository.into());
+        }
+        Some(_) => {}
+        None => {
+            discover_repository_root_from(path)?;
+        }
     }
 
     Ok(())
@@ -1,16 +1,83 @@
 use std::path::Path;
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result};
 
-use crate::{index::Index, paths::repository_root_path};
+use crate::{
+    error::RygitError,
+    index::Index,
+    paths::{ensure_work_tree, find_repository_root_path},
+    progress::Progress,
+};
 
-pub fn run(path: impl AsRef<Path>) -> Result<()> {
+pub fn run(path: impl AsRef<Path>, progress: &mut dyn Progress) -> Result<()> {
     let path = path.as_ref();
-    let repository_path = repository_root_path();
+    let repository_path = find_repository_root_path()
+        .and_then(|path| ensure_work_tree().map(|_| path))
+        .with_context(|| format!("Cannot add {}", path.display()))?;
     if !path.starts_with(repository_path) {
-        bail!("Cannot add {}, not part of this repository", path.display())
+        return Err(RygitError::OutsideRepository(path.to_path_buf()))
+            .with_context(|| format!("Cannot add {}", path.display()));
     }
     let mut index = Index::load()
         .with_context(|| format!("Unable to add {}. Unable to generate index", path.display()))?;
-    index.add(path)
+    index.add_with_progress(path, progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{
+        commands, output::OutputFormat, paths::reset_repository_root_path, progress::NoProgress,
+        repository_status::UntrackedMode, test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_run_outside_repository() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        let outside = TempDir::new()?;
+        let file_path = outside.path().canonicalize()?.join("a.txt");
+        fs::write(&file_path, "a")?;
+
+        let error = run(&file_path, &mut NoProgress).unwrap_err();
+        assert_eq!(
+            Some(&RygitError::OutsideRepository(file_path.clone())),
+            error.downcast_ref::<RygitError>()
+        );
+
+        env::set_current_dir(outside.path())?;
+        reset_repository_root_path();
+        let error = run(&file_path, &mut NoProgress).unwrap_err();
+        assert_eq!(
+            Some(&RygitError::NotARepository),
+            error.downcast_ref::<RygitError>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_in_bare_repository() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        let bare = TempDir::new()?;
+        let bare_path = bare.path().canonicalize()?;
+        commands::init::run(&bare_path, true)?;
+        fs::write(bare_path.join("a.txt"), "a")?;
+
+        env::set_current_dir(&bare_path)?;
+        reset_repository_root_path();
+        let error = run(bare_path.join("a.txt"), &mut NoProgress).unwrap_err();
+        assert_eq!(
+            Some(&RygitError::BareRepository),
+            error.downcast_ref::<RygitError>()
+        );
+        assert!(commands::status::run(UntrackedMode::Normal, OutputFormat::Human).is_err());
+
+        Ok(())
+    }
 }
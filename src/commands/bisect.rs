@@ -0,0 +1,241 @@
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    branch::Branch,
+    hash::Hash,
+    objects::commit::Commit,
+    operation::{BisectState, Operation},
+    output::info,
+    paths::ensure_work_tree,
+    refs,
+    rev_list::walk,
+    revparse,
+};
+
+/// Where a bisect session stands after a step.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BisectStatus {
+    /// A good and a bad commit are both needed before the search can start.
+    Waiting,
+    /// `commit` is checked out for testing, with `remaining` other commits
+    /// still suspected.
+    Testing { commit: Hash, remaining: usize },
+    /// The search is over: `commit` is the first bad commit.
+    Found(Hash),
+}
+
+pub fn run_start(bad: Option<&str>, good: &[String]) -> Result<()> {
+    report(start(bad, good)?)
+}
+
+pub fn run_mark(revision: Option<&str>, is_good: bool) -> Result<()> {
+    report(mark(revision, is_good)?)
+}
+
+pub fn run_reset() -> Result<()> {
+    reset()
+}
+
+fn report(status: BisectStatus) -> Result<()> {
+    match status {
+        BisectStatus::Waiting => {}
+        BisectStatus::Testing { commit, remaining } => {
+            info!(
+                "Bisecting: {remaining} revisions left to test after this (roughly {} steps)",
+                steps(remaining)
+            );
+            println!("[{commit}] {}", Commit::load(&commit)?.subject());
+        }
+        BisectStatus::Found(commit) => {
+            println!("{commit} is the first bad commit");
+            println!("{}", Commit::load(&commit)?.subject());
+        }
+    }
+
+    Ok(())
+}
+
+/// The number of further steps needed to narrow `remaining` commits to one.
+fn steps(remaining: usize) -> u32 {
+    (remaining + 1).next_power_of_two().trailing_zeros()
+}
+
+/// Starts a bisect session, remembering the current branch to return to on
+/// reset. The bad and good commits can be given up front or marked later.
+pub fn start(bad: Option<&str>, good: &[String]) -> Result<BisectStatus> {
+    ensure_work_tree().context("Unable to start bisect")?;
+    Operation::ensure_none().context("Unable to start bisect")?;
+    if BisectState::in_progress() {
+        bail!(
+            "Unable to start bisect. A bisect is already in progress. Run \"rygit bisect reset\" first"
+        );
+    }
+
+    let start = match Branch::current_name()? {
+        Some(branch) => branch,
+        None => refs::resolve("HEAD")?
+            .context("Unable to start bisect. HEAD has no commits")?
+            .to_hex(),
+    };
+    let bad = bad
+        .map(revparse::resolve)
+        .transpose()
+        .context("Unable to start bisect")?;
+    let good = good
+        .iter()
+        .map(|revision| revparse::resolve(revision))
+        .collect::<Result<_>>()
+        .context("Unable to start bisect")?;
+    let state = BisectState { start, bad, good };
+    let status = next(&state)?;
+    state.save()?;
+
+    Ok(status)
+}
+
+/// Marks `revision`, HEAD by default, as good or bad and checks out the next
+/// commit to test.
+pub fn mark(revision: Option<&str>, is_good: bool) -> Result<BisectStatus> {
+    let mut state = BisectState::load()?;
+    let commit = revparse::resolve(revision.unwrap_or("HEAD")).context("Unable to bisect")?;
+    if is_good {
+        state.good.push(commit);
+    } else {
+        state.bad = Some(commit);
+    }
+    // Only a mark that leaves a usable search is kept
+    let status = next(&state)?;
+    state.save()?;
+
+    Ok(status)
+}
+
+/// Ends the bisect session and switches back to where it started.
+pub fn reset() -> Result<()> {
+    let state = BisectState::load()?;
+    match Hash::from_hex(&state.start) {
+        Ok(commit) => Branch::switch_detached(&commit)?,
+        Err(_) => Branch::switch(&state.start)?,
+    }
+
+    BisectState::clear()
+}
+
+/// Checks out the commit that best splits the suspected range, or reports
+/// the first bad commit once only one suspect is left. The suspects are the
+/// commits reachable from the bad commit but not from any good one.
+fn next(state: &BisectState) -> Result<BisectStatus> {
+    let Some(bad) = state.bad else {
+        return Ok(BisectStatus::Waiting);
+    };
+    if state.good.is_empty() {
+        return Ok(BisectStatus::Waiting);
+    }
+    for good in &state.good {
+        if !Commit::is_ancestor(good, &bad)? {
+            bail!("Unable to bisect. Good commit {good} is not an ancestor of bad commit {bad}");
+        }
+    }
+
+    let suspects = walk(&[bad], &state.good).context("Unable to bisect")?;
+    if suspects.is_empty() {
+        bail!("Unable to bisect. {bad} is marked both good and bad");
+    }
+    if suspects.len() == 1 {
+        return Ok(BisectStatus::Found(bad));
+    }
+
+    // Whichever way the midpoint is marked, about half the suspects are ruled out
+    let mut midpoint = bad;
+    let mut best_split = 0;
+    for suspect in &suspects {
+        let below = walk(&[*suspect], &state.good)?.len();
+        let split = below.min(suspects.len() - below);
+        if split > best_split {
+            midpoint = *suspect;
+            best_split = split;
+        }
+    }
+
+    Branch::switch_detached(&midpoint)?;
+    Ok(BisectStatus::Testing {
+        commit: midpoint,
+        remaining: suspects.len() - best_split - 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    #[test]
+    fn test_bisect_linear_history() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let mut commits = vec![];
+        for i in 0..10 {
+            repo.file("a.txt", &i.to_string())?
+                .stage(".")?
+                .commit(format!("Commit {i}"))?;
+            commits.push(revparse::resolve("HEAD")?);
+        }
+        let first_bad = commits[6];
+
+        let mut status = start(Some("HEAD"), &[commits[0].to_hex()])?;
+        let mut tested = 0;
+        while let BisectStatus::Testing { commit, .. } = status {
+            assert_eq!(Some(commit), refs::resolve("HEAD")?);
+            let position = commits.iter().position(|c| *c == commit).unwrap();
+            status = mark(None, position < 6)?;
+            tested += 1;
+        }
+
+        assert_eq!(BisectStatus::Found(first_bad), status);
+        assert!(tested <= 4);
+        reset()?;
+        assert_eq!(Some("master".to_string()), Branch::current_name()?);
+        assert_eq!(Some(commits[9]), refs::resolve("HEAD")?);
+        assert!(!BisectState::in_progress());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bisect_waits_for_good_and_bad() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let head = revparse::resolve("HEAD")?;
+
+        assert!(mark(None, false).is_err());
+        assert_eq!(BisectStatus::Waiting, start(None, &[])?);
+        assert!(start(None, &[]).is_err());
+        assert_eq!(BisectStatus::Waiting, mark(None, false)?);
+        assert_eq!(BisectStatus::Found(head), mark(Some("HEAD~1"), true)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bisect_same_commit_good_and_bad() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+
+        start(None, &[])?;
+        assert_eq!(BisectStatus::Waiting, mark(Some("HEAD"), false)?);
+        let error = mark(Some("HEAD"), true).unwrap_err();
+        assert!(error.to_string().contains("is marked both good and bad"));
+        assert!(BisectState::load()?.good.is_empty());
+
+        Ok(())
+    }
+}
@@ -2,16 +2,33 @@ use anyhow::{Ok, Result};
 
 use crate::branch::Branch;
 
-pub fn list() -> Result<()> {
-    let current_branch = Branch::current()?;
-    let branches = Branch::list()?;
-    let branches = branches
-        .iter()
-        .filter(|b| b.name() != current_branch.name());
+pub fn list(remotes: bool, all: bool) -> Result<()> {
+    if !remotes || all {
+        let current_branch = Branch::current()?;
+        let branches = Branch::list()?;
+        let branches = branches
+            .iter()
+            .filter(|b| b.name() != current_branch.name());
 
-    println!("* {}", current_branch.name());
-    for branch in branches {
-        println!("  {}", branch.name());
+        println!("* {}", current_branch.name());
+        for branch in branches {
+            println!("  {}", branch.name());
+        }
+    }
+
+    if remotes || all {
+        let prefix = if all { "remotes/" } else { "" };
+        for branch in Branch::list_remotes()? {
+            println!("  {prefix}{}", branch.name());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn show_current() -> Result<()> {
+    if let Some(name) = Branch::current_name()? {
+        println!("{name}");
     }
 
     Ok(())
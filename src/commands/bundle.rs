@@ -0,0 +1,19 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::bundle;
+
+pub fn create(file: &Path, refs: &[String]) -> Result<()> {
+    bundle::create(file, refs)
+}
+
+/// Imports the objects in a bundle and prints the refs it carries, which can
+/// then be pointed at them with `branch` or `tag`.
+pub fn unbundle(file: &Path) -> Result<()> {
+    for (name, hash) in bundle::unbundle(file)? {
+        println!("{hash} {name}");
+    }
+
+    Ok(())
+}
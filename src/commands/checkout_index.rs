@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    index::Index,
+    output::info,
+    paths::{ensure_work_tree, repository_root_path},
+    sparse::SparseCheckout,
+    worktree,
+};
+
+/// Writes the staged contents of `paths`, or of every file in the index when
+/// `paths` is `None`, to the working tree. Files that already exist are left
+/// alone unless `force` is set, and files a sparse checkout leaves out aren't
+/// written.
+pub fn run(paths: Option<&[PathBuf]>, force: bool) -> Result<()> {
+    ensure_work_tree().context("Unable to check out index")?;
+    let repository_path = repository_root_path();
+    let index = Index::load().context("Unable to check out index")?;
+    // Conflicted files have no single version to write
+    let staged: BTreeMap<_, _> = index
+        .files()
+        .iter()
+        .filter(|file| file.stage() == 0)
+        .map(|file| (file.path().to_path_buf(), *file.hash()))
+        .collect();
+
+    let mut files = match paths {
+        None => staged,
+        Some(paths) => {
+            let mut files = BTreeMap::new();
+            for path in paths {
+                let relative_path = path.strip_prefix(&repository_path).unwrap_or(path);
+                let Some(hash) = staged.get(relative_path) else {
+                    bail!(
+                        "Unable to check out index. {} is not in the index",
+                        relative_path.display()
+                    );
+                };
+                files.insert(relative_path.to_path_buf(), *hash);
+            }
+            files
+        }
+    };
+
+    if let Some(sparse) = SparseCheckout::load().context("Unable to check out index")? {
+        files.retain(|path, _| sparse.includes(path));
+    }
+
+    if !force {
+        let count = files.len();
+        files.retain(|path, _| !repository_path.join(path).exists());
+        let skipped = count - files.len();
+        if skipped > 0 {
+            info!(
+                "Skipped {skipped} existing file{}. Use -f to overwrite",
+                if skipped == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    worktree::ensure_no_case_collisions(files.keys().map(PathBuf::as_path))
+        .context("Unable to check out index")?;
+    worktree::write_files(&files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{config::Config, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_checkout_index() -> Result<()> {
+        let repo = TestRepo::new()?;
+        fs::write(repo.path().join("c.bin"), [0, 159, 146, 150, b'\r', b'\n'])?;
+        repo.file("a.txt", "a")?
+            .file("dir/b.txt", "b")?
+            .stage(".")?
+            .file("a.txt", "unstaged")?
+            .remove_file("dir/b.txt")?
+            .remove_file("c.bin")?;
+        fs::remove_dir(repo.path().join("dir"))?;
+
+        run(Some(&[repo.path().join("dir/b.txt")]), false)?;
+        assert_eq!("b", fs::read_to_string(repo.path().join("dir/b.txt"))?);
+        assert!(!repo.path().join("c.bin").exists());
+
+        run(None, false)?;
+        assert_eq!("unstaged", fs::read_to_string(repo.path().join("a.txt"))?);
+        assert_eq!(
+            vec![0, 159, 146, 150, b'\r', b'\n'],
+            fs::read(repo.path().join("c.bin"))?
+        );
+
+        run(None, true)?;
+        assert_eq!("a", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        assert!(run(Some(&[repo.path().join("missing.txt")]), true).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkout_index_case_collision() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("README", "upper")?
+            .file("readme", "lower")?
+            .stage(".")?
+            .remove_file("README")?
+            .remove_file("readme")?;
+
+        let mut config = Config::load()?;
+        config.set("core.ignorecase", "true")?;
+        config.save()?;
+        let error = run(None, false).unwrap_err();
+        assert!(format!("{error:#}").contains("README and readme differ only in case"));
+        assert!(!repo.path().join("README").exists());
+        assert!(!repo.path().join("readme").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    branch::Branch,
+    bundle,
+    commands::init::init,
+    output::info,
+    paths::{refs_path, reset_repository_root_path},
+    refs::update_ref,
+    remote::Remote,
+};
+
+pub fn run(source: &Path, directory: Option<&Path>, depth: Option<usize>) -> Result<()> {
+    let directory = match directory {
+        Some(directory) => directory.to_path_buf(),
+        None => source
+            .canonicalize()
+            .ok()
+            .and_then(|source| match source.is_file() {
+                // A bundle clones into a directory named after it, minus .bundle
+                true => source.file_stem().map(PathBuf::from),
+                false => source.file_name().map(PathBuf::from),
+            })
+            .with_context(|| format!("Unable to clone. {} does not exist", source.display()))?,
+    };
+
+    info!("Cloning into '{}'...", directory.display());
+    clone(source, &directory, depth)
+}
+
+/// Creates a repository in `directory` with `source` as its `origin` remote,
+/// fetches it, and checks out the branch the source's HEAD is on. With a
+/// `depth` only that many commits of history are copied. `source` may also
+/// be a bundle file, whose branches become the `origin` tracking branches.
+/// The new repository becomes the one later operations act on.
+pub fn clone(source: &Path, directory: &Path, depth: Option<usize>) -> Result<()> {
+    let source = source
+        .canonicalize()
+        .with_context(|| format!("Unable to clone. {} does not exist", source.display()))?;
+    if depth == Some(0) {
+        bail!("Unable to clone. Depth must be at least 1");
+    }
+    if depth.is_some() && source.is_file() {
+        bail!("Unable to clone. --depth is not supported when cloning a bundle");
+    }
+    if directory.exists() && fs::read_dir(directory)?.next().is_some() {
+        bail!(
+            "Unable to clone. Destination {} already exists and is not empty",
+            directory.display()
+        );
+    }
+
+    fs::create_dir_all(directory).context("Unable to clone. Unable to create destination")?;
+    init(directory, false).context("Unable to clone")?;
+    env::set_current_dir(directory).context("Unable to clone")?;
+    reset_repository_root_path();
+
+    let default_branch = if source.is_file() {
+        fetch_bundle(&source).context("Unable to clone")?
+    } else {
+        let remote = Remote::add("origin", &source).context("Unable to clone")?;
+        remote.fetch_with_depth(depth).context("Unable to clone")?;
+        remote.default_branch()?
+    };
+    let Some(default_branch) = default_branch else {
+        return Ok(());
+    };
+    let remote_branch = format!("origin/{default_branch}");
+    if !Branch::list_remotes()?
+        .iter()
+        .any(|branch| branch.name() == remote_branch)
+    {
+        // The source has no commits yet
+        return Ok(());
+    }
+
+    // init's master has no commits, so it's replaced by the default branch
+    fs::remove_file(refs_path().join("heads").join("master"))
+        .context("Unable to clone. Unable to remove the initial branch")?;
+    Branch::track(&remote_branch, None).context("Unable to clone")?;
+    Branch::switch(default_branch).context("Unable to clone")?;
+
+    Ok(())
+}
+
+/// Imports a bundle's objects and points `origin` tracking branches at its
+/// branches. A bundle can't be fetched from again, so no remote is added.
+/// Returns the branch its HEAD is on, or the first branch when it doesn't
+/// carry HEAD.
+fn fetch_bundle(path: &Path) -> Result<Option<String>> {
+    let refs = bundle::unbundle(path)?;
+    let head = refs
+        .iter()
+        .find(|(name, _)| name == "HEAD")
+        .map(|(_, hash)| *hash);
+
+    let mut branches = vec![];
+    for (name, hash) in &refs {
+        if let Some(branch) = name.strip_prefix("refs/heads/") {
+            update_ref(&format!("refs/remotes/origin/{branch}"), hash, None)?;
+            branches.push((branch.to_string(), *hash));
+        }
+    }
+
+    let default_branch = branches
+        .iter()
+        .find(|(_, hash)| Some(*hash) == head)
+        .or(branches.first())
+        .map(|(branch, _)| branch.clone());
+    Ok(default_branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{
+        commands::log::history,
+        objects::{commit::Commit, loose_object_paths},
+        paths::shallow_path,
+        rev_list::Limits,
+        revparse,
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_clone_with_depth() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "aa")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Third commit")?;
+        let tip = revparse::resolve("HEAD")?;
+        let destination = TempDir::new()?;
+        let directory = destination.path().join("clone");
+
+        clone(origin.path(), &directory, Some(1))?;
+
+        // The tip commit, its tree and its three blobs
+        assert_eq!(5, loose_object_paths()?.len());
+        assert_eq!(
+            format!("{}\n", tip.to_hex()),
+            fs::read_to_string(shallow_path())?
+        );
+        let messages: Vec<_> = history(&Limits::default())?
+            .iter()
+            .map(|commit| commit.message().to_string())
+            .collect();
+        assert_eq!(vec!["Third commit"], messages);
+        assert_eq!(tip, revparse::resolve("HEAD")?);
+        assert_eq!("master", Branch::current()?.name());
+        assert_eq!(
+            Some("origin/master".to_string()),
+            Branch::current()?.upstream()?
+        );
+        assert_eq!("aa", fs::read_to_string(directory.join("a.txt"))?);
+        assert!(Commit::load(&tip)?.parents().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let destination = TempDir::new()?;
+        let directory = destination.path().join("clone");
+
+        clone(origin.path(), &directory, None)?;
+
+        assert!(!shallow_path().exists());
+        assert_eq!(2, history(&Limits::default())?.len());
+        assert!(clone(origin.path(), &directory, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone_bundle() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let tip = revparse::resolve("HEAD")?;
+        let destination = TempDir::new()?;
+        let bundle_path = destination.path().join("repo.bundle");
+        bundle::create(
+            &bundle_path,
+            &[
+                "feature".to_string(),
+                "master".to_string(),
+                "HEAD".to_string(),
+            ],
+        )?;
+        let directory = destination.path().join("clone");
+
+        clone(&bundle_path, &directory, None)?;
+
+        assert_eq!(tip, revparse::resolve("HEAD")?);
+        assert_eq!("master", Branch::current()?.name());
+        assert_eq!(2, history(&Limits::default())?.len());
+        assert_eq!("b", fs::read_to_string(directory.join("b.txt"))?);
+        let mut remote_branches: Vec<_> = Branch::list_remotes()?
+            .iter()
+            .map(|branch| branch.name().to_string())
+            .collect();
+        remote_branches.sort();
+        assert_eq!(vec!["origin/feature", "origin/master"], remote_branches);
+        assert!(clone(&bundle_path, &destination.path().join("shallow"), Some(1)).is_err());
+
+        Ok(())
+    }
+}
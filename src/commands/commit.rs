@@ -1,14 +1,513 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 
 use crate::{
+    branch::Branch,
+    config::Config,
+    diff::DiffStat,
     index::Index,
-    objects::{commit::Commit, signature::Signature},
+    merge::has_conflict_markers,
+    objects::{blob::Blob, commit::Commit, signature::Signature},
+    operation::Operation,
+    output::info,
+    paths::ensure_work_tree,
+    progress::{NoProgress, Progress, StderrProgress},
+    repository_status::{RepositoryStatus, UntrackedMode},
+    revparse, trailers,
 };
 
-pub fn run(message: impl Into<String>) -> Result<()> {
-    let author = Signature::new("Larry Sellers", "lsellers@test.com");
-    let index = Index::load()?;
-    Commit::create(&index, message, author.clone(), author)?;
+#[derive(Debug, Default)]
+pub struct CommitOptions {
+    pub allow_empty_message: bool,
+    /// Lists the changed files under the summary.
+    pub verbose: bool,
+    /// Marks the commit as a fixup of this revision for a later autosquash.
+    pub fixup: Option<String>,
+    /// Marks the commit to be squashed into this revision by a later
+    /// autosquash.
+    pub squash: Option<String>,
+    /// Skips the check for leftover conflict markers in staged files.
+    pub no_verify: bool,
+    /// Reports the files stored while writing the tree to stderr.
+    pub progress: bool,
+    /// Stages modified and deleted tracked files before committing. New
+    /// files are left untracked.
+    pub all: bool,
+    /// Appends a `Signed-off-by` trailer for the committer to the message.
+    pub signoff: bool,
+    /// `Key: value` trailers to append to the message.
+    pub trailers: Vec<String>,
+    /// Replaces the current commit instead of adding one on top of it.
+    pub amend: bool,
+    /// Reuses the amended commit's message instead of the one given.
+    pub no_edit: bool,
+}
+
+pub fn run(message: impl Into<String>, options: CommitOptions) -> Result<()> {
+    let mut message = message.into();
+    if options.no_edit {
+        let head = revparse::resolve("HEAD").context("Unable to amend commit")?;
+        message = Commit::load(&head)?.message().to_string();
+    }
+    if let Some(revision) = &options.fixup {
+        message = autosquash_message("fixup", revision, &message)?;
+    } else if let Some(revision) = &options.squash {
+        message = autosquash_message("squash", revision, &message)?;
+    }
+    let mut message = cleanup_message(&message);
+    if message.is_empty() && !options.allow_empty_message {
+        bail!(
+            "Aborting commit due to empty commit message. Use --allow-empty-message to commit anyway"
+        );
+    }
+
+    for trailer in &options.trailers {
+        if !trailers::is_trailer(trailer) {
+            bail!("Unable to commit. Invalid trailer '{trailer}', expected 'Key: value'");
+        }
+        message = trailers::append(&message, trailer);
+    }
+
+    ensure_work_tree().context("Unable to commit")?;
+    // A plain commit would drop the other side of a merge and leave its state
+    // behind, so the operation has to be finished with --continue
+    Operation::ensure_none().context("Unable to commit")?;
+    let author = identity()?;
+    if options.signoff {
+        message = sign_off(&message, &author);
+    }
+    let mut index = Index::load()?;
+    if options.all {
+        stage_tracked_changes(&mut index)?;
+    }
+    index.ensure_merged().context("Unable to commit")?;
+    if !options.no_verify {
+        ensure_no_conflict_markers(&index)?;
+    }
+    let mut progress: Box<dyn Progress> = if options.progress {
+        Box::new(StderrProgress::new("Writing objects"))
+    } else {
+        Box::new(NoProgress)
+    };
+    let commit = if options.amend {
+        Commit::amend_with_progress(&index, message, author, progress.as_mut())?
+    } else {
+        Commit::create_with_progress(&index, message, author.clone(), author, progress.as_mut())?
+    };
+
+    info!("{}", summary(&commit, options.verbose)?);
+
+    Ok(())
+}
+
+/// Stages every modification and deletion of a tracked file.
+fn stage_tracked_changes(index: &mut Index) -> Result<()> {
+    let status = RepositoryStatus::load(UntrackedMode::No)
+        .context("Unable to commit. Unable to determine changed files")?;
+    for change in status.unstaged_changes() {
+        index.add(&change.path)?;
+    }
 
     Ok(())
 }
+
+/// Errors if any staged file still has conflict markers from a merge.
+fn ensure_no_conflict_markers(index: &Index) -> Result<()> {
+    let mut conflicted = vec![];
+    for file in index.files() {
+        if has_conflict_markers(&Blob::load(file.hash()).body()?) {
+            conflicted.push(file.path().display().to_string());
+        }
+    }
+
+    if !conflicted.is_empty() {
+        bail!(
+            "Aborting commit. Conflict markers found in: {}. Resolve them or use --no-verify to commit anyway",
+            conflicted.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The signature used for new commits, from `user.name` and `user.email` in
+/// the config.
+pub fn identity() -> Result<Signature> {
+    let config = Config::load().context("Unable to determine identity")?;
+    let name = config.get("user.name").unwrap_or("Larry Sellers");
+    let email = config.get("user.email").unwrap_or("lsellers@test.com");
+
+    Ok(Signature::new(name, email))
+}
+
+/// Summarizes a new commit as `[<branch> <short hash>] <subject>` followed by
+/// the diff stat against its first parent.
+fn summary(commit: &Commit, verbose: bool) -> Result<String> {
+    let tree = commit.tree()?;
+    let (root_marker, changes) = match commit.parents()?.first() {
+        Some(parent) => ("", parent.tree()?.diff(&tree)),
+        None => (" (root-commit)", tree.diff_from_empty()),
+    };
+
+    let branch = Branch::current_name()?.unwrap_or_else(|| "detached HEAD".to_string());
+    let mut summary = format!(
+        "[{branch}{root_marker} {}] {}",
+        &commit.hash().to_hex()[..7],
+        commit.subject()
+    );
+    if verbose {
+        for change in changes.iter() {
+            let stat = DiffStat::for_changes(std::slice::from_ref(change))?;
+            summary.push_str(&format!(
+                "\n {} | {} {}{}",
+                change.path().display(),
+                stat.insertions + stat.deletions,
+                "+".repeat(stat.insertions),
+                "-".repeat(stat.deletions)
+            ));
+        }
+    }
+    summary.push_str(&format!("\n {}", DiffStat::for_changes(&changes)?));
+
+    Ok(summary)
+}
+
+/// Prefixes `message` with `<marker>! <subject of revision>`, the form an
+/// autosquash rebase looks for.
+fn autosquash_message(marker: &str, revision: &str, message: &str) -> Result<String> {
+    let hash =
+        revparse::resolve(revision).with_context(|| format!("Unable to create {marker} commit"))?;
+    let target = Commit::load(&hash)?;
+    let mut autosquash_message = format!("{marker}! {}", target.subject());
+    if !message.trim().is_empty() {
+        autosquash_message.push_str("\n\n");
+        autosquash_message.push_str(message);
+    }
+
+    Ok(autosquash_message)
+}
+
+/// Appends a `Signed-off-by` trailer for `signer` to `message`, unless its
+/// trailers already include it.
+fn sign_off(message: &str, signer: &Signature) -> String {
+    let trailer = format!("Signed-off-by: {} <{}>", signer.name(), signer.email());
+    trailers::append(message, &trailer)
+}
+
+/// Normalizes a commit message: drops `#` comment lines, strips trailing
+/// whitespace from every line, collapses runs of blank lines and removes
+/// leading and trailing blank lines.
+pub fn cleanup_message(message: &str) -> String {
+    let mut lines: Vec<&str> = vec![];
+    for line in message.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use std::path::PathBuf;
+
+    use crate::{hash::Hash, paths::head_ref_path, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_cleanup_message() {
+        assert_eq!(
+            "Subject\n\nBody line",
+            cleanup_message("\n# comment\nSubject  \n\n\n\nBody line\t\n\n")
+        );
+        assert_eq!("", cleanup_message("  \n# only a comment\n"));
+    }
+
+    #[test]
+    fn test_sign_off() {
+        let signer = Signature::new("Larry Sellers", "lsellers@test.com");
+        let trailer = "Signed-off-by: Larry Sellers <lsellers@test.com>";
+        assert_eq!(
+            format!("Subject\n\n{trailer}"),
+            sign_off("Subject", &signer)
+        );
+        assert_eq!(
+            format!("Subject\n\nBody\n\nReviewed-by: Someone <s@test.com>\n{trailer}"),
+            sign_off(
+                "Subject\n\nBody\n\nReviewed-by: Someone <s@test.com>",
+                &signer
+            )
+        );
+        assert_eq!(
+            format!("Subject: with a colon\n\n{trailer}"),
+            sign_off("Subject: with a colon", &signer)
+        );
+        assert_eq!(trailer, sign_off("", &signer));
+    }
+
+    #[test]
+    fn test_run_trailers() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?.stage(".")?;
+        let options = CommitOptions {
+            trailers: vec![
+                "Fixes: #12".to_string(),
+                "Co-authored-by: Sam <sam@test.com>".to_string(),
+            ],
+            ..CommitOptions::default()
+        };
+        run("Add a", options)?;
+
+        let commit = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!(
+            "Add a\n\nFixes: #12\nCo-authored-by: Sam <sam@test.com>",
+            commit.message()
+        );
+        assert_eq!(
+            vec![
+                ("Fixes".to_string(), "#12".to_string()),
+                (
+                    "Co-authored-by".to_string(),
+                    "Sam <sam@test.com>".to_string()
+                ),
+            ],
+            commit.trailers()
+        );
+
+        let options = CommitOptions {
+            trailers: vec!["not a trailer".to_string()],
+            ..CommitOptions::default()
+        };
+        assert!(run("Add a", options).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_signoff_once() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?.stage(".")?;
+        let options = CommitOptions {
+            signoff: true,
+            ..CommitOptions::default()
+        };
+        run("Add a\n\nWith details", options)?;
+        let first = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!(
+            "Add a\n\nWith details\n\nSigned-off-by: Larry Sellers <lsellers@test.com>",
+            first.message()
+        );
+
+        repo.file("a.txt", "aa")?.stage(".")?;
+        let options = CommitOptions {
+            signoff: true,
+            ..CommitOptions::default()
+        };
+        run(first.message(), options)?;
+        let second = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!(first.message(), second.message());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_amend() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Add b\n\nWith details")?;
+        let original = Commit::load(&revparse::resolve("HEAD")?)?;
+
+        repo.file("b.txt", "bb")?.stage(".")?;
+        let options = CommitOptions {
+            amend: true,
+            no_edit: true,
+            ..CommitOptions::default()
+        };
+        run("", options)?;
+        let amended = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!("Add b\n\nWith details", amended.message());
+        assert_ne!(original.hash(), amended.hash());
+        assert_ne!(original.tree_hash(), amended.tree_hash());
+        assert_eq!(original.parent_hashes(), amended.parent_hashes());
+
+        let options = CommitOptions {
+            amend: true,
+            ..CommitOptions::default()
+        };
+        run("Add b, reworded", options)?;
+        let reworded = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!("Add b, reworded", reworded.message());
+        assert_eq!(amended.tree_hash(), reworded.tree_hash());
+        assert_eq!(original.parent_hashes(), reworded.parent_hashes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\nb\n")?
+            .stage(".")?
+            .commit("Initial commit\n\nWith a body")?;
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        let short_hash = &commit.hash().to_hex()[..7];
+        assert_eq!(
+            format!(
+                "[master (root-commit) {short_hash}] Initial commit\n 1 file changed, 2 insertions(+)"
+            ),
+            summary(&commit, false)?
+        );
+
+        repo.file("a.txt", "a\nc\n")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        let short_hash = &commit.hash().to_hex()[..7];
+        assert_eq!(
+            format!(
+                "[master {short_hash}] Second commit\n a.txt | 2 +-\n b.txt | 1 +\n 2 files changed, 2 insertions(+), 1 deletion(-)"
+            ),
+            summary(&commit, true)?
+        );
+
+        Branch::switch_detached(commit.hash())?;
+        repo.file("b.txt", "c\n")?
+            .stage(".")?
+            .commit("Detached commit")?;
+        let commit = Commit::load(&revparse::resolve("HEAD")?)?;
+        let short_hash = &commit.hash().to_hex()[..7];
+        assert_eq!(
+            format!(
+                "[detached HEAD {short_hash}] Detached commit\n 1 file changed, 1 insertion(+), 1 deletion(-)"
+            ),
+            summary(&commit, false)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixup_and_squash() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Add a\n\nWith details")?;
+
+        repo.file("a.txt", "aa")?.stage(".")?;
+        let options = CommitOptions {
+            fixup: Some("HEAD".to_string()),
+            ..CommitOptions::default()
+        };
+        run("", options)?;
+        let commit = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!("fixup! Add a", commit.message());
+
+        repo.file("a.txt", "aaa")?.stage(".")?;
+        let options = CommitOptions {
+            squash: Some("HEAD~1".to_string()),
+            ..CommitOptions::default()
+        };
+        run("Extra details", options)?;
+        let commit = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!("squash! Add a\n\nExtra details", commit.message());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_all() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?
+            .remove_file("b.txt")?;
+
+        let options = CommitOptions {
+            all: true,
+            ..CommitOptions::default()
+        };
+        run("Change a and remove b", options)?;
+
+        let tree = Commit::load(&revparse::resolve("HEAD")?)?.tree()?;
+        let files = tree.files();
+        assert_eq!(1, files.len());
+        assert_eq!(
+            b"changed".to_vec(),
+            Blob::load(&files[&PathBuf::from("a.txt")]).body()?
+        );
+        let index = Index::load()?;
+        assert_eq!(1, index.files().len());
+        assert_eq!(files[&PathBuf::from("a.txt")], *index.files()[0].hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_rejects_empty_message() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?.stage(".")?;
+
+        assert!(run("  \n\t", CommitOptions::default()).is_err());
+        assert!(run("# just a comment", CommitOptions::default()).is_err());
+        assert!(fs::read_to_string(head_ref_path())?.is_empty());
+
+        let options = CommitOptions {
+            allow_empty_message: true,
+            ..CommitOptions::default()
+        };
+        run("", options)?;
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        assert!(commit.parent_hashes().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_rejects_conflict_markers() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file(
+            "a.txt",
+            "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n",
+        )?
+        .file("b.bin", "\0\n=======\n")?
+        .stage(".")?;
+
+        let error = run("Conflicted", CommitOptions::default()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Conflict markers found in: a.txt")
+        );
+        assert!(fs::read_to_string(head_ref_path())?.is_empty());
+
+        let options = CommitOptions {
+            no_verify: true,
+            ..CommitOptions::default()
+        };
+        run("Conflicted", options)?;
+        assert!(!fs::read_to_string(head_ref_path())?.is_empty());
+
+        Ok(())
+    }
+}
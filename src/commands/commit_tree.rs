@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+
+use crate::{
+    commands::commit::identity,
+    hash::Hash,
+    objects::{commit::Commit, tree::Tree},
+    revparse,
+};
+
+/// Stores a commit of `tree` with the given parents and prints its hash.
+/// No refs are moved.
+pub fn run(tree: &str, parents: &[String], message: &str) -> Result<()> {
+    println!("{}", commit_tree(tree, parents, message)?);
+
+    Ok(())
+}
+
+fn commit_tree(tree: &str, parents: &[String], message: &str) -> Result<Hash> {
+    let tree_hash = Hash::from_hex(tree)
+        .with_context(|| format!("Unable to commit tree. Invalid tree hash {tree}"))?;
+    Tree::load(&tree_hash).context("Unable to commit tree")?;
+    let parent_hashes = parents
+        .iter()
+        .map(|parent| revparse::resolve(parent))
+        .collect::<Result<_>>()
+        .context("Unable to commit tree")?;
+
+    let signature = identity()?;
+    let commit = Commit::create_from_tree(
+        tree_hash,
+        parent_hashes,
+        message,
+        signature.clone(),
+        signature,
+    )?;
+
+    Ok(*commit.hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    #[test]
+    fn test_commit_tree() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("first")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let head = revparse::resolve("HEAD")?;
+        let first = revparse::resolve("first")?;
+        let tree = Commit::load(&head)?.tree_hash().to_hex();
+
+        let hash = commit_tree(&tree, &["HEAD".to_string(), "first".to_string()], "Merge")?;
+        let commit = Commit::load(&hash)?;
+        assert_eq!(tree, commit.tree_hash().to_hex());
+        assert_eq!(&[head, first], commit.parent_hashes());
+        assert_eq!("Merge", commit.message());
+        assert_eq!(head, revparse::resolve("HEAD")?);
+
+        assert!(commit_tree(&head.to_hex(), &[], "Not a tree").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+
+use crate::config::Config;
+
+/// Prints the value of `key`, or sets it when `value` is given. With
+/// `global` only the global config is read or changed.
+pub fn run(key: &str, value: Option<&str>, global: bool) -> Result<()> {
+    match value {
+        Some(value) => set(key, value, global),
+        None => {
+            println!("{}", get(key, global)?);
+            Ok(())
+        }
+    }
+}
+
+/// Prints every key and value as `key=value`, in file order.
+pub fn run_list(global: bool) -> Result<()> {
+    for line in list(global)? {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn load(global: bool) -> Result<Config> {
+    if global {
+        Config::load_global()
+    } else {
+        Config::load()
+    }
+}
+
+fn get(key: &str, global: bool) -> Result<String> {
+    let config = load(global)?;
+    config
+        .get(key)
+        .map(str::to_string)
+        .with_context(|| format!("Config key {key} is not set"))
+}
+
+fn set(key: &str, value: &str, global: bool) -> Result<()> {
+    let mut config = load(global)?;
+    config
+        .set(key, value)
+        .with_context(|| format!("Unable to set config key {key}"))?;
+    config.save()
+}
+
+fn list(global: bool) -> Result<Vec<String>> {
+    let config = load(global)?;
+    Ok(config
+        .entries()
+        .into_iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{commands::commit::identity, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_set_get_and_list() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        assert!(Config::load()?.get("remote.origin.url").is_none());
+
+        set("user.name", "Donny Kerabatsos", false)?;
+        set("user.email", "d.kerabatsos@example.com", false)?;
+        set("remote.origin.url", "/tmp/origin", false)?;
+        assert_eq!("Donny Kerabatsos", get("user.name", false)?);
+        assert_eq!("Donny Kerabatsos", get("User.Name", false)?);
+        assert_eq!("Donny Kerabatsos", identity()?.name());
+        assert_eq!("d.kerabatsos@example.com", identity()?.email());
+
+        set("user.name", "Larry Sellers", false)?;
+        let lines = list(false)?;
+        assert_eq!(
+            vec![
+                "user.name=Larry Sellers",
+                "user.email=d.kerabatsos@example.com",
+                "remote.origin.url=/tmp/origin",
+            ],
+            lines[lines.len() - 3..]
+        );
+        assert!(set("invalid", "value", false).is_err());
+
+        Ok(())
+    }
+}
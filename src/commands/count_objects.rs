@@ -0,0 +1,116 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    objects::loose_object_paths,
+    pack::{PackIndex, pack_indexes},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ObjectCounts {
+    pub loose_count: usize,
+    pub loose_size: u64,
+    pub pack_count: usize,
+    pub packed_count: usize,
+}
+
+pub fn run(human_readable: bool) -> Result<()> {
+    let counts = count_objects()?;
+    let size = if human_readable {
+        format_size(counts.loose_size)
+    } else {
+        counts.loose_size.to_string()
+    };
+
+    println!("count: {}", counts.loose_count);
+    println!("size: {size}");
+    println!("in-pack: {}", counts.packed_count);
+    println!("packs: {}", counts.pack_count);
+
+    Ok(())
+}
+
+pub fn count_objects() -> Result<ObjectCounts> {
+    let loose_object_paths =
+        loose_object_paths().context("Unable to count objects. Unable to list loose objects")?;
+    let mut loose_size = 0;
+    for path in loose_object_paths.iter() {
+        let metadata = fs::metadata(path)
+            .context("Unable to count objects. Unable to read object metadata")?;
+        loose_size += metadata.len();
+    }
+
+    let pack_indexes = pack_indexes().context("Unable to count objects. Unable to list packs")?;
+    let mut packed_count = 0;
+    for index_path in pack_indexes.iter() {
+        packed_count += PackIndex::load(index_path)?.object_count();
+    }
+
+    Ok(ObjectCounts {
+        loose_count: loose_object_paths.len(),
+        loose_size,
+        pack_count: pack_indexes.len(),
+        packed_count,
+    })
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} bytes");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.2} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{hash::Hash, pack::write_pack, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_count_objects() -> Result<()> {
+        let repo = TestRepo::new()?;
+        assert_eq!(0, count_objects()?.loose_count);
+
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?;
+
+        // Two blobs, one tree, and one commit
+        let counts = count_objects()?;
+        assert_eq!(4, counts.loose_count);
+        assert!(counts.loose_size > 0);
+        assert_eq!(0, counts.pack_count);
+
+        let hashes: Vec<_> = loose_object_paths()?
+            .iter()
+            .map(Hash::from_object_path)
+            .collect::<Result<_, _>>()?;
+        write_pack(&hashes)?;
+        let counts = count_objects()?;
+        assert_eq!(1, counts.pack_count);
+        assert_eq!(4, counts.packed_count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!("512 bytes", format_size(512));
+        assert_eq!("1.50 KiB", format_size(1536));
+        assert_eq!("2.00 MiB", format_size(2 * 1024 * 1024));
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+
+use crate::{
+    hash::Hash,
+    objects::{AnyObject, load_any, read_object, signature::SignatureKind},
+};
+
+pub fn dump_object(hash: &str) -> Result<()> {
+    let hash = Hash::from_hex(hash).context("Unable to dump object. Invalid hash")?;
+    println!("{}", dump(&hash)?);
+
+    Ok(())
+}
+
+/// Describes an object for troubleshooting: its kind and size, the raw body
+/// with non-printable bytes escaped, and the structure parsed from it.
+fn dump(hash: &Hash) -> Result<String> {
+    let (kind, body) = read_object(hash).context("Unable to dump object")?;
+    let mut lines = vec![
+        format!("kind: {kind}"),
+        format!("size: {}", body.len()),
+        "raw:".to_string(),
+        body.escape_ascii().to_string(),
+        "parsed:".to_string(),
+    ];
+
+    match load_any(hash).context("Unable to dump object. Unable to parse it")? {
+        AnyObject::Blob(blob) => lines.push(format!("blob {}", blob.hash())),
+        AnyObject::Tree(tree) => {
+            for entry in tree.entries() {
+                lines.push(format!(
+                    "{} {} {}",
+                    entry.mode(),
+                    entry.hash(),
+                    entry.name()
+                ));
+            }
+        }
+        AnyObject::Commit(commit) => {
+            lines.push(format!("tree {}", commit.tree_hash()));
+            for parent in commit.parent_hashes() {
+                lines.push(format!("parent {parent}"));
+            }
+            lines.push(commit.author().serialize_as(SignatureKind::Author));
+            lines.push(String::new());
+            lines.push(commit.message().to_string());
+        }
+    }
+
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{index::Index, objects::tree::Tree, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_dump_tree() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("src/main.rs", "main")?
+            .stage(".")?;
+        let tree = Tree::from_index(&Index::load()?)?;
+        let a = tree.entries()[0].hash().to_hex();
+        let src = tree.entries()[1].hash().to_hex();
+
+        let dump = dump(tree.hash())?;
+        let mut lines = dump.lines();
+        assert_eq!(Some("kind: tree"), lines.next());
+        assert!(lines.next().unwrap().starts_with("size: "));
+        assert_eq!(Some("raw:"), lines.next());
+        assert!(lines.next().unwrap().starts_with("100644 a.txt\\x00"));
+        assert_eq!(Some("parsed:"), lines.next());
+        assert_eq!(Some(format!("100644 {a} a.txt").as_str()), lines.next());
+        assert_eq!(Some(format!("40000 {src} src").as_str()), lines.next());
+        assert_eq!(None, lines.next());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result, bail};
+
+use crate::{objects::commit::Commit, revparse, tag::Tag};
+
+pub fn run(revision: &str) -> Result<()> {
+    println!("{}", describe(revision)?);
+
+    Ok(())
+}
+
+/// Names a commit after the closest tag it descends from: `<tag>` when the
+/// commit is tagged, otherwise `<tag>-<n>-g<short hash>`, where `n` counts
+/// the commits reachable from the commit but not from the tag.
+pub fn describe(revision: &str) -> Result<String> {
+    let hash = revparse::resolve(revision).context("Unable to describe")?;
+    let tags = Tag::by_commit()?;
+    let ancestors = Commit::ancestors(&hash)?;
+
+    let mut best: Option<(usize, &str)> = None;
+    let mut tagged: Vec<_> = tags.iter().filter(|(c, _)| ancestors.contains(c)).collect();
+    tagged.sort_by(|a, b| a.1.cmp(b.1));
+    for (tagged_hash, names) in tagged {
+        let tag_ancestors = Commit::ancestors(tagged_hash)?;
+        let distance = ancestors.difference(&tag_ancestors).count();
+        // Several tags on one commit resolve to the alphabetically first
+        let name = names.iter().min().unwrap();
+        if best.is_none_or(|(best_distance, _)| distance < best_distance) {
+            best = Some((distance, name));
+        }
+    }
+
+    match best {
+        Some((0, name)) => Ok(name.to_string()),
+        Some((distance, name)) => Ok(format!("{name}-{distance}-g{}", &hash.to_hex()[..7])),
+        None => bail!("No tags can describe {}", hash.to_hex()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    #[test]
+    fn test_describe() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        assert!(describe("HEAD").is_err());
+
+        Tag::create("v1.0", None)?;
+        assert_eq!("v1.0", describe("HEAD")?);
+
+        repo.file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Third commit")?;
+        let head = revparse::resolve("HEAD")?;
+        assert_eq!(
+            format!("v1.0-2-g{}", &head.to_hex()[..7]),
+            describe("HEAD")?
+        );
+        assert_eq!(
+            format!("v1.0-1-g{}", &revparse::resolve("HEAD~1")?.to_hex()[..7]),
+            describe("HEAD~1")?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    attributes,
+    diff::{RenameOptions, detect_renames, unified_diff, unified_diff_paths},
+    index::Index,
+    objects::{blob::Blob, commit::Commit, tree::TreeChange},
+    paths::{ensure_work_tree, repository_root_path},
+    revparse,
+};
+
+/// Shows the changes between two revisions, or between one revision and the
+/// working tree. With `textconv`, files whose `diff` attribute names a driver
+/// with a textconv command are diffed as that command's output. `renames`
+/// pairs similar files between two revisions as renames and copies.
+pub fn run(
+    old_revision: &str,
+    new_revision: Option<&str>,
+    textconv: bool,
+    renames: RenameOptions,
+) -> Result<()> {
+    let patch = match new_revision {
+        Some(new_revision) => diff_revisions(old_revision, new_revision, textconv, renames)?,
+        None => diff_work_tree(old_revision, textconv)?,
+    };
+    print!("{patch}");
+
+    Ok(())
+}
+
+/// Shows the changes between two files outside of any repository. A path of
+/// `-` reads that side from stdin.
+pub fn run_no_index(old_path: &Path, new_path: &Path) -> Result<()> {
+    print!("{}", diff_files(old_path, new_path)?);
+
+    Ok(())
+}
+
+fn diff_files(old_path: &Path, new_path: &Path) -> Result<String> {
+    let old = read_file_or_stdin(old_path)?;
+    let new = read_file_or_stdin(new_path)?;
+    if old == new {
+        return Ok(String::new());
+    }
+
+    Ok(unified_diff_paths(
+        old_path,
+        new_path,
+        Some(&old),
+        Some(&new),
+    ))
+}
+
+fn read_file_or_stdin(path: &Path) -> Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut content = vec![];
+        io::stdin()
+            .read_to_end(&mut content)
+            .context("Unable to diff. Unable to read stdin")?;
+        return Ok(content);
+    }
+
+    fs::read(path).with_context(|| format!("Unable to diff. Unable to read {}", path.display()))
+}
+
+fn diff_revisions(
+    old_revision: &str,
+    new_revision: &str,
+    textconv: bool,
+    renames: RenameOptions,
+) -> Result<String> {
+    let old_tree =
+        Commit::load(&revparse::resolve(old_revision).context("Unable to diff")?)?.tree()?;
+    let new_tree =
+        Commit::load(&revparse::resolve(new_revision).context("Unable to diff")?)?.tree()?;
+
+    let mut patch = String::new();
+    for change in detect_renames(old_tree.diff(&new_tree), renames)? {
+        let (old_hash, new_hash) = match &change {
+            TreeChange::Added { hash, .. } => (None, Some(hash)),
+            TreeChange::Deleted { hash, .. } => (Some(hash), None),
+            TreeChange::Modified {
+                old_hash, new_hash, ..
+            }
+            | TreeChange::Renamed {
+                old_hash, new_hash, ..
+            }
+            | TreeChange::Copied {
+                old_hash, new_hash, ..
+            } => (Some(old_hash), Some(new_hash)),
+        };
+        let old = old_hash.map(|hash| Blob::load(hash).body()).transpose()?;
+        let new = new_hash.map(|hash| Blob::load(hash).body()).transpose()?;
+        let old_path = match &change {
+            TreeChange::Renamed { old_path, .. } | TreeChange::Copied { old_path, .. } => old_path,
+            change => change.path(),
+        };
+        let old = convert(old_path, old, textconv)?;
+        let new = convert(change.path(), new, textconv)?;
+        let file_patch =
+            unified_diff_paths(old_path, change.path(), old.as_deref(), new.as_deref());
+        patch.push_str(&with_rename_header(file_patch, &change));
+    }
+
+    Ok(patch)
+}
+
+/// Diffs the files tracked in `revision` or the index against their
+/// contents in the working tree. Untracked files are left out.
+fn diff_work_tree(revision: &str, textconv: bool) -> Result<String> {
+    ensure_work_tree().context("Unable to diff")?;
+    let files = Commit::load(&revparse::resolve(revision).context("Unable to diff")?)?
+        .tree()?
+        .files();
+    let repository_root = repository_root_path();
+    let index = Index::load()?;
+    let mut paths: BTreeSet<_> = files.keys().cloned().collect();
+    for file in index.files() {
+        paths.insert(file.path().to_path_buf());
+    }
+
+    let mut patch = String::new();
+    for path in paths {
+        let old = files
+            .get(&path)
+            .map(|hash| Blob::load(hash).body())
+            .transpose()?;
+        let new = read_work_tree_file(&repository_root, &path)?;
+        if old != new {
+            let old = convert(&path, old, textconv)?;
+            let new = convert(&path, new, textconv)?;
+            patch.push_str(&unified_diff(&path, old.as_deref(), new.as_deref()));
+        }
+    }
+
+    Ok(patch)
+}
+
+/// Adds the similarity and rename or copy lines git puts under the
+/// `diff --git` line of a file that was renamed or copied.
+fn with_rename_header(file_patch: String, change: &TreeChange) -> String {
+    let (kind, old_path, new_path, similarity) = match change {
+        TreeChange::Renamed {
+            old_path,
+            new_path,
+            similarity,
+            ..
+        } => ("rename", old_path, new_path, similarity),
+        TreeChange::Copied {
+            old_path,
+            new_path,
+            similarity,
+            ..
+        } => ("copy", old_path, new_path, similarity),
+        _ => return file_patch,
+    };
+    let (diff_line, rest) = file_patch.split_once('\n').unwrap_or((&file_patch, ""));
+
+    format!(
+        "{diff_line}\nsimilarity index {similarity}%\n{kind} from {}\n{kind} to {}\n{rest}",
+        old_path.display(),
+        new_path.display()
+    )
+}
+
+/// Runs a file's contents through its textconv filter when `textconv` is on.
+fn convert(path: &Path, content: Option<Vec<u8>>, textconv: bool) -> Result<Option<Vec<u8>>> {
+    match content {
+        Some(content) if textconv => Ok(Some(attributes::textconv(path, content)?)),
+        content => Ok(content),
+    }
+}
+
+/// A working tree file's contents as they would be stored, or `None` when it
+/// doesn't exist.
+fn read_work_tree_file(repository_root: &Path, path: &Path) -> Result<Option<Vec<u8>>> {
+    let full_path = repository_root.join(path);
+    if !full_path.is_file() {
+        return Ok(None);
+    }
+
+    let content = fs::read(&full_path)
+        .with_context(|| format!("Unable to diff. Unable to read {}", path.display()))?;
+    Ok(Some(attributes::to_stored(path, content)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{attributes::ATTRIBUTES_FILE_NAME, config::Config, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_diff_revisions() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\nb\n")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("first")?
+            .file("a.txt", "a\nB\n")?
+            .remove_file("b.txt")?
+            .file("c.txt", "c\n")?
+            .stage(".")?
+            .commit("Second commit")?;
+
+        assert_eq!(
+            "diff --git a/a.txt b/a.txt\n--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n a\n-b\n+B\n\
+             diff --git a/b.txt b/b.txt\n--- a/b.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-b\n\
+             diff --git a/c.txt b/c.txt\n--- /dev/null\n+++ b/c.txt\n@@ -0,0 +1 @@\n+c\n",
+            diff_revisions("first", "HEAD", true, RenameOptions::default())?
+        );
+        assert_eq!(
+            "",
+            diff_revisions("HEAD", "master", true, RenameOptions::default())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_work_tree() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed\n")?
+            .remove_file("b.txt")?
+            .file("untracked.txt", "untracked\n")?;
+
+        assert_eq!(
+            "diff --git a/a.txt b/a.txt\n--- a/a.txt\n+++ b/a.txt\n@@ -1 +1 @@\n-a\n+changed\n\
+             diff --git a/b.txt b/b.txt\n--- a/b.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-b\n",
+            diff_work_tree("HEAD", true)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_files() -> Result<()> {
+        let dir = TempDir::new()?;
+        let old = dir.path().join("old.txt");
+        let new = dir.path().join("new.txt");
+        fs::write(&old, "a\nb\nc\n")?;
+        fs::write(&new, "a\nB\nc\n")?;
+
+        let patch = diff_files(&old, &new)?;
+        let mut lines = patch.lines();
+        assert_eq!(
+            Some(format!("diff --git a/{} b/{}", old.display(), new.display()).as_str()),
+            lines.next()
+        );
+        assert_eq!(
+            vec!["@@ -1,3 +1,3 @@", " a", "-b", "+B", " c"],
+            lines.skip(2).collect::<Vec<_>>()
+        );
+        assert_eq!("", diff_files(&old, &old)?);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_textconv() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let mut config = Config::load()?;
+        config.set("diff.upper.textconv", "tr a-z A-Z")?;
+        config.save()?;
+        repo.file(ATTRIBUTES_FILE_NAME, "*.txt diff=upper\n")?
+            .file("a.txt", "a\nb\n")?
+            .file("b.md", "b\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "a\nc\n")?
+            .file("b.md", "c\n")?;
+
+        assert_eq!(
+            "diff --git a/a.txt b/a.txt\n--- a/a.txt\n+++ b/a.txt\n@@ -1,2 +1,2 @@\n A\n-B\n+C\n\
+             diff --git a/b.md b/b.md\n--- a/b.md\n+++ b/b.md\n@@ -1 +1 @@\n-b\n+c\n",
+            diff_work_tree("HEAD", true)?
+        );
+        assert!(diff_work_tree("HEAD", false)?.contains(" a\n-b\n+c\n"));
+
+        Ok(())
+    }
+}
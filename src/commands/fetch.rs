@@ -0,0 +1,25 @@
+use anyhow::Result;
+
+use crate::{output::info, remote::Remote};
+
+pub fn run(remote_name: &str) -> Result<()> {
+    let remote = Remote::find(remote_name)?;
+    let fetched = remote.fetch()?;
+
+    info!("From {}", remote.url().display());
+    for fetched_ref in fetched {
+        let tracking_ref = format!("{}/{}", remote.name(), fetched_ref.branch);
+        match fetched_ref.old_hash {
+            None => info!(" * [new branch]  {} -> {tracking_ref}", fetched_ref.branch),
+            Some(old_hash) if old_hash != fetched_ref.new_hash => info!(
+                "   {}..{}  {} -> {tracking_ref}",
+                &old_hash.to_hex()[..7],
+                &fetched_ref.new_hash.to_hex()[..7],
+                fetched_ref.branch
+            ),
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::{
+    index::Index,
+    objects::{blob::Blob, commit::Commit, is_text},
+    paths::repository_root_path,
+    revparse,
+};
+
+/// Where `grep` looks for matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrepSource {
+    /// Tracked files as they are in the working tree.
+    WorkTree,
+    /// The staged version of every file.
+    Cached,
+    /// The files of a commit.
+    Revision(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    /// Relative to the repository root.
+    pub path: PathBuf,
+    /// 1-based.
+    pub line_number: usize,
+    pub line: String,
+}
+
+pub fn run(pattern: &str, fixed_strings: bool, source: &GrepSource) -> Result<()> {
+    let prefix = match source {
+        GrepSource::Revision(revision) => format!("{revision}:"),
+        _ => String::new(),
+    };
+    for grep_match in grep(pattern, fixed_strings, source)? {
+        println!(
+            "{prefix}{}:{}:{}",
+            grep_match.path.display(),
+            grep_match.line_number,
+            grep_match.line
+        );
+    }
+
+    Ok(())
+}
+
+/// Finds the lines matching `pattern` in tracked files, in path order.
+/// `pattern` is a regular expression unless `fixed_strings` is set. Binary
+/// files are skipped.
+pub fn grep(pattern: &str, fixed_strings: bool, source: &GrepSource) -> Result<Vec<GrepMatch>> {
+    let pattern = if fixed_strings {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    let regex = Regex::new(&pattern).with_context(|| format!("Invalid pattern \"{pattern}\""))?;
+
+    let mut matches = vec![];
+    for (path, content) in searched_files(source)? {
+        if !is_text(&content) {
+            continue;
+        }
+
+        let content = String::from_utf8_lossy(&content);
+        for (i, line) in content.lines().enumerate() {
+            if regex.is_match(line) {
+                matches.push(GrepMatch {
+                    path: path.clone(),
+                    line_number: i + 1,
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+fn searched_files(source: &GrepSource) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let repository_path = repository_root_path();
+    let mut files = BTreeMap::new();
+    match source {
+        GrepSource::WorkTree | GrepSource::Cached => {
+            for file in Index::load()?.files() {
+                let relative_path = file.path().to_path_buf();
+                let full_path = repository_path.join(&relative_path);
+                let content = if *source == GrepSource::Cached {
+                    Blob::load(file.hash()).body()?
+                } else if full_path.is_file() {
+                    fs::read(&full_path).with_context(|| {
+                        format!("Unable to grep. Unable to read {}", relative_path.display())
+                    })?
+                } else {
+                    continue;
+                };
+                files.insert(relative_path, content);
+            }
+        }
+        GrepSource::Revision(revision) => {
+            let hash = revparse::resolve(revision).context("Unable to grep")?;
+            for (path, hash) in Commit::load(&hash)?.tree()?.files() {
+                files.insert(path, Blob::load(&hash).body()?);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    fn locations(matches: &[GrepMatch]) -> Vec<(String, usize)> {
+        matches
+            .iter()
+            .map(|m| (m.path.display().to_string(), m.line_number))
+            .collect()
+    }
+
+    #[test]
+    fn test_grep() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "fn main() {}\nlet x = 1;\n")?
+            .file("src/b.rs", "fn helper() {}\n")?
+            .file("image.bin", "fn \0binary")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("untracked.txt", "fn untracked() {}\n")?;
+
+        let matches = grep(r"fn \w+\(\)", false, &GrepSource::WorkTree)?;
+        assert_eq!(
+            vec![("a.txt".to_string(), 1), ("src/b.rs".to_string(), 1)],
+            locations(&matches)
+        );
+        assert_eq!("fn main() {}", matches[0].line);
+
+        assert_eq!(1, grep("main()", true, &GrepSource::WorkTree)?.len());
+        assert!(grep("x.=", true, &GrepSource::WorkTree)?.is_empty());
+        assert!(grep("(", false, &GrepSource::WorkTree).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grep_cached_and_revision() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "old\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "staged\n")?
+            .stage(".")?
+            .file("a.txt", "working\n")?;
+
+        assert_eq!(1, grep("working", true, &GrepSource::WorkTree)?.len());
+        assert!(grep("working", true, &GrepSource::Cached)?.is_empty());
+        assert_eq!(
+            vec![("a.txt".to_string(), 1)],
+            locations(&grep("staged", true, &GrepSource::Cached)?)
+        );
+        let revision = GrepSource::Revision("HEAD".to_string());
+        assert_eq!(1, grep("old", true, &revision)?.len());
+        assert!(grep("staged", true, &revision)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -6,22 +6,46 @@ use std::{
 
 use anyhow::{Context, Result, anyhow};
 
-pub fn run(path: impl AsRef<Path>) -> Result<()> {
+use crate::{config::Config, output::info, paths::rygit_dir_override};
+
+const DEFAULT_DESCRIPTION: &str =
+    "Unnamed repository; edit this file 'description' to name the repository.\n";
+
+pub fn run(path: impl AsRef<Path>, bare: bool) -> Result<()> {
+    init(path, bare)?;
+    info!("Repository initialized!");
+
+    Ok(())
+}
+
+/// Creates a repository in `path`, with its metadata in `.rygit` unless it
+/// has been moved with `--git-dir`. A bare repository has no work tree, so
+/// its metadata goes in `path` itself.
+pub fn init(path: impl AsRef<Path>, bare: bool) -> Result<()> {
     let path = path.as_ref();
-    let rygit_dir = path.join(".rygit");
-    if rygit_dir.exists() {
+    let rygit_dir = match rygit_dir_override() {
+        Some(rygit_dir) => rygit_dir,
+        None if bare => path.to_path_buf(),
+        None => path.join(".rygit"),
+    };
+    if rygit_dir.join("HEAD").exists() {
         return Err(anyhow!("rygit already initialized"));
     }
 
-    fs::create_dir(&rygit_dir)
+    fs::create_dir_all(&rygit_dir)
         .context("Unable to initialize rygit, unable to create .rygit directory")?;
 
     File::create(rygit_dir.join("HEAD"))
         .context("Unable to initialize rygit, unable to create .rygit/HEAD")?
         .write_all(b"ref: refs/heads/master")?;
 
-    File::create(rygit_dir.join("index"))
-        .context("Unable to initialize rygit, unable to create .rygit/index")?;
+    if !bare {
+        File::create(rygit_dir.join("index"))
+            .context("Unable to initialize rygit, unable to create .rygit/index")?;
+    }
+
+    fs::create_dir_all(rygit_dir.join("objects"))
+        .context("Unable to initialize rygit, unable to create .rygit/objects directory")?;
 
     let refs_path = rygit_dir.join("refs");
     fs::create_dir(&refs_path)
@@ -30,10 +54,33 @@ pub fn run(path: impl AsRef<Path>) -> Result<()> {
     fs::create_dir(refs_path.join("heads"))
         .context("Unable to initialize rygit, unable to create .rygit/refs/heads directory")?;
 
+    fs::create_dir(refs_path.join("tags"))
+        .context("Unable to initialize rygit, unable to create .rygit/refs/tags directory")?;
+
+    fs::create_dir(refs_path.join("remotes"))
+        .context("Unable to initialize rygit, unable to create .rygit/refs/remotes directory")?;
+
+    fs::write(rygit_dir.join("description"), DEFAULT_DESCRIPTION)
+        .context("Unable to initialize rygit. Unable to create .rygit/description")?;
+
     File::create(refs_path.join("heads").join("master"))
         .context("Unable to initialize rygit. Unable to create refs/heads/master")?;
 
-    println!("Repository initialized!");
+    // HEAD was written in upper case, so finding it in lower case means the
+    // filesystem can't tell paths that differ only in case apart
+    let ignore_case = rygit_dir.join("head").exists();
+    if bare || ignore_case {
+        let mut config = Config::load_from(rygit_dir.join("config"))?;
+        if bare {
+            config.set("core.bare", "true")?;
+        }
+        if ignore_case {
+            config.set("core.ignorecase", "true")?;
+        }
+        config
+            .save()
+            .context("Unable to initialize rygit. Unable to write config")?;
+    }
 
     Ok(())
 }
@@ -52,7 +99,7 @@ mod tests {
     #[test]
     fn test_run_when_already_initialized() -> Result<()> {
         let repo = TestRepo::new()?;
-        let result = run(repo.path());
+        let result = run(repo.path(), false);
         assert!(result.is_err());
 
         Ok(())
@@ -62,7 +109,7 @@ mod tests {
     fn test_run_initializes_ryigit() -> Result<()> {
         let dir = TempDir::new()?;
 
-        run(&dir)?;
+        run(&dir, false)?;
 
         let rygit_path = dir.path().join(".rygit");
         let rygit_initialized = rygit_path.exists() && rygit_path.is_dir();
@@ -88,6 +135,36 @@ mod tests {
         let heads_initialized = heads_path.exists() && heads_path.is_dir();
         assert!(heads_initialized);
 
+        assert!(refs_path.join("tags").is_dir());
+        let remotes_path = refs_path.join("remotes");
+        assert!(remotes_path.is_dir());
+
+        assert_eq!(
+            DEFAULT_DESCRIPTION,
+            fs::read_to_string(rygit_path.join("description"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_bare() -> Result<()> {
+        let dir = TempDir::new()?;
+
+        run(&dir, true)?;
+
+        let path = dir.path();
+        assert!(!path.join(".rygit").exists());
+        assert!(!path.join("index").exists());
+        assert_eq!(
+            "ref: refs/heads/master",
+            fs::read_to_string(path.join("HEAD"))?
+        );
+        assert!(path.join("refs/heads/master").is_file());
+        assert!(path.join("objects").is_dir());
+        let config = Config::load_from(path.join("config"))?;
+        assert_eq!(Some(true), config.get_bool("core.bare")?);
+
         Ok(())
     }
 }
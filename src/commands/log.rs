@@ -1,56 +1,436 @@
-use std::{fs::File, io::Read};
-
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
 
-use crate::{hash::Hash, objects::commit::Commit, paths::head_ref_path};
-
-pub fn run() -> Result<()> {
-    let mut head_commit_file =
-        File::open(head_ref_path()).context("Unable to generate log. Unable to open head ref")?;
-    let mut head_commit_hash = String::new();
-    head_commit_file
-        .read_to_string(&mut head_commit_hash)
-        .context("Unable to generate log. Unable to read head commit hash")?;
-    let head_commit_hash = head_commit_hash.trim();
-    let head_commit_hash = Hash::from_hex(head_commit_hash)
-        .context("Unable to generate log. head commit hash is not a valid hash")?;
-    let head_commit = Commit::load(&head_commit_hash)
-        .context("Unable to generate log. Unable to load head commit")?;
-
-    let mut log_contents = String::new();
-    let mut commit = Some(head_commit);
-    while let Some(c) = commit {
-        let commit_log = commit_log(&c);
-        log_contents.push_str(&commit_log);
-
-        let parents = c.parents()?;
-        commit = if !parents.is_empty() {
-            Some(parents.into_iter().next().unwrap())
-        } else {
-            None
-        };
+use crate::{
+    branch::Branch, notes::Notes, objects::commit::Commit, output::OutputFormat, refs,
+    rev_list::Limits, tag::Tag,
+};
+
+/// How each commit in the log is rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Hash, author, date and the indented message.
+    Medium,
+    /// Hash and subject on one line.
+    Oneline,
+    /// A user supplied template, see [`format_commit`].
+    Format(String),
+}
+
+impl LogFormat {
+    /// Parses a `--pretty` value: `medium`, `oneline` or `format:<template>`.
+    pub fn parse(pretty: &str) -> Result<Self> {
+        if let Some(template) = pretty.strip_prefix("format:") {
+            return Ok(LogFormat::Format(template.to_string()));
+        }
+
+        match pretty {
+            "medium" => Ok(LogFormat::Medium),
+            "oneline" => Ok(LogFormat::Oneline),
+            _ => bail!("Invalid pretty format \"{pretty}\""),
+        }
+    }
+}
+
+/// A commit as printed by `log --format json`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub hash: String,
+    pub parents: Vec<String>,
+    pub author_name: String,
+    pub author_email: String,
+    /// The author date in RFC 3339 format.
+    pub date: String,
+    pub message: String,
+}
+
+impl From<&Commit> for LogEntry {
+    fn from(commit: &Commit) -> Self {
+        Self {
+            hash: commit.hash().to_hex(),
+            parents: commit
+                .parent_hashes()
+                .iter()
+                .map(|hash| hash.to_hex())
+                .collect(),
+            author_name: commit.author().name().to_string(),
+            author_email: commit.author().email().to_string(),
+            date: commit.author().timestamp().to_rfc3339(),
+            message: commit.message().to_string(),
+        }
+    }
+}
+
+/// Which commits `log` shows, and in what order.
+#[derive(Debug, Default)]
+pub struct LogOptions {
+    pub limits: Limits,
+    /// Shows the oldest commit first. Limits still select the newest commits.
+    pub reverse: bool,
+    /// Shows the history of every branch and tag instead of just HEAD.
+    pub all: bool,
+}
+
+pub fn run(format: &LogFormat, output_format: OutputFormat, options: &LogOptions) -> Result<()> {
+    let mut commits = if options.all {
+        all_history(&options.limits)?
+    } else {
+        history(&options.limits)?
+    };
+    if options.reverse {
+        commits.reverse();
+    }
+    if commits.is_empty() && output_format == OutputFormat::Human {
+        let branch = Branch::current_name()?.unwrap_or_else(|| "HEAD".to_string());
+        println!("Your current branch '{branch}' does not have any commits yet");
+        return Ok(());
+    }
+
+    if output_format == OutputFormat::Json {
+        let entries: Vec<_> = commits.iter().map(LogEntry::from).collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).context("Unable to serialize log")?
+        );
+        return Ok(());
     }
 
+    let notes = Notes::load().context("Unable to generate log")?;
+    let commit_logs = commits
+        .iter()
+        .map(|c| Ok(commit_log(c, format, notes.get(c.hash())?.as_deref())))
+        .collect::<Result<Vec<_>>>()?;
+    let separator = if *format == LogFormat::Medium {
+        "\n\n"
+    } else {
+        "\n"
+    };
+    println!("{}", commit_logs.join(separator));
+
     Ok(())
 }
 
-fn commit_log(commit: &Commit) -> String {
-    let mut log = String::new();
-    log.push_str(&format!("commit {}", commit.hash().to_hex()));
-    log.push_str(&format!(
-        "Author: {} <{}>",
-        commit.author().name(),
-        commit.author().email()
-    ));
-    log.push_str(&format!(
-        "Date: {}",
-        format_commit_date(commit.author().timestamp())
-    ));
+/// The commits reachable from HEAD within `limits`, newest first. Empty
+/// before the first commit.
+pub fn history(limits: &Limits) -> Result<Vec<Commit>> {
+    let Some(head_commit_hash) =
+        refs::resolve("HEAD").context("Unable to generate log. Unable to read head ref")?
+    else {
+        return Ok(vec![]);
+    };
+
+    limits
+        .walk(&[head_commit_hash], &[])
+        .context("Unable to generate log")
+}
+
+/// The commits reachable from any branch or tag within `limits`, each listed
+/// once, newest first.
+fn all_history(limits: &Limits) -> Result<Vec<Commit>> {
+    let mut tips: Vec<_> = Branch::list()
+        .context("Unable to generate log")?
+        .iter()
+        .filter_map(|branch| branch.commit_hash().copied())
+        .collect();
+    tips.extend(
+        Tag::list()
+            .context("Unable to generate log")?
+            .iter()
+            .map(|tag| *tag.commit_hash()),
+    );
+
+    limits.walk(&tips, &[]).context("Unable to generate log")
+}
+
+/// Renders one commit. The medium format also shows the commit's note.
+fn commit_log(commit: &Commit, format: &LogFormat, note: Option<&str>) -> String {
+    match format {
+        LogFormat::Medium => {
+            let mut log = vec![
+                format!("commit {}", commit.hash().to_hex()),
+                format!(
+                    "Author: {} <{}>",
+                    commit.author().name(),
+                    commit.author().email()
+                ),
+                format!(
+                    "Date:   {}",
+                    format_commit_date(commit.author().timestamp())
+                ),
+                String::new(),
+            ];
+            log.extend(commit.message().lines().map(|line| format!("    {line}")));
+            if let Some(note) = note {
+                log.push(String::new());
+                log.push("Notes:".to_string());
+                log.extend(note.lines().map(|line| format!("    {line}")));
+            }
+
+            log.join("\n")
+        }
+        LogFormat::Oneline => format!("{} {}", commit.hash().to_hex(), commit.subject()),
+        LogFormat::Format(template) => format_commit(commit, template),
+    }
+}
+
+/// Renders a commit through a template. Supported placeholders are `%H`
+/// (hash), `%h` (abbreviated hash), `%an` / `%ae` (author name / email),
+/// `%ad` (author date), `%s` (subject), `%n` (newline) and `%%`. Anything
+/// else is copied as is.
+pub fn format_commit(commit: &Commit, template: &str) -> String {
+    let mut formatted = String::new();
+    let mut rest = template;
+    while let Some(position) = rest.find('%') {
+        formatted.push_str(&rest[..position]);
+        rest = &rest[position..];
+
+        let placeholders: [(&str, &dyn Fn() -> String); 8] = [
+            ("%H", &|| commit.hash().to_hex()),
+            ("%h", &|| commit.hash().to_hex()[..7].to_string()),
+            ("%an", &|| commit.author().name().to_string()),
+            ("%ae", &|| commit.author().email().to_string()),
+            ("%ad", &|| format_commit_date(commit.author().timestamp())),
+            ("%s", &|| commit.subject().to_string()),
+            ("%n", &|| "\n".to_string()),
+            ("%%", &|| "%".to_string()),
+        ];
+        match placeholders.iter().find(|(p, _)| rest.starts_with(p)) {
+            Some((placeholder, expand)) => {
+                formatted.push_str(&expand());
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                formatted.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    formatted.push_str(rest);
 
-    log
+    formatted
 }
 
 fn format_commit_date(timestamp: &DateTime<FixedOffset>) -> String {
     timestamp.format("%a %b %e %T %Y %z").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+    use regex::Regex;
+
+    use crate::{
+        commands::merge::{MergeOptions, merge},
+        hash::Hash,
+        index::Index,
+        objects::signature::Signature,
+        paths::head_ref_path,
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_log_entry_json() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+
+        let commits = history(&Limits::default())?;
+        let entries: Vec<_> = commits.iter().map(LogEntry::from).collect();
+        let json = serde_json::to_string(&entries)?;
+        let entries: Vec<LogEntry> = serde_json::from_str(&json)?;
+
+        assert_eq!(2, entries.len());
+        assert_eq!(commits[0].hash().to_hex(), entries[0].hash);
+        assert_eq!(vec![commits[1].hash().to_hex()], entries[0].parents);
+        assert_eq!("Second commit", entries[0].message);
+        assert_eq!("Larry Sellers", entries[0].author_name);
+        assert_eq!("lsellers@test.com", entries[0].author_email);
+        assert!(entries[1].parents.is_empty());
+        assert_eq!("Initial commit", entries[1].message);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_commit() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit\n\nBody")?;
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        let hash = commit.hash().to_hex();
+
+        assert_eq!(
+            format!(
+                "{hash} {} Larry Sellers <lsellers@test.com> Initial commit 100%",
+                &hash[..7]
+            ),
+            format_commit(&commit, "%H %h %an <%ae> %s 100%%")
+        );
+        assert_eq!("%x\n", format_commit(&commit, "%x%n"));
+        assert_eq!(
+            format_commit_date(commit.author().timestamp()),
+            format_commit(&commit, "%ad")
+        );
+
+        assert_eq!(
+            LogFormat::Format("%h %s".to_string()),
+            LogFormat::parse("format:%h %s")?
+        );
+        assert!(LogFormat::parse("fancy").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_author_and_grep() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let larry = Signature::new("Larry Sellers", "lsellers@test.com");
+        let sam = Signature::new("Sam Ortiz", "sam@example.com");
+        for (name, message, author) in [
+            ("a.txt", "Add a", &larry),
+            ("b.txt", "Fix b parsing", &sam),
+            ("c.txt", "Add c", &sam),
+            ("d.txt", "Fix d", &larry),
+        ] {
+            repo.file(name, name)?.stage(".")?;
+            Commit::create(&Index::load()?, message, author.clone(), author.clone())?;
+        }
+        let messages = |limits: &Limits| -> Result<Vec<String>> {
+            Ok(history(limits)?
+                .iter()
+                .map(|commit| commit.message().to_string())
+                .collect())
+        };
+
+        let limits = Limits {
+            author: Some(Regex::new("Sam")?),
+            ..Limits::default()
+        };
+        assert_eq!(vec!["Add c", "Fix b parsing"], messages(&limits)?);
+
+        let limits = Limits {
+            author: Some(Regex::new("example.com")?),
+            grep: Some(Regex::new("Fix")?),
+            ..Limits::default()
+        };
+        assert_eq!(vec!["Fix b parsing"], messages(&limits)?);
+
+        let limits = Limits {
+            grep: Some(Regex::new("Fix")?),
+            ..Limits::default()
+        };
+        assert_eq!(vec!["Fix d", "Fix b parsing"], messages(&limits)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_history() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Master commit")?
+            .switch("feature")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Feature commit")?
+            .file("d.txt", "d")?
+            .stage(".")?
+            .commit("Another feature commit")?;
+
+        let mut messages: Vec<_> = all_history(&Limits::default())?
+            .iter()
+            .map(|commit| commit.message().to_string())
+            .collect();
+        messages.sort();
+        assert_eq!(
+            vec![
+                "Another feature commit",
+                "Feature commit",
+                "Initial commit",
+                "Master commit"
+            ],
+            messages
+        );
+        assert_eq!(3, history(&Limits::default())?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_first_parent() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .switch("feature")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Feature commit")?
+            .switch("master")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Master commit")?;
+        let options = MergeOptions {
+            no_ff: true,
+            ..MergeOptions::default()
+        };
+        merge("feature", &options)?;
+        let messages = |limits: &Limits| -> Result<Vec<String>> {
+            Ok(history(limits)?
+                .iter()
+                .map(|commit| commit.subject().to_string())
+                .collect())
+        };
+
+        assert!(messages(&Limits::default())?.contains(&"Feature commit".to_string()));
+        let limits = Limits {
+            first_parent: true,
+            ..Limits::default()
+        };
+        assert_eq!(
+            vec!["Merge branch 'feature'", "Master commit", "Initial commit"],
+            messages(&limits)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_log_shows_note() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let commit = Commit::load(&Hash::from_hex(&fs::read_to_string(head_ref_path())?)?)?;
+        let mut notes = Notes::load()?;
+        notes.add(
+            commit.hash(),
+            "Reviewed\nLooks good",
+            Signature::new("Walter Sobchak", "walter@example.com"),
+            false,
+        )?;
+        let note = Notes::load()?.get(commit.hash())?;
+
+        let log = commit_log(&commit, &LogFormat::Medium, note.as_deref());
+        assert!(log.ends_with("    Initial commit\n\nNotes:\n    Reviewed\n    Looks good"));
+        assert!(!commit_log(&commit, &LogFormat::Oneline, note.as_deref()).contains("Reviewed"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+
+use crate::index::Index;
+
+/// Lists the files in the index, or with `unmerged` only the files that
+/// still have conflict stages from a merge.
+pub fn run(unmerged: bool) -> Result<()> {
+    let index = Index::load()?;
+    let paths = if unmerged {
+        index.unmerged_paths()
+    } else {
+        let mut paths: Vec<_> = index
+            .files()
+            .iter()
+            .map(|file| file.path().to_path_buf())
+            .collect();
+        paths.dedup();
+        paths
+    };
+    for path in paths {
+        println!("{}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        commands::merge::{MergeOptions, merge},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_unmerged_paths() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "master\n")?
+            .file("b.txt", "master\n")?
+            .stage(".")?
+            .commit("Change a and b on master")?
+            .switch("feature")?
+            .file("a.txt", "feature\n")?
+            .file("c.txt", "c\n")?
+            .stage(".")?
+            .commit("Change a on feature")?
+            .switch("master")?;
+        assert!(Index::load()?.unmerged_paths().is_empty());
+
+        assert!(merge("feature", &MergeOptions::default()).is_err());
+        assert_eq!(
+            vec![PathBuf::from("a.txt")],
+            Index::load()?.unmerged_paths()
+        );
+
+        repo.file("a.txt", "master and feature\n")?.stage("a.txt")?;
+        assert!(Index::load()?.unmerged_paths().is_empty());
+
+        Ok(())
+    }
+}
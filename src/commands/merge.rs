@@ -0,0 +1,490 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    branch::Branch,
+    commands::commit::identity,
+    hash::Hash,
+    index::Index,
+    merge::merge_trees,
+    objects::{commit::Commit, tree::Tree},
+    operation::{MergeState, Operation, ensure_resolved},
+    output::info,
+    paths::ensure_work_tree,
+    refs::ref_exists,
+    revparse, worktree,
+};
+
+/// What merging a revision into the current branch did.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// The branch already contains the revision.
+    UpToDate,
+    /// The branch was moved forward to the revision.
+    FastForward(Hash),
+    /// A merge commit was created.
+    Merged(Hash),
+    /// The merged changes were staged without committing.
+    Squashed,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeOptions {
+    /// Creates a merge commit even when the branch could be fast-forwarded.
+    pub no_ff: bool,
+    /// Stages the merged changes in the index and working tree without
+    /// committing them or recording the merge.
+    pub squash: bool,
+}
+
+pub fn run(revision: &str, options: MergeOptions) -> Result<()> {
+    match merge(revision, &options)? {
+        MergeOutcome::UpToDate => info!("Already up to date."),
+        MergeOutcome::FastForward(_) => info!("Fast-forward"),
+        MergeOutcome::Merged(_) => info!("Merge made by the 'ort' strategy."),
+        MergeOutcome::Squashed => info!("Squash commit -- not updating HEAD"),
+    }
+
+    Ok(())
+}
+
+/// Merges `revision` into the current branch, fast-forwarding when the branch
+/// has no commits of its own unless `--no-ff` is given. Otherwise the two
+/// histories are merged against their merge base and committed with both as
+/// parents.
+///
+/// If the merge conflicts, the merged files are checked out with conflict
+/// markers and the merge is saved as in progress, to be continued or aborted.
+/// A squash merge only checks out the merged files, leaving the commit to
+/// the user.
+pub fn merge(revision: &str, options: &MergeOptions) -> Result<MergeOutcome> {
+    ensure_work_tree().context("Unable to merge")?;
+    Operation::ensure_none().context("Unable to merge")?;
+    let mut branch = Branch::current().context("Unable to merge")?;
+    let head = branch.tip().context("Unable to merge")?;
+    let other = revparse::resolve(revision).context("Unable to merge")?;
+
+    if Commit::is_ancestor(&other, &head)? {
+        return Ok(MergeOutcome::UpToDate);
+    }
+    if Commit::is_ancestor(&head, &other)? && !options.no_ff && !options.squash {
+        branch.update(other)?;
+        worktree::checkout(&Commit::load(&other)?.tree()?)?;
+        return Ok(MergeOutcome::FastForward(other));
+    }
+
+    let base = Commit::merge_base(&head, &other)?
+        .map(|hash| Commit::load(&hash)?.tree())
+        .transpose()?;
+    let ours = Commit::load(&head)?.tree()?;
+    let theirs = Commit::load(&other)?.tree()?;
+    let merge = merge_trees(base.as_ref(), &ours, &theirs, "HEAD", revision)?;
+    let tree = Tree::from_paths(&merge.files)?;
+    let conflicts: Vec<_> = merge
+        .conflicts
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect();
+
+    if options.squash {
+        worktree::checkout(&tree)?;
+        if !merge.is_clean() {
+            bail!(
+                "Automatic merge failed. Conflicts in: {}. Resolve them and commit the result",
+                conflicts.join(", ")
+            );
+        }
+        return Ok(MergeOutcome::Squashed);
+    }
+
+    let message = merge_message(revision, branch.name());
+    if !merge.is_clean() {
+        MergeState {
+            orig_head: head,
+            merge_head: other,
+            message,
+        }
+        .save()?;
+        worktree::checkout(&tree)?;
+        record_conflicts(&merge.conflicts, base.as_ref(), &ours, &theirs)?;
+        bail!(
+            "Automatic merge failed. Conflicts in: {}. Resolve them and run \"rygit merge --continue\", or run \"rygit merge --abort\"",
+            conflicts.join(", ")
+        );
+    }
+
+    let commit = Commit::create_from_tree(
+        *tree.hash(),
+        vec![head, other],
+        message,
+        identity()?,
+        identity()?,
+    )?;
+    branch.update(*commit.hash())?;
+    worktree::checkout(&tree)?;
+
+    Ok(MergeOutcome::Merged(*commit.hash()))
+}
+
+/// Finishes a conflicted merge once the conflicts are resolved and staged,
+/// committing the index with both heads as parents.
+pub fn continue_merge() -> Result<Hash> {
+    let state = MergeState::load().context("Unable to continue. No merge in progress")?;
+    let index = Index::load()?;
+    index.ensure_merged().context("Unable to continue merge")?;
+    let tree = Tree::from_index(&index)?;
+    ensure_resolved(&tree).context("Unable to continue merge")?;
+
+    let commit = Commit::create_from_tree(
+        *tree.hash(),
+        vec![state.orig_head, state.merge_head],
+        state.message,
+        identity()?,
+        identity()?,
+    )?;
+    Branch::current()?.update(*commit.hash())?;
+    MergeState::clear()?;
+
+    Ok(*commit.hash())
+}
+
+/// Abandons a conflicted merge, restoring the branch, index and working tree
+/// to how they were before it started.
+pub fn abort() -> Result<()> {
+    let state = MergeState::load().context("Unable to abort. No merge in progress")?;
+    Branch::current()?.update(state.orig_head)?;
+    worktree::checkout(&Commit::load(&state.orig_head)?.tree()?)?;
+
+    MergeState::clear()
+}
+
+/// Replaces the index entries of the conflicted `paths` with stages holding
+/// the base, our and their versions.
+fn record_conflicts(
+    paths: &[PathBuf],
+    base: Option<&Tree>,
+    ours: &Tree,
+    theirs: &Tree,
+) -> Result<()> {
+    let base_files = base.map(|b| b.files()).unwrap_or_default();
+    let our_files = ours.files();
+    let their_files = theirs.files();
+    let mut index = Index::load()?;
+    for path in paths {
+        index.record_conflict(
+            path,
+            base_files.get(path).copied(),
+            our_files.get(path).copied(),
+            their_files.get(path).copied(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn merge_message(revision: &str, branch_name: &str) -> String {
+    let kind = if ref_exists(&format!("refs/heads/{revision}")).unwrap_or(false) {
+        "branch"
+    } else {
+        "commit"
+    };
+    if branch_name == "master" || branch_name == "main" {
+        format!("Merge {kind} '{revision}'")
+    } else {
+        format!("Merge {kind} '{revision}' into {branch_name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use anyhow::{Ok, Result};
+
+    use crate::{attributes::ATTRIBUTES_FILE_NAME, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "a\nmaster\n")?
+            .stage(".")?
+            .commit("Advance master")?;
+        let master = revparse::resolve("master")?;
+        repo.switch("feature")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Add b")?;
+        let feature = revparse::resolve("feature")?;
+
+        repo.switch("master")?;
+        let MergeOutcome::Merged(hash) = merge("feature", &MergeOptions::default())? else {
+            panic!("expected a merge commit");
+        };
+        let commit = Commit::load(&hash)?;
+        assert_eq!(&[master, feature], commit.parent_hashes());
+        assert_eq!("Merge branch 'feature'", commit.message());
+        assert_eq!(hash, revparse::resolve("master")?);
+        assert_eq!(
+            "a\nmaster\n",
+            fs::read_to_string(repo.path().join("a.txt"))?
+        );
+        assert_eq!("b\n", fs::read_to_string(repo.path().join("b.txt"))?);
+
+        assert_eq!(
+            MergeOutcome::UpToDate,
+            merge("feature", &MergeOptions::default())?
+        );
+        repo.switch("feature")?;
+        assert_eq!(
+            MergeOutcome::FastForward(hash),
+            merge("master", &MergeOptions::default())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_union() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file(ATTRIBUTES_FILE_NAME, "CHANGELOG merge=union\n")?
+            .file("CHANGELOG", "v1\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("CHANGELOG", "v1\nmaster change\n")?
+            .stage(".")?
+            .commit("Log master change")?
+            .switch("feature")?
+            .file("CHANGELOG", "v1\nfeature change\n")?
+            .stage(".")?
+            .commit("Log feature change")?
+            .switch("master")?;
+
+        let MergeOutcome::Merged(_) = merge("feature", &MergeOptions::default())? else {
+            panic!("expected a merge commit");
+        };
+        assert_eq!(
+            "v1\nmaster change\nfeature change\n",
+            fs::read_to_string(repo.path().join("CHANGELOG"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_no_ff() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .switch("feature")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Add b")?;
+        let feature = revparse::resolve("feature")?;
+        repo.switch("master")?;
+        let master = revparse::resolve("master")?;
+
+        let options = MergeOptions {
+            no_ff: true,
+            ..MergeOptions::default()
+        };
+        let MergeOutcome::Merged(hash) = merge("feature", &options)? else {
+            panic!("expected a merge commit");
+        };
+        let commit = Commit::load(&hash)?;
+        assert_eq!(&[master, feature], commit.parent_hashes());
+        assert_eq!(Commit::load(&feature)?.tree_hash(), commit.tree_hash());
+        assert_eq!(hash, revparse::resolve("master")?);
+        assert_eq!("b\n", fs::read_to_string(repo.path().join("b.txt"))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_squash() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("c.txt", "c\n")?
+            .stage(".")?
+            .commit("Add c on master")?;
+        let master = revparse::resolve("master")?;
+        repo.switch("feature")?
+            .file("a.txt", "a\nfeature\n")?
+            .stage(".")?
+            .commit("Change a")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Add b")?;
+        repo.switch("master")?;
+
+        let options = MergeOptions {
+            squash: true,
+            ..MergeOptions::default()
+        };
+        assert_eq!(MergeOutcome::Squashed, merge("feature", &options)?);
+        assert_eq!(master, revparse::resolve("master")?);
+        assert_eq!(None, Operation::in_progress());
+
+        let indexed: Vec<_> = Index::load()?
+            .files()
+            .iter()
+            .map(|f| f.path().to_path_buf())
+            .collect();
+        assert_eq!(
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("b.txt"),
+                PathBuf::from("c.txt")
+            ],
+            indexed
+        );
+        assert_eq!(
+            "a\nfeature\n",
+            fs::read_to_string(repo.path().join("a.txt"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_conflict_and_abort() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "master\n")?
+            .stage(".")?
+            .commit("Change a on master")?;
+        repo.switch("feature")?
+            .file("a.txt", "feature\n")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Change a on feature")?;
+        repo.switch("master")?;
+        let master = revparse::resolve("master")?;
+
+        let error = merge("feature", &MergeOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("Conflicts in: a.txt"));
+        assert_eq!(Some(Operation::Merge), Operation::in_progress());
+        assert_eq!(
+            "<<<<<<< HEAD\nmaster\n=======\nfeature\n>>>>>>> feature\n",
+            fs::read_to_string(repo.path().join("a.txt"))?
+        );
+        assert!(repo.path().join("b.txt").exists());
+        assert!(merge("feature", &MergeOptions::default()).is_err());
+
+        abort()?;
+        assert_eq!(None, Operation::in_progress());
+        assert_eq!(master, revparse::resolve("master")?);
+        assert_eq!("master\n", fs::read_to_string(repo.path().join("a.txt"))?);
+        assert!(!repo.path().join("b.txt").exists());
+        let indexed: Vec<_> = Index::load()?
+            .files()
+            .iter()
+            .map(|f| f.path().to_path_buf())
+            .collect();
+        assert_eq!(vec![PathBuf::from("a.txt")], indexed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_conflict_stages() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "master\n")?
+            .stage(".")?
+            .commit("Change a on master")?
+            .switch("feature")?
+            .file("a.txt", "feature\n")?
+            .stage(".")?
+            .commit("Change a on feature")?
+            .switch("master")?;
+        let blob = |revision: &str| -> Result<Hash> {
+            let tree = Commit::load(&revparse::resolve(revision)?)?.tree()?;
+            Ok(tree.files()[&PathBuf::from("a.txt")])
+        };
+
+        assert!(merge("feature", &MergeOptions::default()).is_err());
+        let stages: Vec<_> = Index::load()?
+            .files()
+            .iter()
+            .map(|f| (f.path().to_path_buf(), f.stage(), *f.hash()))
+            .collect();
+        let path = PathBuf::from("a.txt");
+        assert_eq!(
+            vec![
+                (path.clone(), 1, blob("master~")?),
+                (path.clone(), 2, blob("master")?),
+                (path.clone(), 3, blob("feature")?),
+            ],
+            stages
+        );
+
+        let error = repo.commit("Merge").err().unwrap();
+        assert!(format!("{error:#}").contains("A merge is in progress"));
+
+        repo.file("a.txt", "master and feature\n")?.stage("a.txt")?;
+        let error = repo.commit("Merge").err().unwrap();
+        assert!(format!("{error:#}").contains("rygit merge --continue"));
+        let hash = continue_merge()?;
+        assert_eq!(2, Commit::load(&hash)?.parent_hashes().len());
+        let index = Index::load()?;
+        assert_eq!(1, index.files().len());
+        assert_eq!(0, index.files()[0].stage());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_continue() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "master\n")?
+            .stage(".")?
+            .commit("Change a on master")?;
+        repo.switch("feature")?
+            .file("a.txt", "feature\n")?
+            .stage(".")?
+            .commit("Change a on feature")?;
+        let feature = revparse::resolve("feature")?;
+        repo.switch("master")?;
+        let master = revparse::resolve("master")?;
+
+        assert!(merge("feature", &MergeOptions::default()).is_err());
+        let error = continue_merge().unwrap_err();
+        assert!(format!("{error:#}").contains("Unresolved conflicts in: a.txt"));
+        repo.stage("a.txt")?;
+        let error = continue_merge().unwrap_err();
+        assert!(format!("{error:#}").contains("Conflict markers remain in: a.txt"));
+
+        repo.file("a.txt", "master and feature\n")?.stage(".")?;
+        let hash = continue_merge()?;
+        let commit = Commit::load(&hash)?;
+        assert_eq!(&[master, feature], commit.parent_hashes());
+        assert_eq!("Merge branch 'feature'", commit.message());
+        assert_eq!(hash, revparse::resolve("master")?);
+        assert_eq!(None, Operation::in_progress());
+
+        Ok(())
+    }
+}
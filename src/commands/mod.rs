@@ -1,6 +1,36 @@
 pub mod add;
+pub mod bisect;
 pub mod branch;
+pub mod bundle;
+pub mod checkout_index;
+pub mod clone;
 pub mod commit;
+pub mod commit_tree;
+pub mod config;
+pub mod count_objects;
+pub mod debug;
+pub mod describe;
+pub mod diff;
+pub mod fetch;
+pub mod grep;
 pub mod init;
 pub mod log;
+pub mod ls_files;
+pub mod merge;
+pub mod notes;
+pub mod pack_refs;
+pub mod push;
+pub mod read_tree;
+pub mod rebase;
+pub mod remote;
+pub mod repack;
+pub mod reset;
+pub mod restore;
+pub mod rev_list;
+pub mod sparse_checkout;
 pub mod status;
+pub mod tag;
+pub mod update_index;
+pub mod verify_pack;
+pub mod worktree;
+pub mod write_tree;
@@ -0,0 +1,18 @@
+use anyhow::{Context, Result, bail};
+
+use crate::{commands::commit::identity, notes::Notes, revparse};
+
+pub fn add(revision: &str, message: &str, force: bool) -> Result<()> {
+    let commit = revparse::resolve(revision).context("Unable to add note")?;
+    Notes::load()?.add(&commit, message, identity()?, force)
+}
+
+pub fn show(revision: &str) -> Result<()> {
+    let commit = revparse::resolve(revision).context("Unable to show note")?;
+    let Some(note) = Notes::load()?.get(&commit)? else {
+        bail!("No note found for {commit}");
+    };
+    print!("{note}");
+
+    Ok(())
+}
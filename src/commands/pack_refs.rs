@@ -0,0 +1,15 @@
+use anyhow::Result;
+
+use crate::{output::info, refs::pack_refs};
+
+/// Moves the loose branch, tag and remote-tracking refs into `packed-refs`.
+pub fn run() -> Result<()> {
+    let count = pack_refs()?;
+    if count == 0 {
+        info!("Nothing to pack");
+    } else {
+        info!("Packed {count} refs");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use anyhow::Result;
+
+use crate::{branch::Branch, output::info, remote::Remote};
+
+pub fn run(remote_name: &str, force: bool) -> Result<()> {
+    let remote = Remote::find(remote_name)?;
+    let branch = Branch::current()?;
+    let pushed = remote.push(&branch, force)?;
+
+    info!("To {}", remote.url().display());
+    match pushed.old_hash {
+        None => info!(" * [new branch]  {} -> {}", pushed.branch, pushed.branch),
+        Some(old_hash) if old_hash == pushed.new_hash => info!("Everything up-to-date"),
+        Some(old_hash) => info!(
+            " {} {}{}{}  {} -> {}",
+            if force { "+" } else { " " },
+            &old_hash.to_hex()[..7],
+            if force { "..." } else { ".." },
+            &pushed.new_hash.to_hex()[..7],
+            pushed.branch,
+            pushed.branch
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    hash::Hash,
+    index::Index,
+    objects::{AnyObject, load_any, tree::Tree},
+    revparse,
+};
+
+/// Replaces the index with the files of `tree_ish`, a tree or a commit. The
+/// working tree is left alone.
+pub fn run(tree_ish: &str) -> Result<()> {
+    let tree = resolve_tree(tree_ish).context("Unable to read tree")?;
+    Index::load()?
+        .reset_to(&tree)
+        .context("Unable to read tree. Unable to update index")
+}
+
+/// Resolves a full tree or commit hash, or a revision naming a commit.
+fn resolve_tree(tree_ish: &str) -> Result<Tree> {
+    let hash = match Hash::from_hex(tree_ish) {
+        Ok(hash) => hash,
+        Err(_) => revparse::resolve(tree_ish)?,
+    };
+    match load_any(&hash)? {
+        AnyObject::Tree(tree) => Ok(tree),
+        AnyObject::Commit(commit) => commit.tree(),
+        AnyObject::Blob(_) => bail!("{tree_ish} is a blob, not a tree"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{objects::commit::Commit, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_read_tree() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("subdir/b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?
+            .remove_file("subdir/b.txt")?
+            .file("c.txt", "c")?
+            .stage(".")?;
+        let tree = Commit::load(&revparse::resolve("HEAD")?)?.tree()?;
+        let index_files = || -> Result<Vec<_>> {
+            Ok(Index::load()?
+                .files()
+                .iter()
+                .map(|file| (file.path().to_path_buf(), *file.hash()))
+                .collect())
+        };
+        let tree_files: Vec<_> = tree.files().into_iter().collect();
+        assert_ne!(tree_files, index_files()?);
+
+        run("HEAD")?;
+        assert_eq!(tree_files, index_files()?);
+        assert_eq!("changed", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        Index::load()?.clear()?;
+        run(&tree.hash().to_hex())?;
+        assert_eq!(2, Index::load()?.files().len());
+
+        Ok(())
+    }
+}
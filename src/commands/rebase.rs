@@ -0,0 +1,263 @@
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    branch::Branch,
+    commands::commit::identity,
+    hash::Hash,
+    index::Index,
+    merge::merge_trees,
+    objects::{commit::Commit, tree::Tree},
+    operation::{Operation, RebaseState, ensure_resolved},
+    output::info,
+    paths::ensure_work_tree,
+    rev_list::walk,
+    revparse, worktree,
+};
+
+pub fn run(upstream: &str) -> Result<()> {
+    let branch_name = Branch::current()?.name().to_string();
+    match rebase(upstream)? {
+        Some(_) => info!("Successfully rebased and updated refs/heads/{branch_name}."),
+        None => info!("Current branch {branch_name} is up to date."),
+    }
+
+    Ok(())
+}
+
+/// Replays the current branch's commits that aren't in `upstream` on top of
+/// it, oldest first, then moves the branch to the last replayed commit and
+/// checks it out. Merge commits are dropped, as are commits whose changes
+/// are already upstream. Returns the new tip, or `None` when the branch
+/// already contains `upstream`.
+///
+/// Each commit is applied with a three-way merge against its parent. If one
+/// conflicts the rebase stops with the conflicted files checked out and its
+/// progress saved, leaving the branch where it was until the rebase is
+/// continued or aborted.
+pub fn rebase(upstream: &str) -> Result<Option<Hash>> {
+    ensure_work_tree().context("Unable to rebase")?;
+    Operation::ensure_none().context("Unable to rebase")?;
+    let branch = Branch::current().context("Unable to rebase")?;
+    let head = branch.tip().context("Unable to rebase")?;
+    let upstream_hash = revparse::resolve(upstream).context("Unable to rebase")?;
+    if Commit::is_ancestor(&upstream_hash, &head)? {
+        return Ok(None);
+    }
+
+    let mut todo = walk(&[head], &[upstream_hash])?;
+    todo.reverse();
+    let state = RebaseState {
+        branch: branch.name().to_string(),
+        orig_head: head,
+        onto: upstream_hash,
+        current: None,
+        todo,
+    };
+
+    replay(state).map(Some)
+}
+
+/// Resumes a conflicted rebase once the conflicts are resolved and staged.
+/// The index is committed in place of the commit that conflicted, unless it
+/// ended up with no changes, and the remaining commits are replayed.
+pub fn continue_rebase() -> Result<Hash> {
+    let mut state = RebaseState::load().context("Unable to continue. No rebase in progress")?;
+    let tree = Tree::from_index(&Index::load()?)?;
+    ensure_resolved(&tree).context("Unable to continue rebase")?;
+
+    if let Some(current) = state.current.take() {
+        let onto_tree_hash = *Commit::load(&state.onto)?.tree_hash();
+        if *tree.hash() != onto_tree_hash {
+            let commit = Commit::load(&current)?;
+            let replayed = Commit::create_from_tree(
+                *tree.hash(),
+                vec![state.onto],
+                commit.message(),
+                commit.author().clone(),
+                identity()?,
+            )?;
+            state.onto = *replayed.hash();
+        }
+    }
+
+    replay(state)
+}
+
+/// Abandons a conflicted rebase, restoring the branch, index and working tree
+/// to how they were before it started.
+pub fn abort() -> Result<()> {
+    let state = RebaseState::load().context("Unable to abort. No rebase in progress")?;
+    let mut branch = Branch::find_by_name(&state.branch)?;
+    branch.update(state.orig_head)?;
+    worktree::checkout(&Commit::load(&state.orig_head)?.tree()?)?;
+
+    RebaseState::clear()
+}
+
+fn replay(mut state: RebaseState) -> Result<Hash> {
+    while !state.todo.is_empty() {
+        let hash = state.todo.remove(0);
+        let commit = Commit::load(&hash)?;
+        if commit.parent_hashes().len() > 1 {
+            continue;
+        }
+
+        let base = commit.parents()?.first().map(|p| p.tree()).transpose()?;
+        let ours = Commit::load(&state.onto)?.tree()?;
+        let theirs = commit.tree()?;
+        let short_hash = &hash.to_hex()[..7];
+        let theirs_label = format!("{short_hash} ({})", commit.subject());
+        let merge = merge_trees(base.as_ref(), &ours, &theirs, "HEAD", &theirs_label)?;
+        let tree = Tree::from_paths(&merge.files)?;
+        if !merge.is_clean() {
+            state.current = Some(hash);
+            state.save()?;
+            worktree::checkout(&tree)?;
+
+            let conflicts: Vec<_> = merge
+                .conflicts
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            bail!(
+                "Could not apply {short_hash}... {}. Conflicts in: {}. Resolve them and run \"rygit rebase --continue\", or run \"rygit rebase --abort\"",
+                commit.subject(),
+                conflicts.join(", ")
+            );
+        }
+
+        if tree.hash() == ours.hash() {
+            continue;
+        }
+        let replayed = Commit::create_from_tree(
+            *tree.hash(),
+            vec![state.onto],
+            commit.message(),
+            commit.author().clone(),
+            identity()?,
+        )?;
+        state.onto = *replayed.hash();
+    }
+
+    let mut branch = Branch::find_by_name(&state.branch)?;
+    branch.update(state.onto)?;
+    worktree::checkout(&Commit::load(&state.onto)?.tree()?)?;
+    RebaseState::clear()?;
+
+    Ok(state.onto)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        rev_list::{Limits, rev_list},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_rebase() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "a\nmaster\n")?
+            .stage(".")?
+            .commit("Advance master")?;
+        let master = revparse::resolve("master")?;
+
+        repo.switch("feature")?
+            .file("b.txt", "b\n")?
+            .stage(".")?
+            .commit("Add b")?
+            .file("c.txt", "c\n")?
+            .stage(".")?
+            .commit("Add c")?;
+        let old_feature = revparse::resolve("feature")?;
+
+        let new_feature = rebase("master")?.unwrap();
+        assert_ne!(old_feature, new_feature);
+        assert_eq!(new_feature, revparse::resolve("feature")?);
+        assert_eq!(master, revparse::resolve("feature~2")?);
+        assert_eq!(2, rev_list("master..feature", &Limits::default())?.len());
+        assert_eq!(
+            vec!["Add c", "Add b", "Advance master", "Initial commit"],
+            rev_list("feature", &Limits::default())?
+                .iter()
+                .map(|h| Ok(Commit::load(h)?.subject().to_string()))
+                .collect::<Result<Vec<_>>>()?
+        );
+
+        let files: Vec<_> = Commit::load(&new_feature)?
+            .tree()?
+            .files()
+            .into_keys()
+            .collect();
+        assert_eq!(
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("b.txt"),
+                PathBuf::from("c.txt")
+            ],
+            files
+        );
+        assert_eq!(
+            "a\nmaster\n",
+            fs::read_to_string(repo.path().join("a.txt"))?
+        );
+        assert_eq!("feature", Branch::current()?.name());
+
+        assert_eq!(None, rebase("master")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rebase_conflict_and_abort() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a\n")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "master\n")?
+            .stage(".")?
+            .commit("Change a on master")?;
+        repo.switch("feature")?
+            .file("a.txt", "feature\n")?
+            .stage(".")?
+            .commit("Change a on feature")?;
+        let feature = revparse::resolve("feature")?;
+
+        let error = rebase("master").unwrap_err();
+        assert!(error.to_string().contains("Conflicts in: a.txt"));
+        assert_eq!(feature, revparse::resolve("feature")?);
+        assert!(fs::read_to_string(repo.path().join("a.txt"))?.contains("<<<<<<< HEAD"));
+        assert_eq!(Some(Operation::Rebase), Operation::in_progress());
+        assert!(rebase("master").is_err());
+
+        repo.file("a.txt", "master and feature\n")?.stage(".")?;
+        continue_rebase()?;
+        assert_eq!(None, Operation::in_progress());
+        assert_eq!(revparse::resolve("master")?, revparse::resolve("feature~")?);
+        assert_eq!(
+            "Change a on feature",
+            Commit::load(&revparse::resolve("feature")?)?.subject()
+        );
+
+        Branch::find_by_name("feature")?.update(feature)?;
+        repo.switch("feature")?;
+        assert!(rebase("master").is_err());
+        abort()?;
+        assert_eq!(None, Operation::in_progress());
+        assert_eq!(feature, revparse::resolve("feature")?);
+        assert_eq!("feature\n", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+
+use crate::remote::Remote;
+
+pub fn add(name: &str, url: &str) -> Result<()> {
+    Remote::add(name, url)?;
+
+    Ok(())
+}
+
+pub fn list(verbose: bool) -> Result<()> {
+    for remote in Remote::list()? {
+        if verbose {
+            println!("{}\t{}", remote.name(), remote.url().display());
+        } else {
+            println!("{}", remote.name());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use anyhow::{Context, Result};
+
+use crate::{hash::Hash, objects::loose_object_paths, output::info, pack::write_pack};
+
+/// Copies every loose object into a new pack. Loose objects are left in place.
+pub fn run() -> Result<()> {
+    let hashes: Vec<_> = loose_object_paths()
+        .context("Unable to repack. Unable to list loose objects")?
+        .iter()
+        .map(Hash::from_object_path)
+        .collect::<Result<_, _>>()?;
+    if hashes.is_empty() {
+        info!("Nothing to pack");
+        return Ok(());
+    }
+
+    let index_path = write_pack(&hashes)?;
+    info!(
+        "Packed {} objects into {}",
+        hashes.len(),
+        index_path.with_extension("pack").display()
+    );
+
+    Ok(())
+}
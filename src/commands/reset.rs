@@ -0,0 +1,88 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{index::Index, objects::commit::Commit, output::info, paths::ensure_work_tree, refs};
+
+/// Unstages `paths`: their index entries go back to the versions committed
+/// at HEAD, or are removed if HEAD doesn't have them. The working tree is
+/// left alone, so this undoes `add` for those paths.
+pub fn run(paths: &[PathBuf]) -> Result<()> {
+    ensure_work_tree().context("Unable to reset")?;
+    let tree_files = match refs::resolve("HEAD").context("Unable to reset")? {
+        Some(head) => Commit::load(&head)
+            .and_then(|commit| commit.tree())
+            .context("Unable to reset")?
+            .files(),
+        None => BTreeMap::new(),
+    };
+    let mut index = Index::load().context("Unable to reset")?;
+    let reset = index.reset_paths(&tree_files, paths)?;
+    if !reset.is_empty() {
+        info!("Unstaged changes after reset:");
+        for path in reset {
+            info!("  {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    fn indexed_paths() -> Result<Vec<PathBuf>> {
+        Ok(Index::load()?
+            .files()
+            .iter()
+            .map(|f| f.path().to_path_buf())
+            .collect())
+    }
+
+    #[test]
+    fn test_reset_unstages_path() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let head = refs::resolve("HEAD")?.unwrap();
+        let committed = Commit::load(&head)?.tree()?.files();
+        repo.file("a.txt", "changed")?
+            .file("b.txt", "b")?
+            .stage(".")?;
+
+        run(&[repo.path().join("b.txt")])?;
+        assert_eq!(vec![PathBuf::from("a.txt")], indexed_paths()?);
+        assert_eq!("b", fs::read_to_string(repo.path().join("b.txt"))?);
+
+        run(&[repo.path().join("a.txt")])?;
+        let index = Index::load()?;
+        assert_eq!(committed[&PathBuf::from("a.txt")], *index.files()[0].hash());
+        assert_eq!("changed", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        assert!(run(&[repo.path().join("missing.txt")]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_without_commits() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("dir/b.txt", "b")?
+            .stage(".")?;
+
+        run(&[repo.path().join("dir")])?;
+        assert_eq!(vec![PathBuf::from("a.txt")], indexed_paths()?);
+        assert!(repo.path().join("dir/b.txt").exists());
+
+        Ok(())
+    }
+}
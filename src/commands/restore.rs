@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::{objects::commit::Commit, output::info, paths::ensure_work_tree, revparse, worktree};
+
+/// Restores `paths` from the `source` revision into the working tree and the
+/// index.
+pub fn run(source: &str, paths: &[PathBuf]) -> Result<()> {
+    ensure_work_tree().context("Unable to restore")?;
+    let hash = revparse::resolve(source).context("Unable to restore")?;
+    let tree = Commit::load(&hash)?.tree()?;
+    let restored = worktree::restore(&tree, paths)?;
+    info!(
+        "Restored {} file{} from {source}",
+        restored.len(),
+        if restored.len() == 1 { "" } else { "s" }
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{index::Index, objects::blob::Blob, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_restore_from_branch() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .file("dir/c.txt", "c")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("other")?
+            .switch("other")?
+            .file("a.txt", "other a")?
+            .file("b.txt", "other b")?
+            .file("dir/c.txt", "other c")?
+            .file("dir/d.txt", "other d")?
+            .stage(".")?
+            .commit("Change everything")?
+            .switch("master")?;
+
+        run("other", &[PathBuf::from("a.txt")])?;
+        assert_eq!("other a", fs::read_to_string(repo.path().join("a.txt"))?);
+        assert_eq!("b", fs::read_to_string(repo.path().join("b.txt"))?);
+        assert_eq!("c", fs::read_to_string(repo.path().join("dir/c.txt"))?);
+        assert!(!repo.path().join("dir/d.txt").exists());
+        let index = Index::load()?;
+        let staged_a = index.files().iter().find(|f| f.path() == "a.txt").unwrap();
+        assert_eq!(b"other a".to_vec(), Blob::load(staged_a.hash()).body()?);
+
+        run("other", &[repo.path().join("dir")])?;
+        assert_eq!(
+            "other c",
+            fs::read_to_string(repo.path().join("dir/c.txt"))?
+        );
+        assert_eq!(
+            "other d",
+            fs::read_to_string(repo.path().join("dir/d.txt"))?
+        );
+        assert_eq!("b", fs::read_to_string(repo.path().join("b.txt"))?);
+
+        assert!(run("other", &[PathBuf::from("missing.txt")]).is_err());
+
+        Ok(())
+    }
+}
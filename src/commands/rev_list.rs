@@ -0,0 +1,16 @@
+use anyhow::Result;
+
+use crate::rev_list::{Limits, rev_list};
+
+pub fn run(spec: &str, count: bool, limits: &Limits) -> Result<()> {
+    let hashes = rev_list(spec, limits)?;
+    if count {
+        println!("{}", hashes.len());
+    } else {
+        for hash in hashes {
+            println!("{hash}");
+        }
+    }
+
+    Ok(())
+}
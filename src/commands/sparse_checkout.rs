@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    index::Index,
+    objects::blob::Blob,
+    output::info,
+    paths::{ensure_work_tree, repository_root_path},
+    sparse::SparseCheckout,
+    worktree,
+};
+
+pub fn run_set(patterns: &[String]) -> Result<()> {
+    let (written, removed) = set(patterns)?;
+    info!("Sparse checkout updated: {written} files written, {removed} removed");
+
+    Ok(())
+}
+
+/// Limits the working tree to the files matching `patterns`. Files that no
+/// longer match are removed, unless they have changes that would be lost,
+/// and matching files missing from the working tree are written from the
+/// index. Returns how many files were written and removed.
+pub fn set(patterns: &[String]) -> Result<(usize, usize)> {
+    ensure_work_tree().context("Unable to set sparse checkout")?;
+    let sparse = SparseCheckout::parse(&patterns.join("\n"));
+    let repository_path = repository_root_path();
+    let index = Index::load().context("Unable to set sparse checkout")?;
+
+    let mut to_write = BTreeMap::new();
+    let mut to_remove = vec![];
+    for file in index.files().iter().filter(|file| file.stage() == 0) {
+        let path = repository_path.join(file.path());
+        if sparse.includes(file.path()) {
+            if !path.exists() {
+                to_write.insert(file.path().to_path_buf(), *file.hash());
+            }
+        } else if path.is_file() {
+            if Blob::hash_for(&path)? != *file.hash() {
+                bail!(
+                    "Unable to set sparse checkout. {} has local changes. Commit or restore them first",
+                    file.path().display()
+                );
+            }
+            to_remove.push(path);
+        }
+    }
+
+    SparseCheckout::set(patterns)?;
+    for path in &to_remove {
+        fs::remove_file(path)
+            .with_context(|| format!("Unable to remove file {}", path.display()))?;
+        // Drop directories the removal emptied, stopping at the first one
+        // that still holds something
+        for dir in path.ancestors().skip(1) {
+            if dir == repository_path || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+    worktree::write_files(&to_write)?;
+
+    Ok((to_write.len(), to_remove.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        branch::Branch,
+        repository_status::{RepositoryStatus, UntrackedMode},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    fn assert_clean() -> Result<()> {
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        assert!(status.staged_changes().is_empty());
+        assert!(status.unstaged_changes().is_empty());
+        assert!(status.untracked_files().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_switch() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("docs/b.md", "b")?
+            .file("src/c.rs", "c")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("other")?
+            .switch("other")?
+            .file("docs/d.md", "d")?
+            .file("src/e.rs", "e")?
+            .stage(".")?
+            .commit("Second commit")?
+            .switch("master")?;
+
+        assert_eq!((0, 2), set(&["docs/".to_string()])?);
+        assert!(!repo.path().join("a.txt").exists());
+        assert!(!repo.path().join("src").exists());
+        assert_eq!("b", fs::read_to_string(repo.path().join("docs/b.md"))?);
+        assert_clean()?;
+
+        Branch::switch("other")?;
+        assert!(repo.path().join("docs/b.md").is_file());
+        assert!(repo.path().join("docs/d.md").is_file());
+        assert!(!repo.path().join("a.txt").exists());
+        assert!(!repo.path().join("src").exists());
+        assert_eq!(5, Index::load()?.files().len());
+        assert_clean()?;
+
+        // Staging everything keeps the files left out of the working tree
+        repo.stage(".")?;
+        assert_eq!(5, Index::load()?.files().len());
+        assert_clean()?;
+
+        assert_eq!((2, 0), set(&["docs".to_string(), "*.rs".to_string()])?);
+        assert_eq!("e", fs::read_to_string(repo.path().join("src/e.rs"))?);
+        assert!(!repo.path().join("a.txt").exists());
+        assert_clean()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_keeps_local_changes() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("docs/b.md", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?;
+
+        assert!(set(&["docs".to_string()]).is_err());
+        assert_eq!("changed", fs::read_to_string(repo.path().join("a.txt"))?);
+        assert!(SparseCheckout::load()?.is_none());
+
+        Ok(())
+    }
+}
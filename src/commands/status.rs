@@ -1,15 +1,35 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use crate::{
     branch::Branch,
-    paths::repository_root_path,
-    repository_status::{RepositoryStatus, StatusEntry},
+    output::OutputFormat,
+    paths::{ensure_work_tree, repository_root_path},
+    refs,
+    repository_status::{RepositoryStatus, StatusEntry, UntrackedMode},
+    rev_list::walk,
 };
 
-pub fn run() -> Result<()> {
-    let status = RepositoryStatus::load()?;
-    let current_branch = Branch::current()?;
-    println!("On branch {}", current_branch.name());
+pub fn run(untracked_mode: UntrackedMode, format: OutputFormat) -> Result<()> {
+    ensure_work_tree()?;
+    let status = RepositoryStatus::load(untracked_mode)?;
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status).context("Unable to serialize status")?
+        );
+        return Ok(());
+    }
+
+    println!("{}", head_line()?);
+    if Branch::current_name()?.is_some() {
+        let current_branch = Branch::current()?;
+        if current_branch.commit_hash().is_none() {
+            println!("\nNo commits yet\n");
+        }
+        if let Some(tracking_summary) = tracking_summary(&current_branch)? {
+            println!("{tracking_summary}");
+        }
+    }
 
     println!("Changes to be committed:");
     for staged_change in status.staged_changes() {
@@ -21,13 +41,86 @@ pub fn run() -> Result<()> {
         print_status_entry(unstaged_change)?;
     }
 
+    let untracked_files = untracked_file_lines(&status)?;
+    if !untracked_files.is_empty() {
+        println!("Untracked files:");
+        for untracked_file in untracked_files {
+            println!("\t{untracked_file}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Where HEAD is: the current branch, or the commit it's detached at.
+fn head_line() -> Result<String> {
+    match Branch::current_name()? {
+        Some(name) => Ok(format!("On branch {name}")),
+        None => {
+            let head = refs::resolve("HEAD")?.context("Unable to read HEAD")?;
+            Ok(format!("HEAD detached at {}", &head.to_hex()[..7]))
+        }
+    }
+}
+
+/// The untracked entries as shown by status, relative to the repository
+/// root. Untracked directories end with a `/`.
+fn untracked_file_lines(status: &RepositoryStatus) -> Result<Vec<String>> {
     let repository_root = repository_root_path();
+    let mut lines = vec![];
     for untracked_file in status.untracked_files() {
         let relative_path = untracked_file.strip_prefix(&repository_root)?.display();
-        println!("\t{relative_path}");
+        if untracked_file.is_dir() {
+            lines.push(format!("{relative_path}/"));
+        } else {
+            lines.push(relative_path.to_string());
+        }
     }
 
-    Ok(())
+    Ok(lines)
+}
+
+/// Describes how the branch compares to its upstream, counting the commits
+/// each side has that the other doesn't.
+fn tracking_summary(branch: &Branch) -> Result<Option<String>> {
+    let Some(upstream) = branch.upstream()? else {
+        return Ok(None);
+    };
+    let Some(upstream_branch) = Branch::list_remotes()?
+        .into_iter()
+        .find(|b| b.name() == upstream)
+    else {
+        return Ok(Some(format!(
+            "Your branch is based on '{upstream}', but the upstream is gone."
+        )));
+    };
+
+    let Some(&local) = branch.commit_hash() else {
+        return Ok(None);
+    };
+    let remote = upstream_branch.tip()?;
+    let ahead = walk(&[local], &[remote])?.len();
+    let behind = walk(&[remote], &[local])?.len();
+    let summary = match (ahead, behind) {
+        (0, 0) => format!("Your branch is up to date with '{upstream}'."),
+        (ahead, 0) => format!(
+            "Your branch is ahead of '{upstream}' by {ahead} {}.",
+            pluralize_commits(ahead)
+        ),
+        (0, behind) => format!(
+            "Your branch is behind '{upstream}' by {behind} {}, and can be fast-forwarded.",
+            pluralize_commits(behind)
+        ),
+        (ahead, behind) => format!(
+            "Your branch and '{upstream}' have diverged,\nand have {ahead} and {behind} different commits each, respectively."
+        ),
+    };
+
+    Ok(Some(summary))
+}
+
+fn pluralize_commits(count: usize) -> &'static str {
+    if count == 1 { "commit" } else { "commits" }
 }
 
 fn print_status_entry(status_entry: &StatusEntry) -> Result<()> {
@@ -38,3 +131,133 @@ fn print_status_entry(status_entry: &StatusEntry) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{remote::Remote, repository_status::FileStatus, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_status_json() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage("a.txt")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?
+            .file("b.txt", "b")?
+            .file("c.txt", "c")?
+            .stage("c.txt")?;
+
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        let json = serde_json::to_string(&status)?;
+        let deserialized: RepositoryStatus = serde_json::from_str(&json)?;
+        assert_eq!(status, deserialized);
+        assert_eq!(
+            vec![StatusEntry {
+                path: repo.path().join("c.txt"),
+                status: FileStatus::Added,
+            }],
+            deserialized.staged_changes()
+        );
+        assert_eq!(
+            vec![StatusEntry {
+                path: repo.path().join("a.txt"),
+                status: FileStatus::Modified,
+            }],
+            deserialized.unstaged_changes()
+        );
+        assert_eq!(
+            vec![repo.path().join("b.txt")],
+            deserialized.untracked_files()
+        );
+        assert!(json.contains("\"status\":\"added\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_line() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        assert_eq!("On branch master", head_line()?);
+
+        let head = refs::resolve("HEAD")?.unwrap();
+        Branch::switch_detached(&head)?;
+        assert_eq!(
+            format!("HEAD detached at {}", &head.to_hex()[..7]),
+            head_line()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_untracked_file_lines() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .file("dir/one.txt", "1")?
+            .file("dir/two.txt", "2")?
+            .file("dir/nested/three.txt", "3")?;
+
+        assert_eq!(
+            vec!["b.txt".to_string(), "dir/".to_string()],
+            untracked_file_lines(&RepositoryStatus::load(UntrackedMode::Normal)?)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracking_summary() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+
+        let repo = TestRepo::new()?;
+        let remote = Remote::add("origin", origin.path())?;
+        remote.fetch()?;
+        repo.switch(Branch::track("origin/master", Some("local".to_string()))?.name())?;
+        assert_eq!(
+            Some("Your branch is up to date with 'origin/master'.".to_string()),
+            tracking_summary(&Branch::current()?)?
+        );
+
+        repo.file("b.txt", "b")?
+            .stage(".")?
+            .commit("Local commit")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Another local commit")?;
+        assert_eq!(
+            Some("Your branch is ahead of 'origin/master' by 2 commits.".to_string()),
+            tracking_summary(&Branch::current()?)?
+        );
+
+        origin
+            .enter()?
+            .file("d.txt", "d")?
+            .stage(".")?
+            .commit("Remote commit")?;
+        repo.enter()?;
+        remote.fetch()?;
+        assert_eq!(
+            Some(
+                "Your branch and 'origin/master' have diverged,\nand have 2 and 1 different commits each, respectively."
+                    .to_string()
+            ),
+            tracking_summary(&Branch::current()?)?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use anyhow::Result;
+
+use crate::tag::Tag;
+
+pub fn create(name: &str, revision: Option<&str>) -> Result<()> {
+    Tag::create(name, revision)?;
+
+    Ok(())
+}
+
+pub fn list() -> Result<()> {
+    for tag in Tag::list()? {
+        println!("{}", tag.name());
+    }
+
+    Ok(())
+}
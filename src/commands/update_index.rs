@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::{index::Index, paths::ensure_work_tree};
+
+/// Sets or clears the assume-unchanged flag of each of `paths`. Status and
+/// add skip flagged files, so local changes to them are ignored.
+pub fn run(paths: &[PathBuf], assume_unchanged: bool) -> Result<()> {
+    ensure_work_tree().context("Unable to update index")?;
+    let mut index = Index::load().context("Unable to update index")?;
+    for path in paths {
+        index.set_assume_unchanged(path, assume_unchanged)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        repository_status::{FileStatus, RepositoryStatus, StatusEntry, UntrackedMode},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_assume_unchanged() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let staged = *Index::load()?.files()[0].hash();
+
+        run(&[repo.path().join("a.txt")], true)?;
+        assert!(Index::load()?.files()[0].assume_unchanged());
+        repo.file("a.txt", "changed")?.stage(".")?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        assert!(status.unstaged_changes().is_empty());
+        assert!(status.staged_changes().is_empty());
+        assert_eq!(staged, *Index::load()?.files()[0].hash());
+        assert_eq!("changed", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        run(&[repo.path().join("a.txt")], false)?;
+        assert_eq!(
+            vec![StatusEntry {
+                path: repo.path().join("a.txt"),
+                status: FileStatus::Modified,
+            }],
+            RepositoryStatus::load(UntrackedMode::Normal)?.unstaged_changes()
+        );
+
+        assert!(run(&[repo.path().join("missing.txt")], true).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::pack::verify_pack;
+
+pub fn run(index_path: impl AsRef<Path>) -> Result<()> {
+    let index_path = index_path.as_ref();
+    let entries = verify_pack(index_path)
+        .with_context(|| format!("Unable to verify pack {}", index_path.display()))?;
+
+    for entry in entries.iter() {
+        println!(
+            "{} {:<6} {} {} {}",
+            entry.hash, entry.kind, entry.size, entry.packed_size, entry.offset
+        );
+    }
+    println!("{}: ok", index_path.with_extension("pack").display());
+
+    Ok(())
+}
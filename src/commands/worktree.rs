@@ -0,0 +1,157 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    branch::Branch,
+    objects::commit::Commit,
+    output::info,
+    paths::{
+        LINKED_RYGIT_DIR_PREFIX, WORKTREES_DIR, common_path, reset_repository_root_path,
+        rygit_dir_override, set_rygit_dir,
+    },
+    worktree,
+};
+
+pub fn run_add(path: &Path, branch: &str) -> Result<()> {
+    add(path, branch)?;
+    info!("Preparing worktree (checking out '{branch}')");
+
+    Ok(())
+}
+
+/// Creates a work tree at `path` with `branch` checked out, linked to this
+/// repository. It shares the repository's objects, refs and config, but
+/// keeps its own HEAD and index in `worktrees/<name>` of the repository's
+/// metadata, so different branches can be checked out side by side.
+pub fn add(path: &Path, branch: &str) -> Result<()> {
+    let branch = Branch::find_by_name(branch).context("Unable to add work tree")?;
+    if branch.is_checked_out_elsewhere()?
+        || Branch::current_name()?.as_deref() == Some(branch.name())
+    {
+        bail!(
+            "Unable to add work tree. {} is already checked out in another work tree",
+            branch.name()
+        );
+    }
+    let tip = branch.tip().context("Unable to add work tree")?;
+    if path.exists() && fs::read_dir(path)?.next().is_some() {
+        bail!(
+            "Unable to add work tree. {} already exists and is not empty",
+            path.display()
+        );
+    }
+    let name = path
+        .file_name()
+        .with_context(|| format!("Unable to add work tree. Invalid path {}", path.display()))?;
+    let common_path = common_path();
+    let rygit_dir = common_path.join(WORKTREES_DIR).join(name);
+    if rygit_dir.exists() {
+        bail!(
+            "Unable to add work tree. A work tree named {} already exists",
+            name.display()
+        );
+    }
+
+    fs::create_dir_all(path).context("Unable to add work tree. Unable to create directory")?;
+    let path = path.canonicalize()?;
+    fs::create_dir_all(&rygit_dir)
+        .and_then(|_| {
+            fs::write(
+                rygit_dir.join("HEAD"),
+                format!("ref: refs/heads/{}", branch.name()),
+            )
+        })
+        .and_then(|_| fs::write(rygit_dir.join("index"), ""))
+        .and_then(|_| {
+            fs::write(
+                rygit_dir.join("commondir"),
+                common_path.display().to_string(),
+            )
+        })
+        .and_then(|_| {
+            fs::write(
+                path.join(".rygit"),
+                format!("{LINKED_RYGIT_DIR_PREFIX}{}\n", rygit_dir.display()),
+            )
+        })
+        .context("Unable to add work tree. Unable to write its metadata")?;
+
+    // Checking out fills the new work tree's index, so it's done from there
+    let original_dir = env::current_dir()?;
+    let original_rygit_dir = rygit_dir_override();
+    env::set_current_dir(&path)?;
+    reset_repository_root_path();
+    let checkout = Commit::load(&tip)
+        .and_then(|commit| commit.tree())
+        .and_then(|tree| worktree::checkout(&tree));
+    env::set_current_dir(original_dir)?;
+    reset_repository_root_path();
+    if let Some(rygit_dir) = original_rygit_dir {
+        set_rygit_dir(rygit_dir);
+    }
+
+    checkout.context("Unable to add work tree")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{
+        commands::{self, commit::CommitOptions},
+        objects::locate,
+        paths::objects_path,
+        progress::NoProgress,
+        refs::{read_ref, resolve},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_add() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?
+            .file("a.txt", "master")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let master = resolve("HEAD")?.unwrap();
+        let dir = TempDir::new()?;
+        let linked = dir.path().join("feature");
+
+        add(&linked, "feature")?;
+
+        assert_eq!("a", fs::read_to_string(linked.join("a.txt"))?);
+        assert_eq!("master", fs::read_to_string(repo.path().join("a.txt"))?);
+        assert!(linked.join(".rygit").is_file());
+        assert!(add(&dir.path().join("other"), "feature").is_err());
+        assert!(add(&dir.path().join("other"), "master").is_err());
+        assert!(Branch::switch("feature").is_err());
+
+        env::set_current_dir(&linked)?;
+        reset_repository_root_path();
+        assert_eq!(Some("feature".to_string()), Branch::current_name()?);
+        fs::write(linked.join("b.txt"), "b")?;
+        commands::add::run(linked.canonicalize()?.join("b.txt"), &mut NoProgress)?;
+        let options = CommitOptions {
+            ..CommitOptions::default()
+        };
+        commands::commit::run("Feature commit", options)?;
+        let feature = resolve("HEAD")?.unwrap();
+        let linked_objects = objects_path();
+
+        repo.enter()?;
+        assert_eq!(linked_objects, objects_path());
+        assert!(locate(&feature)?.is_some());
+        assert_eq!(Some(feature), read_ref("refs/heads/feature")?);
+        assert_eq!(Some(master), resolve("HEAD")?);
+        assert!(!repo.path().join("b.txt").exists());
+
+        Ok(())
+    }
+}
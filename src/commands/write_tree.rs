@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+
+use crate::{hash::Hash, index::Index, objects::tree::Tree};
+
+/// Stores the trees for the index and prints the root tree's hash.
+pub fn run() -> Result<()> {
+    println!("{}", write_tree()?);
+
+    Ok(())
+}
+
+fn write_tree() -> Result<Hash> {
+    let index = Index::load().context("Unable to write tree")?;
+    let tree = Tree::from_index(&index).context("Unable to write tree")?;
+
+    Ok(*tree.hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{objects::commit::Commit, revparse, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_write_tree() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("subdir/b.txt", "b")?
+            .stage(".")?;
+
+        let hash = write_tree()?;
+        assert!(revparse::resolve("HEAD").is_err());
+
+        repo.commit("Initial commit")?;
+        let commit = Commit::load(&revparse::resolve("HEAD")?)?;
+        assert_eq!(commit.tree_hash(), &hash);
+
+        Ok(())
+    }
+}
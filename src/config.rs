@@ -0,0 +1,542 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::paths::{config_path, global_config_path};
+
+// config format:
+// [<section>]
+//     <name> = <value>
+// [<section> "<subsection>"]
+//     <name> = <value>
+//
+// Keys are addressed as <section>.<name> or <section>.<subsection>.<name>.
+// Section and variable names are case-insensitive; subsections are not.
+//
+// An `include.path` entry pulls in the entries of another config file at
+// that point. A relative path is relative to the including file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ConfigEntry {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+    value: String,
+    /// Read from an included file, so it isn't saved with this one.
+    included: bool,
+}
+
+impl ConfigEntry {
+    fn key(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!("{}.{}.{}", self.section, subsection, self.name),
+            None => format!("{}.{}", self.section, self.name),
+        }
+    }
+
+    fn matches(&self, key: &ConfigKey) -> bool {
+        self.section == key.section && self.subsection == key.subsection && self.name == key.name
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ConfigKey {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+}
+
+impl ConfigKey {
+    fn parse(key: &str) -> Result<Self> {
+        let (section, rest) = key
+            .split_once('.')
+            .with_context(|| format!("Invalid config key \"{key}\". Expected section.name"))?;
+        let (subsection, name) = match rest.rsplit_once('.') {
+            Some((subsection, name)) => (Some(subsection.to_string()), name),
+            None => (None, rest),
+        };
+        if section.is_empty() || name.is_empty() {
+            bail!("Invalid config key \"{key}\". Expected section.name");
+        }
+
+        Ok(Self {
+            section: section.to_lowercase(),
+            subsection,
+            name: name.to_lowercase(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    entries: Vec<ConfigEntry>,
+    /// Entries of the global config, which `entries` override. They're
+    /// neither changed by [`Config::set`] nor saved.
+    inherited: Vec<ConfigEntry>,
+}
+
+impl Config {
+    /// Loads the repository's config layered over the global one.
+    pub fn load() -> Result<Self> {
+        Config::load_layered(global_config_path().as_deref(), config_path())
+    }
+
+    /// Loads the global config on its own, for changing it.
+    pub fn load_global() -> Result<Self> {
+        let path =
+            global_config_path().context("Unable to locate the global config. HOME is not set")?;
+        Config::load_from(path)
+    }
+
+    /// Loads the config at `path` layered over the one at `global`, if any.
+    /// Keys set in both take their value from `path`.
+    pub fn load_layered(global: Option<&Path>, path: impl AsRef<Path>) -> Result<Self> {
+        let mut config = Config::load_from(path)?;
+        if let Some(global) = global {
+            config.inherited = Config::load_from(global)?.entries;
+        }
+
+        Ok(config)
+    }
+
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Self {
+                path,
+                entries: vec![],
+                inherited: vec![],
+            });
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read config file {}", path.display()))?;
+        let entries = parse(&contents)
+            .with_context(|| format!("Unable to parse config file {}", path.display()))?;
+        let canonical_path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let entries = resolve_includes(entries, &mut vec![canonical_path])?;
+
+        Ok(Self {
+            path,
+            entries,
+            inherited: vec![],
+        })
+    }
+
+    /// Returns the value of `key`. When a key is set more than once the last
+    /// value wins, and the repository's config wins over the global one.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = ConfigKey::parse(key).ok()?;
+        self.inherited
+            .iter()
+            .chain(self.entries.iter())
+            .rev()
+            .find(|e| e.matches(&key))
+            .map(|e| e.value.as_str())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" | "" => Ok(Some(false)),
+            _ => bail!("Invalid boolean value \"{value}\" for config key {key}"),
+        }
+    }
+
+    /// Sets `key` to `value`, replacing every existing value for the key.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) -> Result<()> {
+        let key = ConfigKey::parse(key)?;
+        let value = value.into();
+        let position = self.entries.iter().position(|e| e.matches(&key));
+        self.entries.retain(|e| !e.matches(&key));
+        let entry = ConfigEntry {
+            section: key.section,
+            subsection: key.subsection,
+            name: key.name,
+            value,
+            included: false,
+        };
+
+        // Keep the entry alongside the rest of its section
+        let position = position.or_else(|| {
+            self.entries
+                .iter()
+                .rposition(|e| e.section == entry.section && e.subsection == entry.subsection)
+                .map(|p| p + 1)
+        });
+        match position {
+            Some(position) => self.entries.insert(position.min(self.entries.len()), entry),
+            None => self.entries.push(entry),
+        }
+
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        let key = ConfigKey::parse(key)?;
+        self.entries.retain(|e| !e.matches(&key));
+
+        Ok(())
+    }
+
+    /// Lists every key and value in file order, global ones first, keys in
+    /// their canonical `section.subsection.name` form.
+    pub fn entries(&self) -> Vec<(String, &str)> {
+        self.inherited
+            .iter()
+            .chain(self.entries.iter())
+            .map(|e| (e.key(), e.value.as_str()))
+            .collect()
+    }
+
+    /// Lists the distinct subsections of `section`, e.g. the remote names
+    /// under `[remote "<name>"]`.
+    pub fn subsections(&self, section: &str) -> Vec<String> {
+        let section = section.to_lowercase();
+        let mut subsections = vec![];
+        let entries = self.inherited.iter().chain(self.entries.iter());
+        for entry in entries.filter(|e| e.section == section) {
+            if let Some(subsection) = &entry.subsection
+                && !subsections.contains(subsection)
+            {
+                subsections.push(subsection.clone());
+            }
+        }
+
+        subsections
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, serialize(&self.entries))
+            .with_context(|| format!("Unable to write config file {}", self.path.display()))
+    }
+}
+
+fn parse(contents: &str) -> Result<Vec<ConfigEntry>> {
+    let mut entries = vec![];
+    let mut section: Option<(String, Option<String>)> = None;
+    for (line_number, line) in contents.lines().enumerate() {
+        let line_number = line_number + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .split_once(']')
+                .with_context(|| format!("Invalid section header on line {line_number}"))?
+                .0
+                .trim();
+            section = Some(
+                parse_section_header(header)
+                    .with_context(|| format!("Invalid section header on line {line_number}"))?,
+            );
+            continue;
+        }
+
+        let Some((section, subsection)) = &section else {
+            bail!("Variable outside of a section on line {line_number}");
+        };
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), parse_value(value.trim())?),
+            // A bare variable name is shorthand for true
+            None => (line, "true".to_string()),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            bail!("Invalid variable name \"{name}\" on line {line_number}");
+        }
+
+        entries.push(ConfigEntry {
+            section: section.clone(),
+            subsection: subsection.clone(),
+            name: name.to_lowercase(),
+            value,
+            included: false,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Follows the `include.path` entries of the config file at the top of
+/// `stack`, placing each included file's entries after the directive.
+/// Missing files are skipped; a file that includes itself, directly or not,
+/// is an error.
+fn resolve_includes(
+    entries: Vec<ConfigEntry>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Vec<ConfigEntry>> {
+    let including_path = stack.last().unwrap().clone();
+    let mut resolved = vec![];
+    for entry in entries {
+        let include =
+            entry.section == "include" && entry.subsection.is_none() && entry.name == "path";
+        let value = entry.value.clone();
+        resolved.push(entry);
+        if !include {
+            continue;
+        }
+
+        let include_path = match value.strip_prefix("~/") {
+            Some(relative) => env::var_os("HOME")
+                .map(PathBuf::from)
+                .context("Unable to include config. HOME is not set")?
+                .join(relative),
+            None => including_path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(&value),
+        };
+        let Ok(include_path) = include_path.canonicalize() else {
+            continue;
+        };
+        if stack.contains(&include_path) {
+            bail!(
+                "Unable to include config file {}. It is already included by {}",
+                include_path.display(),
+                including_path.display()
+            );
+        }
+
+        let contents = fs::read_to_string(&include_path)
+            .with_context(|| format!("Unable to read config file {}", include_path.display()))?;
+        let included = parse(&contents)
+            .with_context(|| format!("Unable to parse config file {}", include_path.display()))?;
+        stack.push(include_path);
+        let included = resolve_includes(included, stack)?;
+        stack.pop();
+        resolved.extend(included.into_iter().map(|entry| ConfigEntry {
+            included: true,
+            ..entry
+        }));
+    }
+
+    Ok(resolved)
+}
+
+fn parse_section_header(header: &str) -> Result<(String, Option<String>)> {
+    match header.split_once(char::is_whitespace) {
+        Some((section, subsection)) => {
+            let subsection = subsection
+                .trim()
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .context("Subsection names must be quoted")?;
+            Ok((
+                section.to_lowercase(),
+                Some(subsection.replace("\\\"", "\"")),
+            ))
+        }
+        None => Ok((header.to_lowercase(), None)),
+    }
+}
+
+fn parse_value(raw: &str) -> Result<String> {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c @ ('"' | '\\')) => value.push(c),
+                _ => bail!("Invalid escape sequence in value \"{raw}\""),
+            },
+            c => value.push(c),
+        }
+    }
+    if in_quotes {
+        bail!("Unterminated quote in value \"{raw}\"");
+    }
+
+    Ok(value.trim_end().to_string())
+}
+
+fn serialize(entries: &[ConfigEntry]) -> String {
+    let mut contents = String::new();
+    let mut current_section: Option<(&str, Option<&str>)> = None;
+    for entry in entries.iter().filter(|e| !e.included) {
+        let section = (entry.section.as_str(), entry.subsection.as_deref());
+        if current_section != Some(section) {
+            match section {
+                (section, Some(subsection)) => contents.push_str(&format!(
+                    "[{section} \"{}\"]\n",
+                    subsection.replace('"', "\\\"")
+                )),
+                (section, None) => contents.push_str(&format!("[{section}]\n")),
+            }
+            current_section = Some(section);
+        }
+        contents.push_str(&format!(
+            "\t{} = {}\n",
+            entry.name,
+            serialize_value(&entry.value)
+        ));
+    }
+
+    contents
+}
+
+fn serialize_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    if needs_quotes {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let entries = parse(
+            "# comment\n[core]\n\tbare = false\n\tfilemode\n[remote \"origin\"]\n\turl = \"/tmp/a b\" ; trailing\n",
+        )?;
+
+        let keys: Vec<_> = entries
+            .iter()
+            .map(|e| (e.key(), e.value.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("core.bare".to_string(), "false"),
+                ("core.filemode".to_string(), "true"),
+                ("remote.origin.url".to_string(), "/tmp/a b"),
+            ],
+            keys
+        );
+        assert!(parse("name = outside").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_and_save() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        let mut config = Config::load()?;
+        assert_eq!(None, config.get("user.name"));
+
+        config.set("user.name", "Larry Sellers")?;
+        config.set("remote.origin.url", "/tmp/origin")?;
+        config.set("user.email", "l.sellers@example.com")?;
+        config.set("User.Name", "Donny Kerabatsos")?;
+        config.save()?;
+
+        let config = Config::load()?;
+        assert_eq!(Some("Donny Kerabatsos"), config.get("user.name"));
+        assert_eq!(Some("/tmp/origin"), config.get("remote.origin.url"));
+        assert_eq!(vec!["origin".to_string()], config.subsections("remote"));
+        assert_eq!(
+            "[user]\n\tname = Donny Kerabatsos\n\temail = l.sellers@example.com\n[remote \"origin\"]\n\turl = /tmp/origin\n",
+            fs::read_to_string(config_path())?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_global_config() -> Result<()> {
+        let dir = TempDir::new()?;
+        let global_path = dir.path().join(".rygitconfig");
+        let local_path = dir.path().join("config");
+        fs::write(
+            &global_path,
+            "[user]\n\tname = Larry Sellers\n\temail = l.sellers@example.com\n",
+        )?;
+        fs::write(&local_path, "[user]\n\temail = larry@work.example.com\n")?;
+
+        let mut config = Config::load_layered(Some(&global_path), &local_path)?;
+        assert_eq!(Some("Larry Sellers"), config.get("user.name"));
+        assert_eq!(Some("larry@work.example.com"), config.get("user.email"));
+        assert_eq!(3, config.entries().len());
+
+        config.set("core.bare", "false")?;
+        config.save()?;
+        assert_eq!(
+            "[user]\n\temail = larry@work.example.com\n[core]\n\tbare = false\n",
+            fs::read_to_string(&local_path)?
+        );
+        assert_eq!(
+            None,
+            Config::load_layered(None, &local_path)?.get("user.name")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> Result<()> {
+        let dir = TempDir::new()?;
+        let config_path = dir.path().join("config");
+        fs::create_dir(dir.path().join("shared"))?;
+        fs::write(
+            &config_path,
+            "[user]\n\tname = Larry Sellers\n[include]\n\tpath = shared/identity\n",
+        )?;
+        fs::write(
+            dir.path().join("shared/identity"),
+            "[user]\n\temail = l.sellers@example.com\n[include]\n\tpath = missing\n",
+        )?;
+
+        let mut config = Config::load_from(&config_path)?;
+        assert_eq!(Some("Larry Sellers"), config.get("user.name"));
+        assert_eq!(Some("l.sellers@example.com"), config.get("user.email"));
+
+        // Included entries stay in their own file
+        config.set("core.bare", "false")?;
+        config.save()?;
+        assert_eq!(
+            "[user]\n\tname = Larry Sellers\n[include]\n\tpath = shared/identity\n[core]\n\tbare = false\n",
+            fs::read_to_string(&config_path)?
+        );
+
+        fs::write(
+            dir.path().join("shared/identity"),
+            "[include]\n\tpath = ../config\n",
+        )?;
+        let error = Config::load_from(&config_path).unwrap_err();
+        assert!(format!("{error:#}").contains("already included"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_bool() -> Result<()> {
+        let mut config = Config::load_from("/nonexistent/config")?;
+        config.set("core.bare", "yes")?;
+        assert_eq!(Some(true), config.get_bool("core.bare")?);
+        config.set("core.bare", "off")?;
+        assert_eq!(Some(false), config.get_bool("core.bare")?);
+        config.set("core.bare", "maybe")?;
+        assert!(config.get_bool("core.bare").is_err());
+        assert_eq!(None, config.get_bool("core.missing")?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,656 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+
+use crate::{
+    hash::Hash,
+    objects::{blob::Blob, is_text, tree::TreeChange},
+};
+
+pub const DEFAULT_SIMILARITY_THRESHOLD: u8 = 50;
+
+/// Controls similarity-based pairing of changes. Thresholds are percentages
+/// of matching lines; `None` disables that kind of detection.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenameOptions {
+    pub rename_threshold: Option<u8>,
+    pub copy_threshold: Option<u8>,
+}
+
+/// Rewrites a tree diff so that a deleted file and an added file that are at
+/// least `rename_threshold` similar become a single rename, and an added file
+/// at least `copy_threshold` similar to a modified or deleted file's original
+/// content becomes a copy.
+pub fn detect_renames(changes: Vec<TreeChange>, options: RenameOptions) -> Result<Vec<TreeChange>> {
+    if options.rename_threshold.is_none() && options.copy_threshold.is_none() {
+        return Ok(changes);
+    }
+
+    let mut bodies: HashMap<Hash, Vec<u8>> = HashMap::new();
+    let mut body = |hash: &Hash| -> Result<Vec<u8>> {
+        if let Some(body) = bodies.get(hash) {
+            return Ok(body.clone());
+        }
+        let body = Blob::load(hash).body()?;
+        bodies.insert(*hash, body.clone());
+        Ok(body)
+    };
+
+    let mut changes: Vec<_> = changes.into_iter().map(Some).collect();
+    let added: Vec<_> = indexes_where(&changes, |c| matches!(c, TreeChange::Added { .. }));
+    let deleted: Vec<_> = indexes_where(&changes, |c| matches!(c, TreeChange::Deleted { .. }));
+
+    let mut renamed = vec![];
+    if let Some(threshold) = options.rename_threshold {
+        let mut candidates = vec![];
+        for &added_index in added.iter() {
+            let (_, added_hash) = path_and_hash(changes[added_index].as_ref().unwrap());
+            for &deleted_index in deleted.iter() {
+                let (_, deleted_hash) = path_and_hash(changes[deleted_index].as_ref().unwrap());
+                let score = similarity(&body(&deleted_hash)?, &body(&added_hash)?);
+                if score >= threshold {
+                    candidates.push((score, added_index, deleted_index));
+                }
+            }
+        }
+
+        // Pair the most similar files first so each file is used at most once
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        let mut used_deleted = vec![];
+        for (score, added_index, deleted_index) in candidates {
+            if changes[added_index].is_none() || used_deleted.contains(&deleted_index) {
+                continue;
+            }
+
+            let (old_path, old_hash) = path_and_hash(changes[deleted_index].as_ref().unwrap());
+            let (new_path, new_hash) = path_and_hash(changes[added_index].as_ref().unwrap());
+            used_deleted.push(deleted_index);
+            changes[added_index] = None;
+            renamed.push(TreeChange::Renamed {
+                old_path,
+                new_path,
+                old_hash,
+                new_hash,
+                similarity: score,
+            });
+        }
+        for deleted_index in used_deleted {
+            changes[deleted_index] = None;
+        }
+    }
+
+    let mut copied = vec![];
+    if let Some(threshold) = options.copy_threshold {
+        let mut sources = vec![];
+        for change in changes.iter().flatten().chain(renamed.iter()) {
+            match change {
+                TreeChange::Modified { path, old_hash, .. }
+                | TreeChange::Deleted {
+                    path,
+                    hash: old_hash,
+                } => sources.push((path.clone(), *old_hash)),
+                TreeChange::Renamed {
+                    old_path, old_hash, ..
+                } => sources.push((old_path.clone(), *old_hash)),
+                _ => {}
+            }
+        }
+
+        for &added_index in added.iter() {
+            let Some(change) = changes[added_index].as_ref() else {
+                continue;
+            };
+            let (new_path, new_hash) = path_and_hash(change);
+            let mut best: Option<(u8, usize)> = None;
+            for (source_index, (_, source_hash)) in sources.iter().enumerate() {
+                let score = similarity(&body(source_hash)?, &body(&new_hash)?);
+                if score >= threshold && best.is_none_or(|(best_score, _)| score > best_score) {
+                    best = Some((score, source_index));
+                }
+            }
+
+            if let Some((score, source_index)) = best {
+                let (old_path, old_hash) = sources[source_index].clone();
+                changes[added_index] = None;
+                copied.push(TreeChange::Copied {
+                    old_path,
+                    new_path,
+                    old_hash,
+                    new_hash,
+                    similarity: score,
+                });
+            }
+        }
+    }
+
+    let mut changes: Vec<_> = changes
+        .into_iter()
+        .flatten()
+        .chain(renamed)
+        .chain(copied)
+        .collect();
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+
+    Ok(changes)
+}
+
+/// One step of an edit script turning an old sequence into a new one, by
+/// index into the old and/or new sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Computes a shortest edit script between two sequences with Myers'
+/// algorithm. Edits come out in order; deletions precede insertions at the
+/// same position.
+pub fn myers_diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    let offset = max;
+    let index = |k: isize| (k + offset) as usize;
+
+    // v[k] holds the furthest x reached on diagonal k = x - y
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = vec![];
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Equal(x as usize - 1, y as usize - 1));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                edits.push(Edit::Insert(y as usize - 1));
+            } else {
+                edits.push(Edit::Delete(x as usize - 1));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.reverse();
+
+    edits
+}
+
+/// Splits content into lines, keeping each line's terminator so a missing
+/// final newline counts as a change.
+pub fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&c| c == b'\n').collect()
+}
+
+/// Lines of unchanged context shown around each change in a patch.
+const PATCH_CONTEXT_LINES: usize = 3;
+
+/// Renders the change to one file as a unified diff. `None` stands for a
+/// side where the file doesn't exist. Binary files are reported as differing
+/// without their contents.
+pub fn unified_diff(path: &Path, old: Option<&[u8]>, new: Option<&[u8]>) -> String {
+    unified_diff_paths(path, path, old, new)
+}
+
+/// Like [`unified_diff`], for content that lives at different paths on each
+/// side.
+pub fn unified_diff_paths(
+    old_path: &Path,
+    new_path: &Path,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+) -> String {
+    let (old_path, new_path) = (old_path.display(), new_path.display());
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{old_path}"));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{new_path}"));
+    let old = old.unwrap_or_default();
+    let new = new.unwrap_or_default();
+
+    let mut patch = format!("diff --git a/{old_path} b/{new_path}\n");
+    if old == new {
+        return patch;
+    }
+    if !is_text(old) || !is_text(new) {
+        patch.push_str(&format!("Binary files {old_name} and {new_name} differ\n"));
+        return patch;
+    }
+    patch.push_str(&format!("--- {old_name}\n+++ {new_name}\n"));
+
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = myers_diff(&old_lines, &new_lines);
+
+    // Where each edit sits in the old and new files
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_position, mut new_position) = (0, 0);
+    for edit in &edits {
+        positions.push((old_position, new_position));
+        match edit {
+            Edit::Equal(..) => {
+                old_position += 1;
+                new_position += 1;
+            }
+            Edit::Delete(_) => old_position += 1,
+            Edit::Insert(_) => new_position += 1,
+        }
+    }
+    positions.push((old_position, new_position));
+
+    // Group changes closer than twice the context into the same hunk
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for (i, _) in edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Equal(..)))
+    {
+        let start = i.saturating_sub(PATCH_CONTEXT_LINES);
+        let end = (i + 1 + PATCH_CONTEXT_LINES).min(edits.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        patch.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        for edit in &edits[start..end] {
+            let (prefix, line) = match *edit {
+                Edit::Equal(o, _) => (' ', old_lines[o]),
+                Edit::Delete(o) => ('-', old_lines[o]),
+                Edit::Insert(n) => ('+', new_lines[n]),
+            };
+            patch.push(prefix);
+            patch.push_str(&String::from_utf8_lossy(line));
+            if !line.ends_with(b"\n") {
+                patch.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    patch
+}
+
+/// Formats a hunk header range. Ranges are 1-based, an empty range names the
+/// line before it and a count of one is left out.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{count}", start + 1),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    /// Counts the lines added and removed between two versions of a file.
+    /// Binary files count as changed without any lines.
+    pub fn of(old: &[u8], new: &[u8]) -> Self {
+        let mut stat = Self {
+            files_changed: 1,
+            ..Self::default()
+        };
+        if !is_text(old) || !is_text(new) {
+            return stat;
+        }
+        for edit in myers_diff(&split_lines(old), &split_lines(new)) {
+            match edit {
+                Edit::Insert(_) => stat.insertions += 1,
+                Edit::Delete(_) => stat.deletions += 1,
+                Edit::Equal(..) => {}
+            }
+        }
+
+        stat
+    }
+
+    /// Totals the line counts for a set of tree changes.
+    pub fn for_changes(changes: &[TreeChange]) -> Result<Self> {
+        let mut total = Self::default();
+        for change in changes {
+            let (old_hash, new_hash) = match change {
+                TreeChange::Added { hash, .. } => (None, Some(hash)),
+                TreeChange::Deleted { hash, .. } => (Some(hash), None),
+                TreeChange::Modified {
+                    old_hash, new_hash, ..
+                }
+                | TreeChange::Renamed {
+                    old_hash, new_hash, ..
+                }
+                | TreeChange::Copied {
+                    old_hash, new_hash, ..
+                } => (Some(old_hash), Some(new_hash)),
+            };
+            let old = old_hash.map(|h| Blob::load(h).body()).transpose()?;
+            let new = new_hash.map(|h| Blob::load(h).body()).transpose()?;
+            total += DiffStat::of(
+                old.as_deref().unwrap_or_default(),
+                new.as_deref().unwrap_or_default(),
+            );
+        }
+
+        Ok(total)
+    }
+}
+
+impl std::ops::AddAssign for DiffStat {
+    fn add_assign(&mut self, other: Self) {
+        self.files_changed += other.files_changed;
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+    }
+}
+
+impl std::fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = |count: usize, one: &str, many: &str| {
+            format!("{count} {}", if count == 1 { one } else { many })
+        };
+        write!(f, "{} changed", plural(self.files_changed, "file", "files"))?;
+        if self.insertions > 0 {
+            write!(
+                f,
+                ", {}(+)",
+                plural(self.insertions, "insertion", "insertions")
+            )?;
+        }
+        if self.deletions > 0 {
+            write!(
+                f,
+                ", {}(-)",
+                plural(self.deletions, "deletion", "deletions")
+            )?;
+        }
+
+        std::fmt::Result::Ok(())
+    }
+}
+
+/// Scores how alike two contents are as the percentage of lines they share,
+/// relative to the longer of the two.
+pub fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    if old == new {
+        return 100;
+    }
+
+    let old_lines: Vec<_> = old.split(|&c| c == b'\n').collect();
+    let new_lines: Vec<_> = new.split(|&c| c == b'\n').collect();
+    let mut remaining: HashMap<&[u8], usize> = HashMap::new();
+    for line in old_lines.iter() {
+        *remaining.entry(line).or_default() += 1;
+    }
+
+    let mut matching = 0;
+    for line in new_lines.iter() {
+        if let Some(count) = remaining.get_mut(line)
+            && *count > 0
+        {
+            *count -= 1;
+            matching += 1;
+        }
+    }
+
+    let longest = old_lines.len().max(new_lines.len());
+    (matching * 100 / longest) as u8
+}
+
+fn indexes_where(
+    changes: &[Option<TreeChange>],
+    predicate: impl Fn(&TreeChange) -> bool,
+) -> Vec<usize> {
+    changes
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.as_ref().is_some_and(&predicate))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn path_and_hash(change: &TreeChange) -> (std::path::PathBuf, Hash) {
+    match change {
+        TreeChange::Added { path, hash } | TreeChange::Deleted { path, hash } => {
+            (path.clone(), *hash)
+        }
+        _ => unreachable!("only additions and deletions are paired"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{objects::tree::Tree, test_utils::TestRepo};
+
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(100, similarity(b"a\nb\n", b"a\nb\n"));
+        assert_eq!(0, similarity(b"a", b"b"));
+        assert_eq!(50, similarity(b"a\nb\nc\nd", b"a\nb\nx\ny"));
+    }
+
+    #[test]
+    fn test_myers_diff() {
+        let old: Vec<_> = "ABCABBA".chars().collect();
+        let new: Vec<_> = "CBABAC".chars().collect();
+        let edits = myers_diff(&old, &new);
+        let deletions = edits
+            .iter()
+            .filter(|e| matches!(e, Edit::Delete(_)))
+            .count();
+        let insertions = edits
+            .iter()
+            .filter(|e| matches!(e, Edit::Insert(_)))
+            .count();
+        assert_eq!(5, deletions + insertions);
+
+        // Replaying the script must rebuild the new sequence
+        let mut rebuilt = vec![];
+        for edit in edits {
+            match edit {
+                Edit::Equal(old_index, _) => rebuilt.push(old[old_index]),
+                Edit::Insert(new_index) => rebuilt.push(new[new_index]),
+                Edit::Delete(_) => {}
+            }
+        }
+        assert_eq!(new, rebuilt);
+
+        assert!(myers_diff::<char>(&[], &[]).is_empty());
+        assert_eq!(vec![Edit::Insert(0)], myers_diff(&[], &['a']));
+    }
+
+    #[test]
+    fn test_diff_stat() {
+        let stat = DiffStat::of(b"a\nb\nc\n", b"a\nB\nc\nd\n");
+        assert_eq!(2, stat.insertions);
+        assert_eq!(1, stat.deletions);
+        assert_eq!(
+            "1 file changed, 2 insertions(+), 1 deletion(-)",
+            stat.to_string()
+        );
+        assert_eq!(
+            "1 file changed, 1 insertion(+)",
+            DiffStat::of(b"", b"a").to_string()
+        );
+    }
+
+    #[test]
+    fn test_detect_renames() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let original = numbered_lines(10);
+        repo.file("old.txt", &original)?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let old_tree = Tree::current()?.unwrap();
+
+        let edited = original.replace("line 3\n", "line three\n");
+        repo.remove_file("old.txt")?
+            .file("new.txt", &edited)?
+            .stage(".")?
+            .commit("Rename")?;
+        let new_tree = Tree::current()?.unwrap();
+
+        let options = RenameOptions {
+            rename_threshold: Some(DEFAULT_SIMILARITY_THRESHOLD),
+            copy_threshold: None,
+        };
+        let changes = detect_renames(old_tree.diff(&new_tree), options)?;
+        assert_eq!(1, changes.len());
+        match &changes[0] {
+            TreeChange::Renamed {
+                old_path,
+                new_path,
+                similarity,
+                ..
+            } => {
+                assert_eq!(&PathBuf::from("old.txt"), old_path);
+                assert_eq!(&PathBuf::from("new.txt"), new_path);
+                assert!(*similarity >= 80);
+            }
+            change => panic!("Expected rename but got {change:?}"),
+        }
+
+        let options = RenameOptions {
+            rename_threshold: Some(95),
+            copy_threshold: None,
+        };
+        let changes = detect_renames(old_tree.diff(&new_tree), options)?;
+        assert_eq!(2, changes.len());
+        assert!(matches!(&changes[0], TreeChange::Added { .. }));
+        assert!(matches!(&changes[1], TreeChange::Deleted { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_copies() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let original = numbered_lines(10);
+        repo.file("a.txt", &original)?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let old_tree = Tree::current()?.unwrap();
+
+        repo.file("a.txt", &original.replace("line 10\n", "line ten\n"))?
+            .file("b.txt", &original)?
+            .stage(".")?
+            .commit("Copy")?;
+        let new_tree = Tree::current()?.unwrap();
+
+        let options = RenameOptions {
+            rename_threshold: Some(DEFAULT_SIMILARITY_THRESHOLD),
+            copy_threshold: Some(DEFAULT_SIMILARITY_THRESHOLD),
+        };
+        let changes = detect_renames(old_tree.diff(&new_tree), options)?;
+        assert_eq!(2, changes.len());
+        assert!(matches!(&changes[0], TreeChange::Modified { .. }));
+        match &changes[1] {
+            TreeChange::Copied {
+                old_path,
+                similarity,
+                ..
+            } => {
+                assert_eq!(&PathBuf::from("a.txt"), old_path);
+                assert_eq!(100, *similarity);
+            }
+            change => panic!("Expected copy but got {change:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = numbered_lines(10);
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 10\n", "");
+        assert_eq!(
+            "diff --git a/a.txt b/a.txt\n\
+             --- a/a.txt\n\
+             +++ b/a.txt\n\
+             @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+             @@ -7,4 +7,3 @@\n line 7\n line 8\n line 9\n-line 10\n",
+            unified_diff(
+                Path::new("a.txt"),
+                Some(old.as_bytes()),
+                Some(new.as_bytes())
+            )
+        );
+
+        assert_eq!(
+            "diff --git a/b.txt b/b.txt\n--- /dev/null\n+++ b/b.txt\n@@ -0,0 +1 @@\n+b\n\\ No newline at end of file\n",
+            unified_diff(Path::new("b.txt"), None, Some(b"b"))
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_binary() {
+        assert_eq!(
+            "diff --git a/image.png b/image.png\nBinary files a/image.png and b/image.png differ\n",
+            unified_diff(
+                Path::new("image.png"),
+                Some(b"\x89PNG\0\x01"),
+                Some(b"\x89PNG\0\x02")
+            )
+        );
+        assert_eq!(
+            DiffStat {
+                files_changed: 1,
+                insertions: 0,
+                deletions: 0
+            },
+            DiffStat::of(b"\0a\n", b"\0b\n")
+        );
+    }
+}
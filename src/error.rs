@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::{hash::Hash, objects::ObjectKind};
+
+/// Failures callers may want to tell apart. Everything else is reported as a
+/// plain `anyhow` error; these can be recovered from one with
+/// `downcast_ref::<RygitError>()`, even under added context.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RygitError {
+    #[error("Not in a rygit repository (or any parent directories)")]
+    NotARepository,
+    #[error("This operation must be run in a work tree, not a bare repository")]
+    BareRepository,
+    #[error("{} is not part of this repository", .0.display())]
+    OutsideRepository(PathBuf),
+    #[error("Object {0} not found")]
+    ObjectNotFound(Hash),
+    #[error("Object {hash} is a {actual}, not a {expected}")]
+    UnexpectedObjectKind {
+        hash: Hash,
+        expected: ObjectKind,
+        actual: ObjectKind,
+    },
+    #[error("Unknown revision \"{0}\"")]
+    UnknownRevision(String),
+    #[error(
+        "Unable to lock {}. Another rygit process is running in this repository. If not, remove the lock file",
+        .0.display()
+    )]
+    Locked(PathBuf),
+}
+
+impl RygitError {
+    /// The process exit code for this failure. Like git, being outside a
+    /// usable repository exits with 128. A bad revision exits with 2, a
+    /// missing or mistyped object with 3 and a held lock with 4, so scripts
+    /// can tell them apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RygitError::NotARepository
+            | RygitError::BareRepository
+            | RygitError::OutsideRepository(_) => 128,
+            RygitError::UnknownRevision(_) => 2,
+            RygitError::ObjectNotFound(_) | RygitError::UnexpectedObjectKind { .. } => 3,
+            RygitError::Locked(_) => 4,
+        }
+    }
+}
+
+/// The process exit code for an error: the code of the [`RygitError`] behind
+/// it, or 1 for anything else.
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<RygitError>()
+        .map_or(1, RygitError::exit_code)
+}
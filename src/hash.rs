@@ -1,10 +1,12 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use sha1::{Digest, Sha1};
 
 use crate::paths::objects_path;
 
+const HASH_HEX_LENGTH: usize = 40;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Hash([u8; 20]);
 
@@ -21,11 +23,20 @@ impl Hash {
         hex::encode(self.0)
     }
 
+    /// Parses a full 40 character hex hash. Uppercase digits are accepted.
     pub fn from_hex(hex: &str) -> Result<Self> {
-        let bytes = hex::decode(hex).with_context(|| format!("Invalid hex string: {hex}"))?;
-        if bytes.len() != 20 {
-            return Err(anyhow::anyhow!("Hash must be exactly 20 bytes"));
+        if hex.len() != HASH_HEX_LENGTH {
+            bail!(
+                "Invalid hash \"{hex}\". Expected {HASH_HEX_LENGTH} hex characters, got {}",
+                hex.chars().count()
+            );
+        }
+        if let Some(c) = hex.chars().find(|c| !c.is_ascii_hexdigit()) {
+            bail!("Invalid hash \"{hex}\". '{c}' is not a hex character");
         }
+
+        let bytes = hex::decode(hex.to_ascii_lowercase())
+            .with_context(|| format!("Invalid hash \"{hex}\""))?;
         let mut hash_bytes = [0u8; 20];
         hash_bytes.copy_from_slice(&bytes);
         Ok(Hash(hash_bytes))
@@ -75,3 +86,27 @@ impl std::fmt::Display for Hash {
         write!(f, "{}", self.to_hex())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    #[test]
+    fn test_from_hex() {
+        let hash = Hash::from_hex(HEX).unwrap();
+        assert_eq!(HEX, hash.to_hex());
+        assert_eq!(hash, Hash::from_hex(&HEX.to_uppercase()).unwrap());
+
+        let error = Hash::from_hex(&HEX[..39]).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Expected 40 hex characters, got 39")
+        );
+
+        let error = Hash::from_hex(&HEX.replace('a', "g")).unwrap_err();
+        assert!(error.to_string().contains("'g' is not a hex character"));
+    }
+}
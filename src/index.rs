@@ -1,6 +1,6 @@
 use std::{
-    collections::HashSet,
-    fs::{File, OpenOptions},
+    collections::{BTreeMap, HashSet},
+    fs::File,
     io::{BufRead, BufReader, Write},
     path::{Path, PathBuf},
 };
@@ -10,10 +10,20 @@ use walkdir::WalkDir;
 
 use crate::{
     hash::Hash,
-    objects::blob::Blob,
-    paths::{index_path, repository_root_path, rygit_path},
+    lockfile::LockFile,
+    objects::{blob::Blob, tree::Tree},
+    paths::{index_path, is_metadata_path, repository_root_path},
+    progress::{NoProgress, Progress},
+    sparse::SparseCheckout,
 };
 
+/// Starts the first line of the index, followed by the format version. The
+/// original format had no header and is read as version 1. Version 3 added
+/// the stage number after each entry's hash, version 4 the assume-unchanged
+/// flag after the stage.
+const INDEX_HEADER: &str = "rygit-index";
+const INDEX_VERSION: u32 = 4;
+
 #[derive(Debug)]
 pub struct Index {
     files: Vec<IndexFile>,
@@ -21,148 +31,367 @@ pub struct Index {
 
 impl Index {
     pub fn load() -> Result<Self> {
-        let repository_path = repository_root_path();
         let file = File::open(index_path()).context("Unable to open index file")?;
         let reader = BufReader::new(file);
+        let mut lines = reader.lines().peekable();
+        let mut version = 1;
+        if let Some(Ok(header)) = lines.peek()
+            && let Some(header_version) = header.strip_prefix(INDEX_HEADER)
+        {
+            version = check_version(header_version.trim())?;
+            lines.next();
+        }
+
         let mut files = vec![];
-        for line in reader.lines() {
+        for line in lines {
             let line = line.context("Unable to read index file")?;
-            let mut parts = line.split(" ");
-            let relative_path = parts
-                .next()
+            // The hash, stage and flag never contain a space, so split from
+            // the right to keep paths that do
+            let (line, assume_unchanged) = if version >= 4 {
+                let (line, flag) = line.rsplit_once(' ').context(
+                    "Unable to load index. Invalid index format. Assume-unchanged flag missing",
+                )?;
+                let assume_unchanged = match flag {
+                    "0" => false,
+                    "1" => true,
+                    _ => bail!(
+                        "Unable to load index. Invalid index format. Invalid assume-unchanged flag"
+                    ),
+                };
+                (line, assume_unchanged)
+            } else {
+                (line.as_str(), false)
+            };
+            let (entry, stage) = if version >= 3 {
+                let (entry, stage) = line
+                    .rsplit_once(' ')
+                    .context("Unable to load index. Invalid index format. Stage missing")?;
+                let stage = stage
+                    .parse()
+                    .ok()
+                    .filter(|stage| *stage <= 3)
+                    .context("Unable to load index. Invalid index format. Invalid stage")?;
+                (entry, stage)
+            } else {
+                (line, 0)
+            };
+            let (relative_path, hash) = entry
+                .rsplit_once(' ')
                 .context("Unable to load index. Invalid index format. Relative path missing")?;
-            let path = repository_path.join(relative_path);
-            let hash = parts
-                .next()
-                .context("Unable to load index. Invalid index format. Invalid hash")?;
+            let path = PathBuf::from(relative_path);
             let hash = Hash::from_hex(hash)
                 .context("Unable to load index. Invalid index format. Invalid hash")?;
-            files.push(IndexFile { path, hash });
+            files.push(IndexFile {
+                path,
+                hash,
+                stage,
+                assume_unchanged,
+            });
         }
 
         Ok(Self { files })
     }
 
     pub fn add(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.add_with_progress(path, &mut NoProgress)
+    }
+
+    /// Like [`Index::add`], reporting each file added from a directory to
+    /// `progress`.
+    pub fn add_with_progress(
+        &mut self,
+        path: impl AsRef<Path>,
+        progress: &mut dyn Progress,
+    ) -> Result<()> {
         let path = path.as_ref();
-        self.add_recursive(path)?;
         if path.is_dir() {
-            self.remove_deleted_files(path);
+            self.add_dir(path, progress)?;
+            self.remove_deleted_files(path)?;
+        } else {
+            self.add_file(path)?;
         }
         self.files.sort_by(|a, b| a.path.cmp(&b.path));
         self.write()
     }
 
-    fn add_recursive(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        if path.as_ref().is_dir() {
-            self.add_dir(path)
-        } else {
-            self.add_file(path)
-        }
+    /// Replaces the index contents with the files of `tree`.
+    pub fn reset_to(&mut self, tree: &Tree) -> Result<()> {
+        self.files = tree
+            .files()
+            .into_iter()
+            .map(|(path, hash)| IndexFile {
+                path,
+                hash,
+                stage: 0,
+                assume_unchanged: false,
+            })
+            .collect();
+        self.files.sort_by(|a, b| a.path.cmp(&b.path));
+        self.write()
     }
 
-    fn add_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        let path = path.as_ref();
-        let file_position = self.files.iter().position(|f| f.path == path);
-
-        if !path.exists() {
-            if let Some(pos) = file_position.as_ref() {
-                self.files.remove(*pos);
-                return Ok(());
-            } else {
-                let relative_path = path.strip_prefix(repository_root_path())?;
+    /// Sets the entries for `paths`, files or directories, back to their
+    /// versions in `tree_files`, dropping entries the tree doesn't have. The
+    /// working tree isn't touched. Returns the paths whose entries changed.
+    pub fn reset_paths(
+        &mut self,
+        tree_files: &BTreeMap<PathBuf, Hash>,
+        paths: &[PathBuf],
+    ) -> Result<Vec<PathBuf>> {
+        let mut reset = vec![];
+        for path in paths {
+            let relative_path = relative_to_root(path)?;
+            let in_index = self
+                .files
+                .iter()
+                .any(|f| f.path.starts_with(&relative_path));
+            let in_tree = tree_files
+                .keys()
+                .any(|file_path| file_path.starts_with(&relative_path));
+            if !in_index && !in_tree {
                 bail!(
-                    "Unable to add {}. Did not match any files",
+                    "Unable to reset {}. Did not match any files",
+                    relative_path.display()
+                );
+            }
+
+            let mut previous = BTreeMap::new();
+            self.files.retain(|f| {
+                if f.path.starts_with(&relative_path) {
+                    previous.insert(f.path.clone(), (f.hash, f.stage));
+                    return false;
+                }
+                true
+            });
+            for (file_path, hash) in tree_files {
+                if file_path.starts_with(&relative_path) {
+                    self.files.push(IndexFile {
+                        path: file_path.clone(),
+                        hash: *hash,
+                        stage: 0,
+                        assume_unchanged: false,
+                    });
+                    if previous.remove(file_path) != Some((*hash, 0)) {
+                        reset.push(file_path.clone());
+                    }
+                }
+            }
+            reset.extend(previous.into_keys());
+        }
+        self.files
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+        self.write()?;
+
+        reset.sort();
+        reset.dedup();
+        Ok(reset)
+    }
+
+    /// Sets or clears the assume-unchanged flag of the file at `path`. While
+    /// it's set, status and add don't look at the file in the working tree.
+    pub fn set_assume_unchanged(
+        &mut self,
+        path: impl AsRef<Path>,
+        assume_unchanged: bool,
+    ) -> Result<()> {
+        let relative_path = relative_to_root(path.as_ref())?;
+        let file = self
+            .files
+            .iter_mut()
+            .find(|f| f.path == relative_path && f.stage == 0)
+            .with_context(|| {
+                format!(
+                    "Unable to update index. {} is not in the index",
                     relative_path.display()
                 )
+            })?;
+        file.assume_unchanged = assume_unchanged;
+        self.write()
+    }
+
+    /// Replaces the entry for `path`, relative to the repository root, with
+    /// conflict stages: the base, our and their versions as stages 1, 2 and
+    /// 3. A side that doesn't have the file gets no entry.
+    pub fn record_conflict(
+        &mut self,
+        path: impl AsRef<Path>,
+        base: Option<Hash>,
+        ours: Option<Hash>,
+        theirs: Option<Hash>,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        self.files.retain(|f| f.path != path);
+        for (stage, hash) in (1..).zip([base, ours, theirs]) {
+            if let Some(hash) = hash {
+                self.files.push(IndexFile {
+                    path: path.to_path_buf(),
+                    hash,
+                    stage,
+                    assume_unchanged: false,
+                });
             }
         }
+        self.files
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.stage.cmp(&b.stage)));
+        self.write()
+    }
 
-        let blob = Blob::create(path)?;
-        let index_file = IndexFile {
-            path: path.to_path_buf(),
-            hash: *blob.hash(),
-        };
-        if let Some(position) = file_position {
-            self.files[position] = index_file;
-        } else {
-            self.files.push(index_file);
+    /// The paths that still have conflict stages, in order.
+    pub fn unmerged_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = self
+            .files
+            .iter()
+            .filter(|f| f.stage > 0)
+            .map(|f| f.path.clone())
+            .collect();
+        paths.dedup();
+        paths
+    }
+
+    /// Errors if any path still has conflict stages.
+    pub fn ensure_merged(&self) -> Result<()> {
+        let unmerged = self.unmerged_paths();
+        if !unmerged.is_empty() {
+            let unmerged: Vec<_> = unmerged.iter().map(|p| p.display().to_string()).collect();
+            bail!(
+                "Unresolved conflicts in: {}. Resolve them and stage the files first",
+                unmerged.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Removes every file from the index.
+    pub fn clear(&mut self) -> Result<()> {
+        self.files.clear();
+        self.write()
+    }
+
+    fn add_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let relative_path = relative_to_root(path)?;
+        let tracked = self.files.iter().any(|f| f.path == relative_path);
+        if !path.exists() && !tracked {
+            bail!(
+                "Unable to add {}. Did not match any files",
+                relative_path.display()
+            )
+        }
+
+        if self
+            .files
+            .iter()
+            .any(|f| f.path == relative_path && f.assume_unchanged)
+        {
+            return Ok(());
+        }
+
+        if !path.exists()
+            && SparseCheckout::load()?.is_some_and(|sparse| !sparse.includes(&relative_path))
+        {
+            return Ok(());
+        }
+
+        // Adding a path replaces all of its entries, which resolves a
+        // conflict recorded for it
+        self.files.retain(|f| f.path != relative_path);
+        if path.exists() {
+            let blob = Blob::create(path)?;
+            self.files.push(IndexFile {
+                path: relative_path,
+                hash: *blob.hash(),
+                stage: 0,
+                assume_unchanged: false,
+            });
         }
 
         Ok(())
     }
 
-    fn add_dir(&mut self, path: impl AsRef<Path>) -> Result<()> {
+    fn add_dir(&mut self, path: impl AsRef<Path>, progress: &mut dyn Progress) -> Result<()> {
         let path = path.as_ref();
         if !path.is_dir() {
             bail!("Unable to add {}. Not a dir", path.display());
         }
 
-        let rygit_path = rygit_path();
-        let entries = WalkDir::new(path)
+        let mut file_paths = vec![];
+        for entry in WalkDir::new(path)
             .min_depth(1)
             .into_iter()
-            .filter_entry(|e| !e.path().starts_with(&rygit_path));
-        for entry in entries {
+            .filter_entry(|e| !is_metadata_path(e.path()))
+        {
             let entry = entry.with_context(|| {
                 format!("Unable to add {}. Unable to read file", path.display())
             })?;
-            self.add_recursive(entry.path())?
+            if entry.file_type().is_file() {
+                file_paths.push(entry.into_path());
+            }
         }
 
+        progress.start(Some(file_paths.len()));
+        for file_path in file_paths {
+            self.add_file(file_path)?;
+            progress.tick();
+        }
+        progress.finish();
+
         Ok(())
     }
 
-    fn remove_deleted_files(&mut self, path: &Path) {
+    fn remove_deleted_files(&mut self, path: &Path) -> Result<()> {
+        let repository_path = repository_root_path();
+        let relative_path = relative_to_root(path)?;
+        let sparse = SparseCheckout::load()?;
         self.files.retain(|f| {
-            if !f.path.starts_with(path) {
+            if !f.path.starts_with(&relative_path) {
+                return true;
+            }
+            if f.assume_unchanged {
+                return true;
+            }
+            // Files left out by a sparse checkout aren't on disk by design
+            if sparse
+                .as_ref()
+                .is_some_and(|sparse| !sparse.includes(&f.path))
+            {
                 return true;
             }
 
-            f.path.exists()
+            // A file replaced by a directory of the same name is gone too.
+            repository_path.join(&f.path).is_file()
         });
+
+        Ok(())
     }
 
+    /// Writes the index through `index.lock`, so a failure part way leaves
+    /// the previous index in place.
     fn write(&self) -> Result<()> {
-        let repository_path = repository_root_path().canonicalize()?;
-        let mut index_file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(index_path())
-            .context("Unable to write index contents. Unable to open index file")?;
-
-        for file in self.files.iter() {
-            let path = &file.path;
-            let relative_path = path.strip_prefix(&repository_path).with_context(|| {
-                format!(
-                    "Unable to write index file. Unable to determine relative path for {} with base path {}",
-                    path.display(),
-                    repository_path.display()
-                )
-            })?;
-            let line = format!("{} {}\n", relative_path.display(), file.hash.to_hex());
-            index_file
-                .write_all(line.as_bytes())
-                .context("Unable to write to index file")?;
-        }
-
-        Ok(())
+        let mut index_file = LockFile::acquire(index_path())
+            .context("Unable to write index contents. Unable to lock index file")?;
+        write_entries(&mut index_file, &self.files)?;
+        index_file
+            .commit()
+            .context("Unable to write index contents")
     }
 
     pub fn indexed_files_in_directory(&self, path: impl AsRef<Path>) -> Vec<PathBuf> {
         let path = path.as_ref();
+        let repository_path = repository_root_path();
         self.files
             .iter()
-            .filter(|f| f.path.is_file() && f.path.parent().unwrap() == path)
-            .map(|f| f.path.to_path_buf())
+            .map(|f| repository_path.join(&f.path))
+            .filter(|file_path| file_path.is_file() && file_path.parent() == Some(path))
             .collect()
     }
 
     pub fn indexed_directories_in_directory(&self, path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
         let path = path.as_ref();
+        let repository_path = repository_root_path();
         let mut indexed_directories = HashSet::new();
         for file in self.files.iter() {
-            let file_path = &file.path;
+            let file_path = &repository_path.join(&file.path);
             if file_path.parent().is_none() {
                 continue;
             }
@@ -182,13 +411,69 @@ impl Index {
     }
 }
 
+fn write_entries(index_file: &mut impl Write, files: &[IndexFile]) -> Result<()> {
+    writeln!(index_file, "{INDEX_HEADER} v{INDEX_VERSION}")
+        .context("Unable to write to index file")?;
+    for file in files {
+        writeln!(
+            index_file,
+            "{} {} {} {}",
+            file.path.display(),
+            file.hash.to_hex(),
+            file.stage,
+            u8::from(file.assume_unchanged)
+        )
+        .context("Unable to write to index file")?;
+    }
+
+    Ok(())
+}
+
+/// The path of `path` relative to the repository root. A path that reaches
+/// the work tree through a symlink is resolved first.
+fn relative_to_root(path: &Path) -> Result<PathBuf> {
+    let repository_path = repository_root_path();
+    if let Ok(relative_path) = path.strip_prefix(&repository_path) {
+        return Ok(relative_path.to_path_buf());
+    }
+
+    // A deleted file can't be resolved, but its directory can
+    let resolved = match (path.canonicalize(), path.parent(), path.file_name()) {
+        (Ok(resolved), _, _) => Ok(resolved),
+        (Err(_), Some(parent), Some(name)) => parent.canonicalize().map(|p| p.join(name)),
+        (Err(error), _, _) => Err(error),
+    };
+    resolved
+        .ok()
+        .zip(repository_path.canonicalize().ok())
+        .and_then(|(resolved, root)| Some(resolved.strip_prefix(root).ok()?.to_path_buf()))
+        .with_context(|| format!("Unable to add {}. Outside the repository", path.display()))
+}
+
+/// Parses `version`, as written in the index header, erroring unless it is
+/// one this build can read.
+fn check_version(version: &str) -> Result<u32> {
+    match version
+        .strip_prefix('v')
+        .and_then(|number| number.parse::<u32>().ok())
+    {
+        Some(number) if (1..=INDEX_VERSION).contains(&number) => Ok(number),
+        _ => bail!(
+            "Unable to load index. Unsupported index version {version}. Upgrade rygit to read it"
+        ),
+    }
+}
+
 #[derive(Debug)]
 pub struct IndexFile {
     path: PathBuf,
     hash: Hash,
+    stage: u8,
+    assume_unchanged: bool,
 }
 
 impl IndexFile {
+    /// The path relative to the repository root.
     pub fn path(&self) -> &Path {
         &self.path
     }
@@ -196,17 +481,156 @@ impl IndexFile {
     pub fn hash(&self) -> &Hash {
         &self.hash
     }
+
+    /// 0 for a merged file, otherwise the conflict stage: 1 for the base,
+    /// 2 for our version and 3 for theirs.
+    pub fn stage(&self) -> u8 {
+        self.stage
+    }
+
+    /// Whether the file is treated as matching its entry without being
+    /// checked, so local changes to it are ignored.
+    pub fn assume_unchanged(&self) -> bool {
+        self.assume_unchanged
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
 
     use anyhow::{Ok, Result};
 
-    use crate::test_utils::TestRepo;
+    use crate::test_utils::{CountingProgress, TestRepo};
 
     use super::*;
 
+    #[test]
+    fn test_index_version() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?.stage(".")?;
+
+        let contents = fs::read_to_string(index_path())?;
+        assert_eq!(Some("rygit-index v4"), contents.lines().next());
+        let index = Index::load()?;
+        assert_eq!(1, index.files.len());
+        assert_eq!(PathBuf::from("a.txt"), index.files[0].path);
+
+        let hash = index.files[0].hash;
+        fs::write(index_path(), format!("a.txt {hash}\n"))?;
+        let index = Index::load()?;
+        assert_eq!(1, index.files.len());
+        assert_eq!(hash, index.files[0].hash);
+
+        fs::write(index_path(), format!("rygit-index v2\na.txt {hash}\n"))?;
+        let index = Index::load()?;
+        assert_eq!(hash, index.files[0].hash);
+        assert_eq!(0, index.files[0].stage);
+
+        fs::write(index_path(), format!("rygit-index v3\na.txt {hash} 2\n"))?;
+        let index = Index::load()?;
+        assert_eq!(2, index.files[0].stage);
+        assert!(!index.files[0].assume_unchanged);
+
+        fs::write(index_path(), format!("rygit-index v5\na.txt {hash} 0 0\n"))?;
+        let error = Index::load().unwrap_err();
+        assert!(error.to_string().contains("Unsupported index version v5"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_with_spaces() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("my file.txt", "a")?
+            .file("sub dir/b c.txt", "b")?
+            .stage(".")?;
+
+        let index = Index::load()?;
+        let paths: Vec<_> = index.files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(
+            vec![
+                PathBuf::from("my file.txt"),
+                PathBuf::from("sub dir/b c.txt")
+            ],
+            paths
+        );
+        assert_eq!(
+            Blob::hash_for(repo.path().join("my file.txt"))?,
+            index.files[0].hash
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_add_through_symlink() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?.file("dir/b.txt", "b")?;
+        let link_dir = tempfile::TempDir::new()?;
+        let link = link_dir.path().join("work-tree");
+        std::os::unix::fs::symlink(repo.path(), &link)?;
+
+        let mut index = Index::load()?;
+        index.add(link.join("a.txt"))?;
+        index.add(link.join("dir"))?;
+
+        let paths: Vec<_> = index.files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(
+            vec![PathBuf::from("a.txt"), PathBuf::from("dir/b.txt")],
+            paths
+        );
+        let contents = fs::read_to_string(index_path())?;
+        let written: Vec<_> = contents
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplitn(4, ' ').last().unwrap())
+            .collect();
+        assert_eq!(vec!["a.txt", "dir/b.txt"], written);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_write_keeps_index() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?.stage(".")?;
+        let original = fs::read_to_string(index_path())?;
+
+        let mut index = Index::load()?;
+        let mut index_file = LockFile::acquire(index_path())?;
+        write!(index_file, "{INDEX_HEADER} v{INDEX_VERSION}\npartial")?;
+        drop(index_file);
+        assert_eq!(original, fs::read_to_string(index_path())?);
+
+        let _held = LockFile::acquire(index_path())?;
+        repo.file("b.txt", "b")?;
+        assert!(index.add(repo.path().join("b.txt")).is_err());
+        assert_eq!(original, fs::read_to_string(index_path())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_with_progress() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("subdir/b.txt", "b")?
+            .file("subdir/nested/c.txt", "c")?;
+
+        let mut progress = CountingProgress::default();
+        let mut index = Index::load()?;
+        index.add_with_progress(repo.path(), &mut progress)?;
+
+        assert_eq!(3, index.files.len());
+        assert_eq!(Some(3), progress.total);
+        assert_eq!(3, progress.ticks);
+        assert!(progress.finished);
+
+        Ok(())
+    }
+
     #[test]
     fn test_add() -> Result<()> {
         let repo = TestRepo::new()?;
@@ -221,22 +645,22 @@ mod tests {
 
         assert_eq!(1, index.files.len());
         let indexed_file_paths: HashSet<_> = index.files.iter().map(|f| &f.path).collect();
-        assert!(indexed_file_paths.contains(&repo.path().join("a.txt")));
+        assert!(indexed_file_paths.contains(&PathBuf::from("a.txt")));
 
         let mut index = Index::load()?;
-        assert!(indexed_file_paths.contains(&repo.path().join("a.txt")));
+        assert!(indexed_file_paths.contains(&PathBuf::from("a.txt")));
 
         index.add(repo.path().join("subdir1"))?;
         index.add(repo.path().join("subdir2/e.txt"))?;
         assert_eq!(3, index.files.len());
         let mut files_iter = index.files.iter();
-        assert_eq!(repo.path().join("a.txt"), files_iter.next().unwrap().path);
+        assert_eq!(PathBuf::from("a.txt"), files_iter.next().unwrap().path);
         assert_eq!(
-            repo.path().join("subdir1/c.txt"),
+            PathBuf::from("subdir1/c.txt"),
             files_iter.next().unwrap().path
         );
         assert_eq!(
-            repo.path().join("subdir2/e.txt"),
+            PathBuf::from("subdir2/e.txt"),
             files_iter.next().unwrap().path
         );
 
@@ -244,16 +668,13 @@ mod tests {
             .remove_file("subdir1/c.txt")?
             .stage(".")?;
         let index = Index::load()?;
-        let file_a_present = index
-            .files()
-            .iter()
-            .any(|f| f.path == repo.path().join("a.txt"));
+        let file_a_present = index.files().iter().any(|f| f.path == Path::new("a.txt"));
         assert!(!file_a_present);
 
         let file_c_present = index
             .files()
             .iter()
-            .any(|f| f.path == repo.path().join("subdir1/c.txt"));
+            .any(|f| f.path == Path::new("subdir1/c.txt"));
         assert!(!file_c_present);
 
         Ok(())
@@ -0,0 +1,40 @@
+//! A small git implementation. The [`Repository`] type is the entry point for
+//! embedding rygit; the `rygit` binary is a thin wrapper over [`cli`].
+
+pub mod branch;
+pub mod cli;
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod hash;
+pub mod index;
+pub mod objects;
+pub mod progress;
+pub mod repository;
+pub mod tag;
+
+mod attributes;
+mod bundle;
+mod commands;
+mod compression;
+mod lockfile;
+mod merge;
+mod notes;
+mod operation;
+mod output;
+mod pack;
+mod paths;
+mod refs;
+mod remote;
+mod repository_status;
+mod rev_list;
+mod revparse;
+mod shallow;
+mod sparse;
+#[cfg(test)]
+mod test_utils;
+mod trailers;
+mod transport;
+mod worktree;
+
+pub use repository::Repository;
@@ -0,0 +1,140 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{error::RygitError, paths::common_path};
+
+/// Replaces a file atomically: new contents go to `<path>.lock`, which is
+/// renamed over `path` on [`LockFile::commit`]. Until then readers see the
+/// old contents, and a lock dropped without committing is removed, leaving
+/// `path` untouched. The lock file only exists while it's held, so a
+/// second writer can't create it at the same time.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl LockFile {
+    pub fn acquire(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let file = create_lock(&lock_path)?;
+
+        Ok(Self {
+            path,
+            lock_path,
+            file,
+            committed: false,
+        })
+    }
+
+    /// Replaces the locked file with what was written and releases the lock.
+    pub fn commit(mut self) -> Result<()> {
+        self.file
+            .flush()
+            .and_then(|_| self.file.sync_all())
+            .with_context(|| format!("Unable to write {}", self.lock_path.display()))?;
+        fs::rename(&self.lock_path, &self.path)
+            .with_context(|| format!("Unable to replace {}", self.path.display()))?;
+        self.committed = true;
+
+        Ok(())
+    }
+}
+
+impl Write for LockFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Held for the whole of a command that changes the repository, so another
+/// rygit process can't modify the index and refs underneath it.
+pub struct RepositoryLock {
+    path: PathBuf,
+}
+
+impl RepositoryLock {
+    /// Takes the lock, failing straight away with [`RygitError::Locked`] if
+    /// another process holds it.
+    pub fn acquire() -> Result<Self> {
+        let path = common_path().join("rygit.lock");
+        create_lock(&path)?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Creates the lock file at `lock_path`, which must not exist yet.
+fn create_lock(lock_path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(lock_path)
+        .map_err(|error| match error.kind() {
+            ErrorKind::AlreadyExists => RygitError::Locked(lock_path.to_path_buf()).into(),
+            _ => anyhow::Error::new(error)
+                .context(format!("Unable to create {}", lock_path.display())),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_commit_and_abandon() -> Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join("file");
+        fs::write(&path, "old")?;
+
+        let mut lock = LockFile::acquire(&path)?;
+        write!(lock, "new")?;
+        assert_eq!("old", fs::read_to_string(&path)?);
+        let error = LockFile::acquire(&path).err().unwrap();
+        assert_eq!(
+            Some(&RygitError::Locked(dir.path().join("file.lock"))),
+            error.downcast_ref::<RygitError>()
+        );
+        lock.commit()?;
+        assert_eq!("new", fs::read_to_string(&path)?);
+        assert!(!dir.path().join("file.lock").exists());
+
+        let mut lock = LockFile::acquire(&path)?;
+        write!(lock, "abandoned")?;
+        drop(lock);
+        assert_eq!("new", fs::read_to_string(&path)?);
+        assert!(!dir.path().join("file.lock").exists());
+
+        Ok(())
+    }
+}
@@ -1,18 +1,11 @@
-use clap::Parser;
+use std::process;
 
-use crate::cli::Cli;
+use clap::Parser;
 
-pub mod branch;
-pub mod cli;
-pub mod commands;
-pub mod compression;
-pub mod hash;
-pub mod index;
-pub mod objects;
-pub mod paths;
-pub mod repository_status;
-#[cfg(test)]
-pub mod test_utils;
+use rygit::{
+    cli::{self, Cli},
+    error,
+};
 
 fn main() {
     let cli = Cli::parse();
@@ -23,6 +16,7 @@ fn main() {
             for cause in err.chain() {
                 eprintln!("{cause}")
             }
+            process::exit(error::exit_code(&err));
         }
     }
 }
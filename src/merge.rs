@@ -0,0 +1,448 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    attributes::Attributes,
+    config::Config,
+    diff::{Edit, myers_diff, split_lines},
+    hash::Hash,
+    objects::{blob::Blob, is_text, tree::Tree},
+    paths::rygit_path,
+};
+
+pub const CONFLICT_MARKER_OURS: &str = "<<<<<<<";
+pub const CONFLICT_MARKER_SEPARATOR: &str = "=======";
+pub const CONFLICT_MARKER_THEIRS: &str = ">>>>>>>";
+
+/// The outcome of merging two trees: the merged files by path relative to
+/// the tree root, and the paths that could not be merged cleanly. A
+/// conflicted file holds conflict markers, or the surviving side of a
+/// modify/delete conflict.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TreeMerge {
+    pub files: BTreeMap<PathBuf, Hash>,
+    pub conflicts: Vec<PathBuf>,
+}
+
+impl TreeMerge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merges the changes from `base` to `theirs` into `ours`, file by file.
+/// `base` is `None` when the histories share no common ancestor. Labels name
+/// the two sides in conflict markers. Files changed on both sides go through
+/// the merge driver their `merge` attribute names, if it is configured, the
+/// built-in `union` driver, or otherwise [`merge_content`].
+pub fn merge_trees(
+    base: Option<&Tree>,
+    ours: &Tree,
+    theirs: &Tree,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<TreeMerge> {
+    let base_files = base.map(|b| b.files()).unwrap_or_default();
+    let our_files = ours.files();
+    let their_files = theirs.files();
+    let paths: BTreeSet<_> = base_files
+        .keys()
+        .chain(our_files.keys())
+        .chain(their_files.keys())
+        .cloned()
+        .collect();
+
+    let attributes = Attributes::load()?;
+    let config = Config::load()?;
+    let mut merge = TreeMerge::default();
+    for path in paths {
+        let base_hash = base_files.get(&path);
+        let our_hash = our_files.get(&path);
+        let their_hash = their_files.get(&path);
+
+        let merged_hash = if our_hash == their_hash || their_hash == base_hash {
+            our_hash.copied()
+        } else if our_hash == base_hash {
+            their_hash.copied()
+        } else {
+            match (our_hash, their_hash) {
+                (Some(our_hash), Some(their_hash)) => {
+                    let base_body = match base_hash {
+                        Some(hash) => Blob::load(hash).body()?,
+                        None => vec![],
+                    };
+                    let our_body = Blob::load(our_hash).body()?;
+                    let their_body = Blob::load(their_hash).body()?;
+                    let driver = attributes.for_path(&path).merge;
+                    let command = driver
+                        .as_ref()
+                        .and_then(|name| config.get(&format!("merge.{name}.driver")));
+                    let (body, conflicted) = match (driver.as_deref(), command) {
+                        (_, Some(command)) => {
+                            run_merge_driver(command, &path, &base_body, &our_body, &their_body)?
+                        }
+                        (Some("union"), None) => (
+                            merge_content_union(&base_body, &our_body, &their_body),
+                            false,
+                        ),
+                        _ => merge_content(
+                            &base_body,
+                            &our_body,
+                            &their_body,
+                            ours_label,
+                            theirs_label,
+                        ),
+                    };
+                    if conflicted {
+                        merge.conflicts.push(path.clone());
+                    }
+                    Some(*Blob::from_bytes(&body)?.hash())
+                }
+                // Modified on one side and deleted on the other. Keep the
+                // modified version so nothing is lost
+                (Some(hash), None) | (None, Some(hash)) => {
+                    merge.conflicts.push(path.clone());
+                    Some(*hash)
+                }
+                (None, None) => None,
+            }
+        };
+
+        if let Some(hash) = merged_hash {
+            merge.files.insert(path, hash);
+        }
+    }
+
+    Ok(merge)
+}
+
+/// Three-way merges file contents line by line. Regions changed on only one
+/// side take that side's lines; regions changed differently on both sides
+/// are wrapped in conflict markers. Returns the merged content and whether
+/// it has conflicts.
+pub fn merge_content(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> (Vec<u8>, bool) {
+    merge_lines(base, ours, theirs, Some((ours_label, theirs_label)))
+}
+
+/// Like [`merge_content`], but regions changed differently on both sides
+/// keep our lines followed by theirs instead of conflicting. Suits files
+/// that are only appended to, like changelogs.
+pub fn merge_content_union(base: &[u8], ours: &[u8], theirs: &[u8]) -> Vec<u8> {
+    merge_lines(base, ours, theirs, None).0
+}
+
+/// The three-way merge behind [`merge_content`]. Without labels, conflicting
+/// regions are concatenated rather than wrapped in markers.
+fn merge_lines(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: Option<(&str, &str)>,
+) -> (Vec<u8>, bool) {
+    let base_lines = split_lines(base);
+    let our_lines = split_lines(ours);
+    let their_lines = split_lines(theirs);
+    let our_matches = matching_lines(&base_lines, &our_lines);
+    let their_matches = matching_lines(&base_lines, &their_lines);
+
+    let mut merged = vec![];
+    let mut conflicted = false;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    while b < base_lines.len() || o < our_lines.len() || t < their_lines.len() {
+        // A base line both sides kept in place
+        if b < base_lines.len() && our_matches[b] == Some(o) && their_matches[b] == Some(t) {
+            merged.extend_from_slice(base_lines[b]);
+            b += 1;
+            o += 1;
+            t += 1;
+            continue;
+        }
+
+        // Otherwise the chunk runs up to the next base line both sides kept
+        let next_stable =
+            (b..base_lines.len()).find(|&i| our_matches[i].is_some() && their_matches[i].is_some());
+        let (b_end, o_end, t_end) = match next_stable {
+            Some(i) => (i, our_matches[i].unwrap(), their_matches[i].unwrap()),
+            None => (base_lines.len(), our_lines.len(), their_lines.len()),
+        };
+        let base_chunk = &base_lines[b..b_end];
+        let our_chunk = &our_lines[o..o_end];
+        let their_chunk = &their_lines[t..t_end];
+
+        if our_chunk == base_chunk {
+            their_chunk.iter().for_each(|l| merged.extend_from_slice(l));
+        } else if their_chunk == base_chunk || our_chunk == their_chunk {
+            our_chunk.iter().for_each(|l| merged.extend_from_slice(l));
+        } else if let Some((ours_label, theirs_label)) = labels {
+            conflicted = true;
+            push_marker(&mut merged, CONFLICT_MARKER_OURS, Some(ours_label));
+            our_chunk.iter().for_each(|l| merged.extend_from_slice(l));
+            push_marker(&mut merged, CONFLICT_MARKER_SEPARATOR, None);
+            their_chunk.iter().for_each(|l| merged.extend_from_slice(l));
+            push_marker(&mut merged, CONFLICT_MARKER_THEIRS, Some(theirs_label));
+        } else {
+            our_chunk.iter().for_each(|l| merged.extend_from_slice(l));
+            their_chunk.iter().for_each(|l| merged.extend_from_slice(l));
+        }
+
+        b = b_end;
+        o = o_end;
+        t = t_end;
+    }
+
+    (merged, conflicted)
+}
+
+/// Merges a file with an external merge driver. `%O`, `%A` and `%B` in
+/// `command` are replaced with files holding the base, our and their
+/// versions, and `%P` with the path being merged. The driver leaves the
+/// result in the `%A` file and exits with a non-zero status when it left
+/// conflicts.
+fn run_merge_driver(
+    command: &str,
+    path: &Path,
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+) -> Result<(Vec<u8>, bool)> {
+    let context = || {
+        format!(
+            "Unable to merge {}. Unable to run merge driver `{command}`",
+            path.display()
+        )
+    };
+    let rygit_path = rygit_path();
+    let base_path = rygit_path.join("MERGE_DRIVER_BASE");
+    let ours_path = rygit_path.join("MERGE_DRIVER_OURS");
+    let theirs_path = rygit_path.join("MERGE_DRIVER_THEIRS");
+    fs::write(&base_path, base)
+        .and_then(|_| fs::write(&ours_path, ours))
+        .and_then(|_| fs::write(&theirs_path, theirs))
+        .with_context(context)?;
+
+    let command = command
+        .replace("%O", &shell_quote(&base_path))
+        .replace("%A", &shell_quote(&ours_path))
+        .replace("%B", &shell_quote(&theirs_path))
+        .replace("%P", &shell_quote(path));
+    let status = Command::new("sh")
+        .args(["-c", &command])
+        .status()
+        .with_context(context)?;
+    let merged = fs::read(&ours_path).with_context(context)?;
+    for file in [&base_path, &ours_path, &theirs_path] {
+        fs::remove_file(file).with_context(context)?;
+    }
+
+    Ok((merged, !status.success()))
+}
+
+/// Quotes `path` as a single shell word.
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Whether `content` still has a line starting with a conflict marker.
+/// Binary content never does.
+pub fn has_conflict_markers(content: &[u8]) -> bool {
+    if !is_text(content) {
+        return false;
+    }
+
+    split_lines(content).iter().any(|line| {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        line.starts_with(format!("{CONFLICT_MARKER_OURS} ").as_bytes())
+            || line.starts_with(format!("{CONFLICT_MARKER_THEIRS} ").as_bytes())
+            || line == CONFLICT_MARKER_SEPARATOR.as_bytes()
+    })
+}
+
+/// The files in `tree` that still have conflict markers.
+pub fn unresolved_conflicts(tree: &Tree) -> Result<Vec<PathBuf>> {
+    let mut conflicts = vec![];
+    for (path, hash) in tree.files() {
+        if has_conflict_markers(&Blob::load(&hash).body()?) {
+            conflicts.push(path);
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/// For every base line, the index of the line it was kept as on the other
+/// side, if it was kept.
+fn matching_lines(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for edit in myers_diff(base, other) {
+        if let Edit::Equal(base_index, other_index) = edit {
+            matches[base_index] = Some(other_index);
+        }
+    }
+
+    matches
+}
+
+fn push_marker(merged: &mut Vec<u8>, marker: &str, label: Option<&str>) {
+    if merged.last().is_some_and(|&c| c != b'\n') {
+        merged.push(b'\n');
+    }
+    merged.extend_from_slice(marker.as_bytes());
+    if let Some(label) = label {
+        merged.push(b' ');
+        merged.extend_from_slice(label.as_bytes());
+    }
+    merged.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        attributes::ATTRIBUTES_FILE_NAME,
+        objects::{ObjectKind, write_object},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_merge_content() {
+        let base = b"a\nb\nc\nd\ne\n";
+        let ours = b"a\nB\nc\nd\ne\n";
+        let theirs = b"a\nb\nc\nD\ne\nf\n";
+        let (merged, conflicted) = merge_content(base, ours, theirs, "ours", "theirs");
+        assert!(!conflicted);
+        assert_eq!(b"a\nB\nc\nD\ne\nf\n".to_vec(), merged);
+
+        let theirs = b"a\nX\nc\nd\ne\n";
+        let (merged, conflicted) = merge_content(base, ours, theirs, "ours", "theirs");
+        assert!(conflicted);
+        assert_eq!(
+            b"a\n<<<<<<< ours\nB\n=======\nX\n>>>>>>> theirs\nc\nd\ne\n".to_vec(),
+            merged
+        );
+    }
+
+    #[test]
+    fn test_has_conflict_markers() {
+        assert!(has_conflict_markers(
+            b"a\n<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> feature\n"
+        ));
+        assert!(has_conflict_markers(b"=======\n"));
+        assert!(!has_conflict_markers(b"a\n========\n<<<<<<<<\n"));
+        assert!(!has_conflict_markers(b"\0\n=======\n"));
+    }
+
+    #[test]
+    fn test_merge_trees() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        let blob = |content: &str| write_object(ObjectKind::Blob, content.as_bytes());
+        let tree = |files: &[(&str, Hash)]| {
+            Tree::from_paths(
+                &files
+                    .iter()
+                    .map(|(path, hash)| (PathBuf::from(path), *hash))
+                    .collect(),
+            )
+        };
+
+        let base = tree(&[
+            ("same.txt", blob("same\n")?),
+            ("ours.txt", blob("1\n")?),
+            ("deleted.txt", blob("x\n")?),
+            ("conflict.txt", blob("base\n")?),
+        ])?;
+        let ours = tree(&[
+            ("same.txt", blob("same\n")?),
+            ("ours.txt", blob("2\n")?),
+            ("deleted.txt", blob("x\n")?),
+            ("conflict.txt", blob("ours\n")?),
+        ])?;
+        let theirs = tree(&[
+            ("same.txt", blob("same\n")?),
+            ("ours.txt", blob("1\n")?),
+            ("added.txt", blob("new\n")?),
+            ("conflict.txt", blob("theirs\n")?),
+        ])?;
+
+        let merge = merge_trees(Some(&base), &ours, &theirs, "HEAD", "feature")?;
+        assert_eq!(vec![PathBuf::from("conflict.txt")], merge.conflicts);
+        let paths: Vec<_> = merge.files.keys().cloned().collect();
+        assert_eq!(
+            vec![
+                PathBuf::from("added.txt"),
+                PathBuf::from("conflict.txt"),
+                PathBuf::from("ours.txt"),
+                PathBuf::from("same.txt")
+            ],
+            paths
+        );
+        assert_eq!(
+            b"2\n".to_vec(),
+            Blob::load(&merge.files[&PathBuf::from("ours.txt")]).body()?
+        );
+        assert_eq!(
+            b"<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n".to_vec(),
+            Blob::load(&merge.files[&PathBuf::from("conflict.txt")]).body()?
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_merge_driver() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file(
+            ATTRIBUTES_FILE_NAME,
+            "*.lock merge=ours\n*.txt merge=theirs\n*.md merge=unset\n",
+        )?;
+        let mut config = Config::load()?;
+        config.set("merge.ours.driver", "true")?;
+        config.set("merge.theirs.driver", "cat %B > %A; echo %P >> %A; exit 1")?;
+        config.save()?;
+        let blob = |content: &str| write_object(ObjectKind::Blob, content.as_bytes());
+        let tree = |content: &str| -> Result<Tree> {
+            let files = ["a.lock", "b.txt", "c.md"]
+                .into_iter()
+                .map(|path| Ok((PathBuf::from(path), blob(content)?)))
+                .collect::<Result<_>>()?;
+            Tree::from_paths(&files)
+        };
+
+        let merge = merge_trees(
+            Some(&tree("base\n")?),
+            &tree("ours\n")?,
+            &tree("theirs\n")?,
+            "HEAD",
+            "feature",
+        )?;
+        let merged = |path: &str| Blob::load(&merge.files[&PathBuf::from(path)]).body();
+        assert_eq!(b"ours\n".to_vec(), merged("a.lock")?);
+        assert_eq!(b"theirs\nb.txt\n".to_vec(), merged("b.txt")?);
+        assert_eq!(
+            b"<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> feature\n".to_vec(),
+            merged("c.md")?
+        );
+        assert_eq!(
+            vec![PathBuf::from("b.txt"), PathBuf::from("c.md")],
+            merge.conflicts
+        );
+        assert!(!rygit_path().join("MERGE_DRIVER_OURS").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    hash::Hash,
+    objects::{blob::Blob, commit::Commit, signature::Signature, tree::Tree},
+    refs::{read_ref, update_ref},
+};
+
+pub const NOTES_REF: &str = "refs/notes/commits";
+
+/// The notes as of the latest commit on the notes ref. Notes annotate
+/// commits without changing their hashes: the notes ref points at a commit
+/// whose tree maps each annotated commit's hash to a blob holding its note,
+/// so every change to the notes is kept in their history.
+pub struct Notes {
+    commit_hash: Option<Hash>,
+    /// Note blobs keyed by the hex hash of the commit they annotate.
+    notes: BTreeMap<PathBuf, Hash>,
+}
+
+impl Notes {
+    pub fn load() -> Result<Self> {
+        let commit_hash = read_ref(NOTES_REF).context("Unable to load notes")?;
+        let notes = match &commit_hash {
+            Some(hash) => Commit::load(hash)
+                .and_then(|commit| commit.tree())
+                .context("Unable to load notes")?
+                .files(),
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self { commit_hash, notes })
+    }
+
+    /// The note attached to `commit`, if it has one.
+    pub fn get(&self, commit: &Hash) -> Result<Option<String>> {
+        let Some(blob_hash) = self.notes.get(&PathBuf::from(commit.to_hex())) else {
+            return Ok(None);
+        };
+        let body = Blob::load(blob_hash)
+            .body()
+            .with_context(|| format!("Unable to read the note for {commit}"))?;
+
+        Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Attaches `message` to `commit` and records the change on the notes
+    /// ref. An existing note is only replaced when `force` is set.
+    pub fn add(
+        &mut self,
+        commit: &Hash,
+        message: &str,
+        author: Signature,
+        force: bool,
+    ) -> Result<()> {
+        let path = PathBuf::from(commit.to_hex());
+        if self.notes.contains_key(&path) && !force {
+            bail!("Unable to add note. {commit} already has a note. Use -f to overwrite it");
+        }
+
+        let mut message = message.trim_end().to_string();
+        message.push('\n');
+        let blob = Blob::from_bytes(message.as_bytes()).context("Unable to add note")?;
+        self.notes.insert(path, *blob.hash());
+
+        let tree = Tree::from_paths(&self.notes).context("Unable to add note")?;
+        let notes_commit = Commit::create_from_tree(
+            *tree.hash(),
+            self.commit_hash.into_iter().collect(),
+            "Notes added by 'rygit notes add'",
+            author.clone(),
+            author,
+        )
+        .context("Unable to add note")?;
+        update_ref(NOTES_REF, notes_commit.hash(), self.commit_hash.as_ref())
+            .context("Unable to add note")?;
+        self.commit_hash = Some(*notes_commit.hash());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{refs::resolve, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_add_and_get() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let head = resolve("HEAD")?.unwrap();
+        let author = Signature::new("Walter Sobchak", "walter@example.com");
+
+        let mut notes = Notes::load()?;
+        assert_eq!(None, notes.get(&head)?);
+        notes.add(&head, "Reviewed", author.clone(), false)?;
+        assert_eq!(Some("Reviewed\n".to_string()), Notes::load()?.get(&head)?);
+        assert_eq!(head, resolve("HEAD")?.unwrap());
+
+        let mut notes = Notes::load()?;
+        assert!(notes.add(&head, "Again", author.clone(), false).is_err());
+        notes.add(&head, "Replaced", author, true)?;
+        assert_eq!(Some("Replaced\n".to_string()), Notes::load()?.get(&head)?);
+        let notes_commit = Commit::load(&read_ref(NOTES_REF)?.unwrap())?;
+        assert_eq!(1, notes_commit.parent_hashes().len());
+
+        Ok(())
+    }
+}
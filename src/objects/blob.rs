@@ -1,16 +1,22 @@
-use std::{
-    fs::{self, File},
-    io::{Read, Write},
-    path::{Path, PathBuf},
-};
+use std::{fs, path::Path};
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result};
 
 use crate::{
-    compression::{compress, decompress},
+    attributes,
+    error::RygitError,
     hash::Hash,
+    objects::{ObjectKind, read_object, serialize_object, write_object},
+    paths::repository_root_path,
 };
 
+#[cfg(test)]
+thread_local! {
+    /// How many files this thread has hashed, so tests can check that work
+    /// is skipped.
+    pub static FILES_HASHED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 // blob format:
 // <type> <size>\0<content>
 #[derive(Debug, PartialEq, Eq)]
@@ -19,71 +25,90 @@ pub struct Blob {
 }
 
 impl Blob {
+    /// The hash `path` would be stored under. Its contents go through the
+    /// same filters and line ending conversion as when adding it, so a file
+    /// that differs from its blob only in line endings hashes the same.
     pub fn hash_for(path: impl AsRef<Path>) -> Result<Hash> {
-        let path = path.as_ref();
-        let (_, hash) = serialize_and_hash(path)?;
+        let content = read_stored(path.as_ref())?;
 
-        Ok(hash)
+        Ok(Hash::of(&serialize_object(ObjectKind::Blob, &content)))
     }
 
+    /// Stores the file at `path` as a blob, after the same conversions as
+    /// [`Blob::hash_for`].
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
-        let path = path.as_ref();
-        let (serialized_data, hash) = serialize_and_hash(path)?;
-        let serialized_data = compress(&serialized_data)?;
-        let object_path = hash.object_path();
-        if !object_path.try_exists().unwrap() {
-            fs::create_dir_all(object_path.parent().unwrap())
-                .and_then(|_| File::create(&object_path))
-                .and_then(|mut file| file.write_all(&serialized_data))
-                .context("Unable to generate blob. Unable to create object file")?;
-        }
+        let content = read_stored(path.as_ref())?;
+
+        Self::from_bytes(&content)
+    }
+
+    /// Stores `content` as a blob as is, without a file to read it from.
+    pub fn from_bytes(content: &[u8]) -> Result<Self> {
+        let hash = write_object(ObjectKind::Blob, content).context("Unable to create blob")?;
 
         Ok(Self { hash })
     }
 
     pub fn body(&self) -> Result<Vec<u8>> {
-        let path = self.hash.object_path();
-        let mut buf = vec![];
-        File::open(path).unwrap().read_to_end(&mut buf).unwrap();
-        let mut contents = decompress(&buf)?;
-        if let Some(pos) = contents.iter().position(|&x| x == 0) {
-            contents.drain(0..=pos);
-        } else {
-            bail!("Invalid blob header")
+        let (kind, body) = read_object(&self.hash)?;
+        if kind != ObjectKind::Blob {
+            return Err(RygitError::UnexpectedObjectKind {
+                hash: self.hash,
+                expected: ObjectKind::Blob,
+                actual: kind,
+            }
+            .into());
         }
 
-        Ok(contents)
+        Ok(body)
     }
 
     pub fn hash(&self) -> &Hash {
         &self.hash
     }
 
-    pub fn load(object_path: PathBuf) -> Result<Self> {
-        let hash = Hash::from_object_path(&object_path)?;
-        let blob = Self { hash };
-
-        Ok(blob)
+    pub fn load(hash: &Hash) -> Self {
+        Self { hash: *hash }
     }
 }
-fn serialize(file_path: &Path) -> Result<Vec<u8>> {
-    let file_contents = fs::read(file_path)
-        .with_context(|| format!("Unable to read file {}", file_path.display()))?;
-    let file_length = file_contents.len();
-    let header = format!("blob {file_length}\0");
+/// The contents of the file at `path` as they would be stored, after
+/// filters and line ending conversion.
+fn read_stored(path: &Path) -> Result<Vec<u8>> {
+    let context = || format!("Unable to create blob contents for file {}", path.display());
+    let contents = fs::read(path)
+        .with_context(|| format!("Unable to read file {}", path.display()))
+        .with_context(context)?;
+    let relative_path = path.strip_prefix(repository_root_path()).unwrap_or(path);
+    let contents = attributes::to_stored(relative_path, contents).with_context(context)?;
+    #[cfg(test)]
+    FILES_HASHED.with(|count| count.set(count.get() + 1));
+
+    Ok(contents)
+}
 
-    let mut blob = Vec::with_capacity(header.len() + file_length);
-    blob.extend_from_slice(header.as_bytes());
-    blob.extend_from_slice(&file_contents);
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
 
-    Ok(blob)
-}
+    use crate::{objects::locate, test_utils::TestRepo};
 
-fn serialize_and_hash(path: impl AsRef<Path>) -> Result<(Vec<u8>, Hash)> {
-    let path = path.as_ref();
-    let serialized_data = serialize(path)
-        .with_context(|| format!("Unable to create blob contents for file {}", path.display()))?;
-    let hash = Hash::of(&serialized_data);
+    use super::*;
 
-    Ok((serialized_data, hash))
+    #[test]
+    fn test_from_bytes_matches_file() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "same content\n")?;
+
+        let from_bytes = Blob::from_bytes(b"same content\n")?;
+        assert!(locate(from_bytes.hash())?.is_some());
+        assert_eq!(b"same content\n".to_vec(), from_bytes.body()?);
+        assert_eq!(Blob::create(repo.path().join("a.txt"))?, from_bytes);
+        assert_eq!(
+            Blob::hash_for(repo.path().join("a.txt"))?,
+            *from_bytes.hash()
+        );
+        assert_ne!(from_bytes, Blob::from_bytes(b"other content\n")?);
+
+        Ok(())
+    }
 }
@@ -1,19 +1,20 @@
-use std::{
-    fs::{self, File},
-    io::{Read, Write},
-};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use anyhow::{Context, Result, bail};
 
 use crate::{
-    compression::{compress, decompress},
+    error::RygitError,
     hash::Hash,
     index::Index,
     objects::{
+        ObjectKind, read_object,
         signature::{Signature, SignatureKind},
         tree::Tree,
+        write_object,
     },
-    paths::head_ref_path,
+    progress::{NoProgress, Progress},
+    refs::{read_ref, resolve_name, update_ref},
+    shallow, trailers,
 };
 
 // commit format:
@@ -25,142 +26,191 @@ use crate::{
 // committer <committer_name> <<committer_email>> <timestamp>
 //
 // <commit message>
+//
+// An optional `encoding <name>` header after the committer records the
+// encoding of a message that isn't UTF-8.
+#[derive(Clone)]
 pub struct Commit {
-    _message: String,
+    message: String,
+    raw_message: Vec<u8>,
+    encoding: Option<String>,
     tree_hash: Hash,
     hash: Hash,
     parent_hashes: Vec<Hash>,
     author: Signature,
-    _committer: Signature,
+    raw_author: Vec<u8>,
+    committer: Signature,
 }
 
 impl Commit {
+    /// Commits the files staged in `index` on top of HEAD and moves HEAD's
+    /// ref to the new commit.
     pub fn create(
         index: &Index,
         message: impl Into<String>,
         author: Signature,
         committer: Signature,
     ) -> Result<Self> {
-        let mut parent_hashes: Vec<Hash> = vec![];
-        let mut head_ref_contents = String::new();
-        File::open(head_ref_path())
-            .and_then(|mut file| file.read_to_string(&mut head_ref_contents))
-            .context("Unable to create commit. Unable to read head ref")?;
-        if !head_ref_contents.is_empty() {
-            let head_ref_hash = Hash::from_hex(&head_ref_contents)
-                .context("Unable to create commit. head ref is not a valid hash")?;
-            parent_hashes.push(head_ref_hash);
-        }
-        let tree = Tree::create(index)?;
-        let message: String = message.into();
+        Self::create_with_progress(index, message, author, committer, &mut NoProgress)
+    }
 
-        let serialized_data =
-            Commit::serialize(&author, &committer, &parent_hashes, &tree, &message);
+    /// Like [`Commit::create`], reporting each staged file written into the
+    /// tree to `progress`.
+    pub fn create_with_progress(
+        index: &Index,
+        message: impl Into<String>,
+        author: Signature,
+        committer: Signature,
+        progress: &mut dyn Progress,
+    ) -> Result<Self> {
+        let head_ref = resolve_name("HEAD").context("Unable to create commit")?;
+        let parent_hashes: Vec<Hash> = read_ref(&head_ref)
+            .context("Unable to create commit")?
+            .into_iter()
+            .collect();
+        let tree = Tree::from_index_with_progress(index, progress)?;
 
-        let hash = Hash::of(&serialized_data);
-        let serialized_data = compress(&serialized_data)
-            .context("Unable to create commit. Unable to compress serialized data")?;
-        let object_path = hash.object_path();
-        if let Some(parent) = object_path.parent() {
-            fs::create_dir_all(parent)
-                .context("Unable to create commit. Unable to create object file")?;
-        }
+        let commit =
+            Commit::create_from_tree(*tree.hash(), parent_hashes, message, author, committer)?;
 
-        File::create(hash.object_path())
-            .and_then(|mut file| file.write_all(&serialized_data))
-            .context("Unable to create commit. Unable to write to object file")?;
+        update_ref(&head_ref, &commit.hash, commit.parent_hashes.first())
+            .context("Unable to create commit")?;
 
-        File::create(head_ref_path())
-            .and_then(|mut file| file.write_all(hash.to_hex().as_bytes()))
-            .context("Unable to create commit. Unable to write head ref")?;
+        Ok(commit)
+    }
+
+    /// Replaces the commit HEAD points to with a commit of `index` that has
+    /// the same parents and author, and moves HEAD's ref to it.
+    pub fn amend_with_progress(
+        index: &Index,
+        message: impl Into<String>,
+        committer: Signature,
+        progress: &mut dyn Progress,
+    ) -> Result<Self> {
+        let head_ref = resolve_name("HEAD").context("Unable to amend commit")?;
+        let Some(head_hash) = read_ref(&head_ref).context("Unable to amend commit")? else {
+            bail!("Unable to amend commit. {head_ref} does not have any commits yet");
+        };
+        let head = Commit::load(&head_hash).context("Unable to amend commit")?;
+        let tree = Tree::from_index_with_progress(index, progress)?;
+
+        let commit = Commit::create_from_tree(
+            *tree.hash(),
+            head.parent_hashes,
+            message,
+            head.author,
+            committer,
+        )?;
+
+        update_ref(&head_ref, &commit.hash, Some(&head_hash)).context("Unable to amend commit")?;
+
+        Ok(commit)
+    }
+
+    /// Stores a commit of an existing tree with the given parents. Unlike
+    /// [`Commit::create`] no ref is moved.
+    pub fn create_from_tree(
+        tree_hash: Hash,
+        parent_hashes: Vec<Hash>,
+        message: impl Into<String>,
+        author: Signature,
+        committer: Signature,
+    ) -> Result<Self> {
+        let message: String = message.into();
+        let body = Commit::serialize(&author, &committer, &parent_hashes, &tree_hash, &message);
+        let hash = write_object(ObjectKind::Commit, &body).context("Unable to create commit")?;
 
         let commit = Self {
-            _message: message,
-            tree_hash: *tree.hash(),
+            raw_message: message.as_bytes().to_vec(),
+            message,
+            encoding: None,
+            tree_hash,
             hash,
             parent_hashes,
+            raw_author: author.serialize_as(SignatureKind::Author).into_bytes(),
             author,
-            _committer: committer,
+            committer,
         };
         Ok(commit)
     }
 
     pub fn load(hash: &Hash) -> Result<Self> {
-        let commit_path = hash.object_path();
-        let contents =
-            fs::read(commit_path).context("Unable to load commit. Unable to read object file")?;
-        let contents =
-            decompress(&contents).context("Unable to load commit. Unable to decompress object")?;
-        Commit::deserialize(contents)
+        let (kind, body) = read_object(hash).context("Unable to load commit")?;
+        if kind != ObjectKind::Commit {
+            return Err(RygitError::UnexpectedObjectKind {
+                hash: *hash,
+                expected: ObjectKind::Commit,
+                actual: kind,
+            })
+            .context("Unable to load commit");
+        }
+        Commit::deserialize(*hash, body)
     }
 
-    fn deserialize(serialized_data: Vec<u8>) -> Result<Self> {
-        let serialized_data = String::from_utf8(serialized_data)
-            .context("Unable to parse commit file. Contents are not valid UTF-8")?;
-
+    fn deserialize(hash: Hash, body: Vec<u8>) -> Result<Self> {
         let invalid_format_message = "Unable to parse commit file. Invalid format";
-        let mut parts = serialized_data.split('\0');
-        let header = parts.next().context(invalid_format_message)?;
-        let body = parts.next().context(invalid_format_message)?;
-
-        // Ensure header is in correct format
-        let mut header_parts = header.split(" ");
-        let label = header_parts.next().context(invalid_format_message)?;
-        if label != "commit" {
-            bail!(invalid_format_message)
-        }
-        header_parts.next().context(invalid_format_message)?;
+
+        // The headers end at the first empty line; everything after it is the
+        // message
+        let headers_end = body
+            .windows(2)
+            .position(|w| w == b"\n\n")
+            .context(invalid_format_message)?;
+        let raw_message = body[headers_end + 2..].to_vec();
+        let mut header_lines = body[..headers_end].split(|&c| c == b'\n').peekable();
 
         // Parse tree hash
-        let mut body_lines = body.lines().peekable();
-        let tree_line = body_lines.next().context(invalid_format_message)?;
-        let tree_hash = {
-            let mut parts = tree_line.split(" ");
-            let label = parts.next().context(invalid_format_message)?;
-            if label != "tree" {
-                bail!(invalid_format_message)
-            }
-            let hash = parts.next().context(invalid_format_message)?;
-            Hash::from_hex(hash).context(invalid_format_message)?
-        };
+        let tree_line = header_lines.next().context(invalid_format_message)?;
+        let tree_hash = tree_line
+            .strip_prefix(b"tree ")
+            .context(invalid_format_message)?;
+        let tree_hash = std::str::from_utf8(tree_hash).context(invalid_format_message)?;
+        let tree_hash = Hash::from_hex(tree_hash).context(invalid_format_message)?;
 
         // Parse parent hashes
         let mut parent_hashes = vec![];
-        let mut peek = body_lines.peek().context(invalid_format_message)?;
-
-        while peek.starts_with("parent") {
-            let parent_line = body_lines.next().context(invalid_format_message)?;
-            let mut parts = parent_line.split(" ");
-            let label = parts.next().context(invalid_format_message)?;
-            if label != "parent" {
-                bail!(invalid_format_message)
-            }
-            let hash = parts.next().context(invalid_format_message)?;
-            let hash = Hash::from_hex(hash).context(invalid_format_message)?;
-            parent_hashes.push(hash);
-            peek = body_lines.peek().context(invalid_format_message)?;
+        while let Some(parent_hash) = header_lines
+            .peek()
+            .and_then(|line| line.strip_prefix(b"parent "))
+        {
+            let parent_hash = std::str::from_utf8(parent_hash).context(invalid_format_message)?;
+            parent_hashes.push(Hash::from_hex(parent_hash).context(invalid_format_message)?);
+            header_lines.next();
         }
 
-        // Parse signatures
-        let author_line = body_lines.next().context(invalid_format_message)?;
-        let author = Signature::deserialize(author_line).context(invalid_format_message)?;
-        let committer_line = body_lines.next().context(invalid_format_message)?;
-        let committer = Signature::deserialize(committer_line).context(invalid_format_message)?;
-
-        // Skip the empty line
-        body_lines.next().context(invalid_format_message)?;
-
-        let message = body_lines.collect::<Vec<_>>().join("\n");
+        // Parse signatures. Names in a legacy encoding are decoded lossily;
+        // the raw author line is kept for callers that need the exact bytes
+        let raw_author = header_lines
+            .next()
+            .context(invalid_format_message)?
+            .to_vec();
+        let author = Signature::deserialize(&String::from_utf8_lossy(&raw_author))
+            .context(invalid_format_message)?;
+        let committer_line = header_lines.next().context(invalid_format_message)?;
+        let committer = Signature::deserialize(&String::from_utf8_lossy(committer_line))
+            .context(invalid_format_message)?;
+
+        // Optional headers
+        let mut encoding = None;
+        for line in header_lines {
+            if let Some(value) = line.strip_prefix(b"encoding ") {
+                encoding = Some(String::from_utf8_lossy(value).to_string());
+            }
+        }
 
-        let hash = Hash::of(serialized_data.as_bytes());
+        // Try UTF-8 first and fall back to lossy decoding
+        let message = String::from_utf8_lossy(&raw_message).to_string();
 
         Ok(Self {
             hash,
             tree_hash,
             parent_hashes,
             author,
-            _committer: committer,
-            _message: message,
+            raw_author,
+            committer,
+            message,
+            raw_message,
+            encoding,
         })
     }
 
@@ -168,10 +218,10 @@ impl Commit {
         author: &Signature,
         committer: &Signature,
         parent_hashes: &[Hash],
-        tree: &Tree,
+        tree_hash: &Hash,
         message: impl Into<String>,
     ) -> Vec<u8> {
-        let mut serialized_body = vec![format!("tree {}", tree.hash().to_hex())];
+        let mut serialized_body = vec![format!("tree {}", tree_hash.to_hex())];
         for parent_hash in parent_hashes.iter() {
             serialized_body.push(format!("parent {}", parent_hash.to_hex()));
         }
@@ -179,16 +229,16 @@ impl Commit {
         serialized_body.push(committer.serialize_as(SignatureKind::Committer));
         serialized_body.push(String::new());
         serialized_body.push(message.into());
-        let serialized_body = serialized_body.join("\n");
-        let serialized_body_len = serialized_body.len();
 
-        format!("commit {serialized_body_len}\0{serialized_body}",)
-            .as_bytes()
-            .to_vec()
+        serialized_body.join("\n").into_bytes()
+    }
+
+    pub fn tree_hash(&self) -> &Hash {
+        &self.tree_hash
     }
 
     pub fn tree(&self) -> Result<Tree> {
-        Tree::load(self.tree_hash.object_path())
+        Tree::load(&self.tree_hash)
     }
 
     pub fn hash(&self) -> &Hash {
@@ -199,20 +249,170 @@ impl Commit {
         &self.author
     }
 
+    pub fn committer(&self) -> &Signature {
+        &self.committer
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The first line of the message.
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+
+    /// The `Key: value` trailers at the end of the message, in order.
+    pub fn trailers(&self) -> Vec<(String, String)> {
+        trailers::parse(&self.message)
+    }
+
+    /// The author line exactly as stored, including the `author ` label.
+    pub fn raw_author(&self) -> &[u8] {
+        &self.raw_author
+    }
+
+    /// The message bytes exactly as stored, which may not be UTF-8.
+    pub fn raw_message(&self) -> &[u8] {
+        &self.raw_message
+    }
+
+    /// The encoding named by the commit's `encoding` header, if any.
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
     pub fn parents(&self) -> Result<Vec<Commit>> {
         self.parent_hashes.iter().map(Commit::load).collect()
     }
+
+    pub fn parent_hashes(&self) -> &[Hash] {
+        &self.parent_hashes
+    }
+
+    /// Collects `hash` and every commit reachable from it through parents.
+    pub fn ancestors(hash: &Hash) -> Result<HashSet<Hash>> {
+        CommitCache::new()?.ancestors(hash)
+    }
+
+    /// Finds the best common ancestor of two commits: a commit reachable from
+    /// both that isn't itself an ancestor of another common ancestor. Returns
+    /// `None` when the histories are unrelated.
+    pub fn merge_base(a: &Hash, b: &Hash) -> Result<Option<Hash>> {
+        CommitCache::new()?.merge_base(a, b)
+    }
+
+    /// Whether `ancestor` is `descendant` or reachable from it.
+    pub fn is_ancestor(ancestor: &Hash, descendant: &Hash) -> Result<bool> {
+        Ok(Commit::merge_base(ancestor, descendant)? == Some(*ancestor))
+    }
+}
+
+/// Commits loaded during a history traversal, so a commit reached more than
+/// once is only read from disk the first time. Commits at the boundary of a
+/// shallow clone are loaded without parents, ending the traversal there.
+pub struct CommitCache {
+    commits: HashMap<Hash, Commit>,
+    shallow: HashSet<Hash>,
+}
+
+impl CommitCache {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            commits: HashMap::new(),
+            shallow: shallow::read()?,
+        })
+    }
+
+    pub fn load(&mut self, hash: &Hash) -> Result<&Commit> {
+        if !self.commits.contains_key(hash) {
+            let mut commit = Commit::load(hash)?;
+            if self.shallow.contains(hash) {
+                commit.parent_hashes.clear();
+            }
+            self.commits.insert(*hash, commit);
+        }
+
+        Ok(&self.commits[hash])
+    }
+
+    /// The number of commits read from disk so far.
+    pub fn len(&self) -> usize {
+        self.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+
+    /// Like [`Commit::ancestors`], loading through the cache.
+    pub fn ancestors(&mut self, hash: &Hash) -> Result<HashSet<Hash>> {
+        let mut ancestors = HashSet::new();
+        let mut pending = VecDeque::from([*hash]);
+        while let Some(hash) = pending.pop_front() {
+            if !ancestors.insert(hash) {
+                continue;
+            }
+            pending.extend(self.load(&hash)?.parent_hashes());
+        }
+
+        Ok(ancestors)
+    }
+
+    /// Like [`Commit::merge_base`], loading through the cache.
+    pub fn merge_base(&mut self, a: &Hash, b: &Hash) -> Result<Option<Hash>> {
+        let a_ancestors = self.ancestors(a)?;
+        if a_ancestors.contains(b) {
+            return Ok(Some(*b));
+        }
+
+        // Walk b's history breadth first, stopping at the first common commit
+        // on each path
+        let mut candidates = vec![];
+        let mut visited = HashSet::new();
+        let mut pending = VecDeque::from([*b]);
+        while let Some(hash) = pending.pop_front() {
+            if !visited.insert(hash) {
+                continue;
+            }
+            if a_ancestors.contains(&hash) {
+                candidates.push(hash);
+                continue;
+            }
+            pending.extend(self.load(&hash)?.parent_hashes());
+        }
+
+        for candidate in candidates.iter() {
+            let mut is_best = true;
+            for other in candidates.iter().filter(|c| *c != candidate) {
+                if self.ancestors(other)?.contains(candidate) {
+                    is_best = false;
+                    break;
+                }
+            }
+            if is_best {
+                return Ok(Some(*candidate));
+            }
+        }
+
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::Read};
+    use std::{
+        fs::{self, File},
+        io::Read,
+    };
 
-    use anyhow::{Ok, Result};
+    use anyhow::{Ok, Result, bail};
 
     use crate::{
-        objects::{Object, tree::TreeEntry},
+        compression::compress,
+        objects::{Object, blob::FILES_HASHED, serialize_object, tree::TreeEntry},
         paths::head_ref_path,
+        revparse,
         test_utils::TestRepo,
     };
 
@@ -268,13 +468,13 @@ mod tests {
         let head_ref_hash = Hash::from_hex(&head_ref_commit)?;
         assert_eq!(first_commit.hash, head_ref_hash);
 
-        assert_eq!("Initial commit", first_commit._message);
+        assert_eq!("Initial commit", first_commit.message);
 
         assert_eq!("Larry Sellers", first_commit.author.name());
         assert_eq!("l.sellers@example.com", first_commit.author.email());
 
-        assert_eq!("Donny Kerabatsos", first_commit._committer.name());
-        assert_eq!("d.kerabatsos@example.com", first_commit._committer.email());
+        assert_eq!("Donny Kerabatsos", first_commit.committer.name());
+        assert_eq!("d.kerabatsos@example.com", first_commit.committer.email());
 
         let repo = repo.file("t.txt", "t")?;
         let author = Signature::new("Leroy Jenkins", "l.jenkins@example.com");
@@ -311,4 +511,127 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_create_commits_the_index() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "staged")?
+            .stage("a.txt")?
+            .file("a.txt", "modified")?
+            .file("untracked.txt", "untracked")?
+            .commit("Initial commit")?;
+
+        let commit = Commit::load(&revparse::resolve("HEAD")?)?;
+        let tree = commit.tree()?;
+        assert_eq!(1, tree.entries().len());
+        assert_tree_entry_blob(&tree.entries()[0], "a.txt", b"staged");
+        assert_eq!("modified", fs::read_to_string(repo.path().join("a.txt"))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_does_not_hash_staged_files() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("subdir/b.txt", "b")?
+            .stage(".")?;
+
+        let hashed = FILES_HASHED.with(|count| count.get());
+        assert!(hashed >= 2, "staging should hash both files");
+        repo.commit("Initial commit")?;
+        assert_eq!(hashed, FILES_HASHED.with(|count| count.get()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accessors_after_load() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let parent = revparse::resolve("HEAD")?;
+        let tree_hash = *Commit::load(&parent)?.tree_hash();
+        let author = Signature::new("Larry Sellers", "l.sellers@example.com");
+        let committer = Signature::new("Donny Kerabatsos", "d.kerabatsos@example.com");
+
+        let created = Commit::create_from_tree(
+            tree_hash,
+            vec![parent],
+            "Subject\n\nBody",
+            author,
+            committer,
+        )?;
+        let commit = Commit::load(created.hash())?;
+
+        assert_eq!(created.hash(), commit.hash());
+        assert_eq!("Subject\n\nBody", commit.message());
+        assert_eq!(&tree_hash, commit.tree_hash());
+        assert_eq!(&[parent], commit.parent_hashes());
+        assert_eq!("Larry Sellers", commit.author().name());
+        assert_eq!("d.kerabatsos@example.com", commit.committer().email());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_non_utf8_commit() -> Result<()> {
+        let _repo = TestRepo::new()?;
+
+        // "café" and "Müller" in ISO-8859-1
+        let mut body = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n".to_vec();
+        body.extend_from_slice(b"author M\xfcller <m@example.com> 1700000000 +0000\n");
+        body.extend_from_slice(b"committer M\xfcller <m@example.com> 1700000000 +0000\n");
+        body.extend_from_slice(b"encoding ISO-8859-1\n\ncaf\xe9");
+        let serialized = serialize_object(ObjectKind::Commit, &body);
+        let hash = Hash::of(&serialized);
+        let object_path = hash.object_path();
+        fs::create_dir_all(object_path.parent().unwrap())?;
+        fs::write(object_path, compress(&serialized)?)?;
+
+        let commit = Commit::load(&hash)?;
+        assert_eq!(Some("ISO-8859-1"), commit.encoding());
+        assert_eq!(b"caf\xe9", commit.raw_message());
+        assert_eq!("caf\u{fffd}", commit.message);
+        assert_eq!(
+            b"author M\xfcller <m@example.com> 1700000000 +0000",
+            commit.raw_author()
+        );
+        assert_eq!("M\u{fffd}ller", commit.author().name());
+
+        Ok(())
+    }
+
+    fn head_hash() -> Result<Hash> {
+        Hash::from_hex(&fs::read_to_string(head_ref_path())?)
+    }
+
+    #[test]
+    fn test_merge_base() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let base = head_hash()?;
+        repo.branch("test")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Commit on master")?;
+        let master = head_hash()?;
+        repo.switch("test")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Commit on test")?;
+        let test = head_hash()?;
+
+        assert_eq!(Some(base), Commit::merge_base(&master, &test)?);
+        assert_eq!(Some(base), Commit::merge_base(&test, &master)?);
+        assert_eq!(Some(base), Commit::merge_base(&base, &master)?);
+        assert!(Commit::is_ancestor(&base, &test)?);
+        assert!(Commit::is_ancestor(&test, &test)?);
+        assert!(!Commit::is_ancestor(&master, &test)?);
+
+        Ok(())
+    }
 }
@@ -1,8 +1,19 @@
-use strum::AsRefStr;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, bail};
+use strum::{AsRefStr, Display, EnumString};
+use walkdir::WalkDir;
 
 use crate::{
+    compression::{compress, decompress},
+    error::RygitError,
     hash::Hash,
-    objects::{blob::Blob, tree::Tree},
+    objects::{blob::Blob, commit::Commit, tree::Tree},
+    paths::objects_path,
 };
 
 pub mod blob;
@@ -24,3 +35,298 @@ impl Object {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ObjectKind {
+    Blob,
+    Tree,
+    Commit,
+}
+
+/// A stored object of any kind, parsed according to its kind.
+pub enum AnyObject {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Box<Commit>),
+}
+
+/// Loads and parses the object `hash` whatever its kind.
+pub fn load_any(hash: &Hash) -> Result<AnyObject> {
+    let (kind, _) = read_object(hash)?;
+    let object = match kind {
+        ObjectKind::Blob => AnyObject::Blob(Blob::load(hash)),
+        ObjectKind::Tree => AnyObject::Tree(Tree::load(hash)?),
+        ObjectKind::Commit => AnyObject::Commit(Box::new(Commit::load(hash)?)),
+    };
+
+    Ok(object)
+}
+
+/// Finds the file holding an object, looking in the repository's own object
+/// directory first and then in every directory listed in
+/// `objects/info/alternates`.
+pub fn locate(hash: &Hash) -> Result<Option<PathBuf>> {
+    locate_in(objects_path(), hash)
+}
+
+/// Like [`locate`], but searches the object directory at `objects_path`,
+/// which may belong to another repository.
+pub fn locate_in(objects_path: impl AsRef<Path>, hash: &Hash) -> Result<Option<PathBuf>> {
+    let objects_path = objects_path.as_ref();
+    let hash_hex = hash.to_hex();
+    let object_path = objects_path.join(&hash_hex[0..2]).join(&hash_hex[2..]);
+    if object_path.exists() {
+        return Ok(Some(object_path));
+    }
+
+    for alternate in alternate_object_paths(objects_path)? {
+        let object_path = alternate.join(&hash_hex[0..2]).join(&hash_hex[2..]);
+        if object_path.exists() {
+            return Ok(Some(object_path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads an object and splits it into its kind and body (the content after
+/// the `<type> <size>\0` header).
+pub fn read_object(hash: &Hash) -> Result<(ObjectKind, Vec<u8>)> {
+    read_object_in(objects_path(), hash)
+}
+
+/// Like [`read_object`], but reads from the object directory at
+/// `objects_path`, which may belong to another repository.
+pub fn read_object_in(
+    objects_path: impl AsRef<Path>,
+    hash: &Hash,
+) -> Result<(ObjectKind, Vec<u8>)> {
+    let object_path = locate_in(objects_path, hash)?.ok_or(RygitError::ObjectNotFound(*hash))?;
+    let compressed =
+        fs::read(object_path).with_context(|| format!("Unable to read object {hash}"))?;
+    let contents =
+        decompress(&compressed).with_context(|| format!("Unable to decompress object {hash}"))?;
+
+    parse_object(&contents).with_context(|| format!("Unable to parse object {hash}"))
+}
+
+fn parse_object(contents: &[u8]) -> Result<(ObjectKind, Vec<u8>)> {
+    let header_end = contents
+        .iter()
+        .position(|&c| c == b'\0')
+        .context("Invalid object header")?;
+    let header = std::str::from_utf8(&contents[..header_end]).context("Invalid object header")?;
+    let (kind, size) = header.split_once(' ').context("Invalid object header")?;
+    let kind = ObjectKind::from_str(kind).with_context(|| format!("Unknown object type {kind}"))?;
+    let size: usize = size.parse().context("Invalid object size")?;
+
+    let body = contents[header_end + 1..].to_vec();
+    if body.len() != size {
+        bail!(
+            "Object size mismatch. Header says {size} bytes but found {}",
+            body.len()
+        );
+    }
+
+    Ok((kind, body))
+}
+
+/// Serializes an object body with its `<type> <size>\0` header.
+pub fn serialize_object(kind: ObjectKind, body: &[u8]) -> Vec<u8> {
+    let header = format!("{kind} {}\0", body.len());
+    let mut serialized = Vec::with_capacity(header.len() + body.len());
+    serialized.extend_from_slice(header.as_bytes());
+    serialized.extend_from_slice(body);
+
+    serialized
+}
+
+/// Stores an object body under its hash, unless the object already exists,
+/// and returns the hash.
+pub fn write_object(kind: ObjectKind, body: &[u8]) -> Result<Hash> {
+    let serialized = serialize_object(kind, body);
+    let hash = Hash::of(&serialized);
+    if locate(&hash)?.is_none() {
+        let compressed = compress(&serialized)
+            .with_context(|| format!("Unable to write {kind}. Unable to compress object"))?;
+        let object_path = hash.object_path();
+        fs::create_dir_all(object_path.parent().unwrap())
+            .and_then(|_| fs::write(&object_path, compressed))
+            .with_context(|| format!("Unable to write {kind}. Unable to create object file"))?;
+    }
+
+    Ok(hash)
+}
+
+/// How much of a blob [`is_text`] looks at.
+const TEXT_DETECTION_LENGTH: usize = 8000;
+
+/// Guesses whether content is text from its first few kilobytes: text has no
+/// NUL bytes and is valid UTF-8, allowing for a character cut off at the end
+/// of the sample.
+pub fn is_text(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(TEXT_DETECTION_LENGTH)];
+    if sample.contains(&0) {
+        return false;
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(error) => error.error_len().is_none() && sample.len() < bytes.len(),
+    }
+}
+
+/// Reads the object directories listed in `objects/info/alternates`,
+/// following alternates of alternates. Relative entries are resolved against
+/// the object directory that lists them.
+fn alternate_object_paths(objects_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut alternates = vec![];
+    let mut pending = vec![objects_path.to_path_buf()];
+    while let Some(objects_path) = pending.pop() {
+        let alternates_path = objects_path.join("info").join("alternates");
+        if !alternates_path.exists() {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&alternates_path)
+            .with_context(|| format!("Unable to read alternates {}", alternates_path.display()))?;
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let alternate = objects_path.join(line);
+            if alternate != objects_path && !alternates.contains(&alternate) {
+                alternates.push(alternate.clone());
+                pending.push(alternate);
+            }
+        }
+    }
+
+    Ok(alternates)
+}
+
+/// Lists the files of every loose object, which live at
+/// `objects/<2 hex chars>/<38 hex chars>`.
+pub fn loose_object_paths() -> Result<Vec<PathBuf>> {
+    let objects_path = objects_path();
+    if !objects_path.exists() {
+        return Ok(vec![]);
+    }
+
+    let entries = WalkDir::new(objects_path)
+        .min_depth(2)
+        .max_depth(2)
+        .into_iter()
+        .filter_entry(|e| {
+            e.path()
+                .parent()
+                .and_then(|p| p.file_name())
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.len() == 2 && n.chars().all(|c| c.is_ascii_hexdigit()))
+        });
+    let mut paths = vec![];
+    for entry in entries {
+        let entry = entry.context("Unable to read objects directory")?;
+        if entry.file_type().is_file() {
+            paths.push(entry.into_path());
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        objects::commit::Commit,
+        paths::{head_ref_path, objects_path},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_read_missing_object() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        let hash = Hash::of(b"missing");
+
+        let error = read_object(&hash).unwrap_err();
+        assert_eq!(
+            Some(&RygitError::ObjectNotFound(hash)),
+            error.downcast_ref::<RygitError>()
+        );
+        let Err(error) = Commit::load(&hash) else {
+            panic!("Expected a missing commit");
+        };
+        assert_eq!(
+            Some(&RygitError::ObjectNotFound(hash)),
+            error.downcast_ref::<RygitError>()
+        );
+
+        let blob_hash = write_object(ObjectKind::Blob, b"blob")?;
+        let Err(error) = Commit::load(&blob_hash) else {
+            panic!("Expected a non-commit object");
+        };
+        assert_eq!(
+            Some(&RygitError::UnexpectedObjectKind {
+                hash: blob_hash,
+                expected: ObjectKind::Commit,
+                actual: ObjectKind::Blob
+            }),
+            error.downcast_ref::<RygitError>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_text() {
+        assert!(is_text(b""));
+        assert!(is_text("caf\u{e9}\nna\u{ef}ve\n".as_bytes()));
+        assert!(!is_text(b"PK\x03\x04\0\0binary"));
+        assert!(!is_text(b"\xff\xfe not utf-8"));
+
+        let mut long = "a".repeat(TEXT_DETECTION_LENGTH - 1).into_bytes();
+        long.extend_from_slice("\u{e9}".as_bytes());
+        assert!(is_text(&long));
+    }
+
+    #[test]
+    fn test_read_object_through_alternates() -> Result<()> {
+        let shared = TestRepo::new()?;
+        shared
+            .file("a.txt", "shared")?
+            .stage(".")?
+            .commit("Shared commit")?;
+        let shared_objects_path = objects_path();
+        let commit_hash = Hash::from_hex(&fs::read_to_string(head_ref_path())?)?;
+
+        let _repo = TestRepo::new()?;
+        assert!(locate(&commit_hash)?.is_none());
+        assert!(read_object(&commit_hash).is_err());
+
+        let info_path = objects_path().join("info");
+        fs::create_dir_all(&info_path)?;
+        fs::write(
+            info_path.join("alternates"),
+            format!("{}\n", shared_objects_path.display()),
+        )?;
+
+        let (kind, _) = read_object(&commit_hash)?;
+        assert_eq!(ObjectKind::Commit, kind);
+        let commit = Commit::load(&commit_hash)?;
+        let tree = commit.tree()?;
+        let entry = tree.entries().first().unwrap();
+        match entry.object() {
+            Object::Blob(blob) => assert_eq!(b"shared".to_vec(), blob.body()?),
+            _ => panic!("Expected blob"),
+        }
+
+        Ok(())
+    }
+}
@@ -22,6 +22,11 @@ impl Signature {
         }
     }
 
+    /// The same identity at a different time, for backdated commits.
+    pub fn with_timestamp(self, timestamp: DateTime<FixedOffset>) -> Self {
+        Self { timestamp, ..self }
+    }
+
     pub fn serialize_as(&self, kind: SignatureKind) -> String {
         let kind = match kind {
             SignatureKind::Author => "author",
@@ -1,7 +1,6 @@
 use std::{
-    collections::HashMap,
-    fs::{self, File},
-    io::{Read, Write},
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
     iter::Peekable,
     path::{Path, PathBuf},
     str::FromStr,
@@ -10,14 +9,15 @@ use std::{
 
 use anyhow::{Context, Result, bail};
 use strum::{Display, EnumString};
-use walkdir::WalkDir;
 
 use crate::{
-    compression::{compress, decompress},
+    error::RygitError,
     hash::Hash,
     index::Index,
-    objects::{Object, blob::Blob, commit::Commit},
-    paths::{head_ref_path, repository_root_path, rygit_path},
+    objects::{Object, ObjectKind, blob::Blob, commit::Commit, read_object, write_object},
+    paths::repository_root_path,
+    progress::{NoProgress, Progress},
+    refs,
 };
 
 #[derive(Debug, Clone, PartialEq, Display, EnumString)]
@@ -37,32 +37,12 @@ pub struct TreeEntry {
 // entry format:
 // <mode> <file_name>\0<20 byte hash>
 impl TreeEntry {
-    pub fn create(path: impl AsRef<Path>, index: &Index) -> Result<Self> {
-        let path = path.as_ref();
-        let name = path
-            .file_name()
-            .with_context(|| format!("Could not get file name for {}", path.display()))?
-            .to_string_lossy()
-            .to_string();
-        if path.is_dir() {
-            let directory_tree = Tree::create_recursive(path, index)?;
-            let entry = TreeEntry {
-                object: Object::Tree(directory_tree),
-                name,
-            };
-            Ok(entry)
-        } else if path.is_file() {
-            let blob = Blob::create(path)?;
-            let entry = TreeEntry {
-                object: Object::Blob(blob),
-                name,
-            };
-            Ok(entry)
-        } else {
-            bail!(
-                "Unable to generate tree. {} Was neither a file nor a directory.",
-                path.display()
-            )
+    /// An entry named `name` for a stored blob or tree, for building a tree
+    /// with [`Tree::from_entries`].
+    pub fn new(name: impl Into<String>, object: Object) -> Self {
+        Self {
+            object,
+            name: name.into(),
         }
     }
 
@@ -78,6 +58,13 @@ impl TreeEntry {
         &self.name
     }
 
+    pub fn mode(&self) -> EntryMode {
+        match self.object {
+            Object::Blob(_) => EntryMode::File,
+            Object::Tree(_) => EntryMode::Directory,
+        }
+    }
+
     pub fn parse(serialized_data_iter: &mut Peekable<vec::IntoIter<u8>>) -> Result<Self> {
         let mode: String = serialized_data_iter
             .take_while(|&c| c != b' ')
@@ -93,15 +80,14 @@ impl TreeEntry {
 
         let entry_object_hash_bytes: Vec<_> = serialized_data_iter.take(20).collect();
         let entry_object_hash = Hash::new(entry_object_hash_bytes.try_into().unwrap());
-        let object_path = entry_object_hash.object_path();
 
         let object = match mode {
             EntryMode::File => {
-                let blob = Blob::load(entry_object_hash.object_path())?;
+                let blob = Blob::load(&entry_object_hash);
                 Object::Blob(blob)
             }
             EntryMode::Directory => {
-                let tree = Tree::load(&object_path)?;
+                let tree = Tree::load(&entry_object_hash)?;
                 Object::Tree(tree)
             }
         };
@@ -112,6 +98,69 @@ impl TreeEntry {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange {
+    Added {
+        path: PathBuf,
+        hash: Hash,
+    },
+    Deleted {
+        path: PathBuf,
+        hash: Hash,
+    },
+    Modified {
+        path: PathBuf,
+        old_hash: Hash,
+        new_hash: Hash,
+    },
+    Renamed {
+        old_path: PathBuf,
+        new_path: PathBuf,
+        old_hash: Hash,
+        new_hash: Hash,
+        similarity: u8,
+    },
+    Copied {
+        old_path: PathBuf,
+        new_path: PathBuf,
+        old_hash: Hash,
+        new_hash: Hash,
+        similarity: u8,
+    },
+}
+
+impl TreeChange {
+    pub fn path(&self) -> &Path {
+        match self {
+            TreeChange::Added { path, .. } => path,
+            TreeChange::Deleted { path, .. } => path,
+            TreeChange::Modified { path, .. } => path,
+            TreeChange::Renamed { new_path, .. } => new_path,
+            TreeChange::Copied { new_path, .. } => new_path,
+        }
+    }
+}
+
+/// The result of looking up a path in a tree.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TreeLookup<'a> {
+    /// The path is a file.
+    Found(&'a TreeEntry),
+    /// The path is a directory.
+    FoundTree(&'a Tree),
+    NotFound,
+}
+
+impl<'a> TreeLookup<'a> {
+    /// The file entry, if the path is a file.
+    pub fn blob(self) -> Option<&'a TreeEntry> {
+        match self {
+            TreeLookup::Found(entry) => Some(entry),
+            _ => None,
+        }
+    }
+}
+
 // tree format:
 // tree <content_length>\0<entries>
 #[derive(Debug, PartialEq, Eq)]
@@ -121,45 +170,25 @@ pub struct Tree {
 }
 
 impl Tree {
-    pub fn create(index: &Index) -> Result<Self> {
-        let root = repository_root_path();
-        Self::create_recursive(root, index)
+    /// Builds and stores the trees for the files staged in `index`. Only the
+    /// index is read; what's in the working tree doesn't matter.
+    pub fn from_index(index: &Index) -> Result<Self> {
+        Self::from_index_with_progress(index, &mut NoProgress)
     }
 
-    fn create_recursive(path: impl AsRef<Path>, index: &Index) -> Result<Self> {
-        let path = path.as_ref();
-        let rygit_path = rygit_path();
-        let directory_contents: Vec<_> = WalkDir::new(path)
-            .min_depth(1)
-            .max_depth(1)
-            .into_iter()
-            .filter_entry(|e| !e.path().starts_with(&rygit_path))
-            .collect::<Result<_, _>>()
-            .with_context(|| {
-                format!(
-                    "Unable to create tree. Unable to read directory contents for {}",
-                    path.display()
-                )
-            })?;
-        let mut entries: Vec<_> = directory_contents
-            .iter()
-            .map(|entry_path| TreeEntry::create(entry_path.path(), index))
-            .collect::<Result<_, _>>()?;
-        entries.sort_by(|a, b| a.name.cmp(&b.name));
-
-        let serialized_data = serialize(&entries);
-        let hash = Hash::of(&serialized_data);
-
-        if !hash.object_path().exists() {
-            let serialized_data = compress(&serialized_data)
-                .context("Unable to generate tree. Unable to compress object.")?;
-            fs::create_dir_all(hash.object_path().parent().unwrap())
-                .and_then(|_| File::create(hash.object_path()))
-                .and_then(|mut file| file.write_all(&serialized_data))
-                .context("Unable to generate tree. Unable to create object file")?;
+    /// Like [`Tree::from_index`], reporting each staged file to `progress`.
+    pub fn from_index_with_progress(index: &Index, progress: &mut dyn Progress) -> Result<Self> {
+        index.ensure_merged().context("Unable to build tree")?;
+        progress.start(None);
+        let mut files = BTreeMap::new();
+        for file in index.files() {
+            files.insert(file.path().to_path_buf(), *file.hash());
+            progress.tick();
         }
+        let tree = Self::from_paths(&files)?;
+        progress.finish();
 
-        Ok(Self { hash, entries })
+        Ok(tree)
     }
 
     pub fn hash(&self) -> &Hash {
@@ -171,34 +200,92 @@ impl Tree {
     }
 
     pub fn body(&self) -> Result<Vec<u8>> {
-        let path = self.hash.object_path();
-        let mut buf = vec![];
-        File::open(path).unwrap().read_to_end(&mut buf).unwrap();
-        let mut contents = decompress(&buf)?;
-        if let Some(pos) = contents.iter().position(|&x| x == 0) {
-            contents.drain(0..=pos);
-        } else {
-            bail!("Invalid blob header")
-        }
+        let (_, body) = read_object(&self.hash)?;
 
-        Ok(contents)
+        Ok(body)
     }
 
     pub fn current() -> Result<Option<Self>> {
-        let mut head_ref = String::new();
-        File::open(head_ref_path())
-            .and_then(|mut f| f.read_to_string(&mut head_ref))
-            .context("Unable to read head ref")?;
-        if head_ref.is_empty() {
+        let Some(head_ref_hash) = refs::resolve("HEAD").context("Unable to read head ref")? else {
             return Ok(None);
-        }
-
-        let head_ref_hash = Hash::from_hex(&head_ref)?;
+        };
         let head_commit = Commit::load(&head_ref_hash)?;
         let current_tree = head_commit.tree()?;
         Ok(Some(current_tree))
     }
 
+    /// Builds and stores the trees for a set of files given by paths relative
+    /// to the tree root.
+    pub fn from_paths(files: &BTreeMap<PathBuf, Hash>) -> Result<Self> {
+        let mut blobs = vec![];
+        let mut subdirectories: BTreeMap<String, BTreeMap<PathBuf, Hash>> = BTreeMap::new();
+        for (path, hash) in files {
+            let mut components = path.components();
+            let name = components
+                .next()
+                .with_context(|| format!("Unable to build tree. Invalid path {}", path.display()))?
+                .as_os_str()
+                .to_string_lossy()
+                .to_string();
+            let rest = components.as_path();
+            if rest.as_os_str().is_empty() {
+                blobs.push((name, *hash));
+            } else {
+                subdirectories
+                    .entry(name)
+                    .or_default()
+                    .insert(rest.to_path_buf(), *hash);
+            }
+        }
+
+        let mut entries = vec![];
+        for (name, hash) in blobs {
+            entries.push(TreeEntry::new(name, Object::Blob(Blob::load(&hash))));
+        }
+        for (name, files) in subdirectories.iter() {
+            entries.push(TreeEntry::new(
+                name.clone(),
+                Object::Tree(Tree::from_paths(files)?),
+            ));
+        }
+
+        Self::from_entries(entries)
+    }
+
+    /// Stores a tree of `entries`, whose objects must already be stored.
+    /// Entries may be given in any order, but each name must be a single,
+    /// unique path component.
+    pub fn from_entries(mut entries: Vec<TreeEntry>) -> Result<Self> {
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        for (i, entry) in entries.iter().enumerate() {
+            if entry.name.is_empty() || entry.name.contains(['/', '\0']) {
+                bail!(
+                    "Unable to build tree. Invalid entry name \"{}\"",
+                    entry.name
+                );
+            }
+            if i > 0 && entries[i - 1].name == entry.name {
+                bail!("Unable to build tree. Duplicate entry \"{}\"", entry.name);
+            }
+        }
+
+        let hash = write_object(ObjectKind::Tree, &serialize_body(&entries))
+            .context("Unable to build tree")?;
+
+        Ok(Self { hash, entries })
+    }
+
+    /// Lists every file in the tree by its path relative to the tree root.
+    pub fn files(&self) -> BTreeMap<PathBuf, Hash> {
+        self.diff_from_empty()
+            .into_iter()
+            .map(|change| match change {
+                TreeChange::Added { path, hash } => (path, hash),
+                _ => unreachable!("a diff from an empty tree only adds files"),
+            })
+            .collect()
+    }
+
     pub fn entries_flattened(&self) -> HashMap<PathBuf, Hash> {
         Tree::entries_flattened_recursive(self.entries(), repository_root_path())
     }
@@ -226,28 +313,33 @@ impl Tree {
         collected_entries
     }
 
-    pub fn load(object_path: impl AsRef<Path>) -> Result<Self> {
-        let mut serialized_data_buf = vec![];
-        let serialized_data = File::open(&object_path)
-            .and_then(|mut file| file.read_to_end(&mut serialized_data_buf))
-            .map_err(anyhow::Error::from)
-            .and_then(|_| decompress(&serialized_data_buf))
-            .context("Unable to load tree. Unable to read object file")?;
-
-        let hash = Hash::of(&serialized_data);
-        let mut serialized_data_iter = serialized_data.into_iter().peekable();
-        parse_header(&mut serialized_data_iter)?;
+    pub fn load(hash: &Hash) -> Result<Self> {
+        let (kind, body) = read_object(hash).context("Unable to load tree")?;
+        if kind != ObjectKind::Tree {
+            return Err(RygitError::UnexpectedObjectKind {
+                hash: *hash,
+                expected: ObjectKind::Tree,
+                actual: kind,
+            })
+            .context("Unable to load tree");
+        }
 
+        let mut serialized_data_iter = body.into_iter().peekable();
         let mut entries = vec![];
         while serialized_data_iter.peek().is_some() {
             let entry = TreeEntry::parse(&mut serialized_data_iter)?;
             entries.push(entry);
         }
 
-        Ok(Tree { entries, hash })
+        Ok(Tree {
+            entries,
+            hash: *hash,
+        })
     }
 
-    pub fn find(&self, path: impl AsRef<Path>) -> Result<Option<&TreeEntry>> {
+    /// Looks up `path`, relative to the tree root or absolute within the
+    /// repository, telling apart a file, a directory and nothing at all.
+    pub fn find(&self, path: impl AsRef<Path>) -> Result<TreeLookup<'_>> {
         let mut path = path.as_ref();
         let repository_root = repository_root_path();
         if path.starts_with(&repository_root) {
@@ -261,68 +353,206 @@ impl Tree {
             let entry = tree.entries.iter().find(|e| e.name == name);
             let entry = match entry {
                 Some(e) => e,
-                None => return Ok(None),
+                None => return Ok(TreeLookup::NotFound),
             };
 
-            if components.peek().is_none() {
-                match &entry.object {
-                    Object::Blob(_) => return Ok(Some(entry)),
-                    _ => return Ok(None),
-                }
-            }
-
             match &entry.object {
                 Object::Tree(subtree) => tree = subtree,
-                _ => return Ok(None),
+                Object::Blob(_) if components.peek().is_none() => {
+                    return Ok(TreeLookup::Found(entry));
+                }
+                _ => return Ok(TreeLookup::NotFound),
             }
         }
 
-        Ok(None)
+        Ok(TreeLookup::FoundTree(tree))
+    }
+
+    /// The subtree at a directory path, or `None` if the path is missing or
+    /// a file. An empty path is the tree itself.
+    pub fn find_tree(&self, path: impl AsRef<Path>) -> Result<Option<&Tree>> {
+        match self.find(path)? {
+            TreeLookup::FoundTree(tree) => Ok(Some(tree)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Lists the file-level changes needed to turn this tree into `other`.
+    /// Paths are relative to the tree root and come out in sorted order.
+    /// Subtrees with identical hashes are skipped without being walked.
+    pub fn diff(&self, other: &Tree) -> Vec<TreeChange> {
+        let mut changes = vec![];
+        diff_entries(&self.entries, &other.entries, Path::new(""), &mut changes);
+        changes
+    }
+
+    /// Lists every file in the tree as added, as a diff from an empty tree
+    /// would.
+    pub fn diff_from_empty(&self) -> Vec<TreeChange> {
+        let mut changes = vec![];
+        diff_entries(&[], &self.entries, Path::new(""), &mut changes);
+        changes
     }
 }
 
-fn serialize(entries: &[TreeEntry]) -> Vec<u8> {
-    let mut body: Vec<u8> = vec![];
-    for entry in entries {
-        let mode = match entry.object {
-            Object::Blob(_) => EntryMode::File,
-            Object::Tree(_) => EntryMode::Directory,
+fn diff_entries(
+    old_entries: &[TreeEntry],
+    new_entries: &[TreeEntry],
+    base_path: &Path,
+    changes: &mut Vec<TreeChange>,
+) {
+    let mut old_entries = old_entries.iter().peekable();
+    let mut new_entries = new_entries.iter().peekable();
+    loop {
+        let ordering = match (old_entries.peek(), new_entries.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(old), Some(new)) => old.name.cmp(&new.name),
         };
-        let entry_header = format!("{} {}\0", mode, entry.name);
-        body.extend_from_slice(entry_header.as_bytes());
-        body.extend_from_slice(entry.object.hash().as_bytes());
-    }
 
-    let mut serialized_data = format!("tree {}\0", body.len()).as_bytes().to_vec();
-    serialized_data.extend_from_slice(&body);
+        match ordering {
+            Ordering::Less => {
+                let old = old_entries.next().unwrap();
+                push_entry_files(old, base_path, changes, |path, hash| TreeChange::Deleted {
+                    path,
+                    hash,
+                });
+            }
+            Ordering::Greater => {
+                let new = new_entries.next().unwrap();
+                push_entry_files(new, base_path, changes, |path, hash| TreeChange::Added {
+                    path,
+                    hash,
+                });
+            }
+            Ordering::Equal => {
+                let old = old_entries.next().unwrap();
+                let new = new_entries.next().unwrap();
+                if old.hash() == new.hash() {
+                    continue;
+                }
 
-    serialized_data
+                match (&old.object, &new.object) {
+                    (Object::Blob(old_blob), Object::Blob(new_blob)) => {
+                        changes.push(TreeChange::Modified {
+                            path: base_path.join(&old.name),
+                            old_hash: *old_blob.hash(),
+                            new_hash: *new_blob.hash(),
+                        });
+                    }
+                    (Object::Tree(old_tree), Object::Tree(new_tree)) => {
+                        diff_entries(
+                            old_tree.entries(),
+                            new_tree.entries(),
+                            &base_path.join(&old.name),
+                            changes,
+                        );
+                    }
+                    _ => {
+                        push_entry_files(old, base_path, changes, |path, hash| {
+                            TreeChange::Deleted { path, hash }
+                        });
+                        push_entry_files(new, base_path, changes, |path, hash| TreeChange::Added {
+                            path,
+                            hash,
+                        });
+                    }
+                }
+            }
+        }
+    }
 }
 
-fn parse_header(serialized_data_iter: &mut Peekable<vec::IntoIter<u8>>) -> Result<()> {
-    let label: String = serialized_data_iter
-        .take_while(|&c| c != b' ')
-        .map(|c| c as char)
-        .collect();
-    if label != "tree" {
-        bail!("Invalid tree header. Must start with \"tree\"")
+fn push_entry_files(
+    entry: &TreeEntry,
+    base_path: &Path,
+    changes: &mut Vec<TreeChange>,
+    change: impl Fn(PathBuf, Hash) -> TreeChange + Copy,
+) {
+    let path = base_path.join(&entry.name);
+    match &entry.object {
+        Object::Blob(blob) => changes.push(change(path, *blob.hash())),
+        Object::Tree(tree) => {
+            for subentry in tree.entries() {
+                push_entry_files(subentry, &path, changes, change);
+            }
+        }
     }
+}
 
-    serialized_data_iter
-        .take_while(|&c| c != b'\0')
-        .for_each(drop);
-    Ok(())
+fn serialize_body(entries: &[TreeEntry]) -> Vec<u8> {
+    let mut body: Vec<u8> = vec![];
+    for entry in entries {
+        let entry_header = format!("{} {}\0", entry.mode(), entry.name);
+        body.extend_from_slice(entry_header.as_bytes());
+        body.extend_from_slice(entry.object.hash().as_bytes());
+    }
+
+    body
 }
 
 #[cfg(test)]
 mod test {
 
-    use anyhow::Result;
+    use anyhow::{Result, bail};
 
-    use crate::test_utils::TestRepo;
+    use crate::test_utils::{CountingProgress, TestRepo};
 
     use super::*;
 
+    #[test]
+    fn test_from_entries() -> Result<()> {
+        let _repo = TestRepo::new()?;
+        let a = Blob::from_bytes(b"a")?;
+        let b = Blob::from_bytes(b"b")?;
+
+        let tree = Tree::from_entries(vec![
+            TreeEntry::new("b.txt", Object::Blob(Blob::load(b.hash()))),
+            TreeEntry::new("a.txt", Object::Blob(Blob::load(a.hash()))),
+        ])?;
+
+        assert_eq!(
+            vec!["a.txt", "b.txt"],
+            tree.entries()
+                .iter()
+                .map(TreeEntry::name)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(tree, Tree::load(tree.hash())?);
+        let nested = Tree::from_entries(vec![TreeEntry::new("dir", Object::Tree(tree))])?;
+        assert_eq!(nested, Tree::load(nested.hash())?);
+        assert_eq!(2, nested.files().len());
+
+        let blob = || Object::Blob(Blob::load(a.hash()));
+        assert!(Tree::from_entries(vec![TreeEntry::new("a/b", blob())]).is_err());
+        assert!(
+            Tree::from_entries(vec![
+                TreeEntry::new("a", blob()),
+                TreeEntry::new("a", blob())
+            ])
+            .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_index_with_progress() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("subdir/b.txt", "b")?
+            .file("subdir/nested/c.txt", "c")?
+            .stage(".")?;
+
+        let mut progress = CountingProgress::default();
+        Tree::from_index_with_progress(&Index::load()?, &mut progress)?;
+        assert_eq!(3, progress.ticks);
+        assert!(progress.finished);
+
+        Ok(())
+    }
+
     #[test]
     fn test_from_index() -> Result<()> {
         let repo = TestRepo::new()?;
@@ -335,7 +565,7 @@ mod test {
         index.add(repo.path().join("b.txt"))?;
         index.add(repo.path().join("subdir1/c.txt"))?;
 
-        let tree = Tree::create(&index)?;
+        let tree = Tree::from_index(&index)?;
 
         assert_eq!(3, tree.entries().len());
         let mut entries_iter = tree.entries().iter();
@@ -374,12 +604,56 @@ mod test {
         index.add(repo.path().join("a/a.txt"))?;
         index.add(repo.path().join("subdir/a.txt"))?;
 
-        let tree = Tree::create(&index)?;
+        let tree = Tree::from_index(&index)?;
+
+        let TreeLookup::Found(entry) = tree.find("a.txt")? else {
+            panic!("Expected a.txt to be a file");
+        };
+        assert_eq!("a.txt", entry.name());
+        assert!(tree.find("a/a.txt")?.blob().is_some());
+        assert!(
+            tree.find(repo.path().join("subdir/a.txt"))?
+                .blob()
+                .is_some()
+        );
+
+        let TreeLookup::FoundTree(subdir) = tree.find("subdir")? else {
+            panic!("Expected subdir to be a directory");
+        };
+        assert_eq!(1, subdir.entries().len());
 
-        assert!(tree.find("a.txt")?.is_some());
-        assert!(tree.find("a/a.txt")?.is_some());
-        assert!(tree.find("subdir/a.txt")?.is_some());
-        assert!(tree.find("b.pdf")?.is_none());
+        assert_eq!(TreeLookup::NotFound, tree.find("b.pdf")?);
+        assert_eq!(TreeLookup::NotFound, tree.find("a.txt/b.txt")?);
+        assert_eq!(TreeLookup::NotFound, tree.find("subdir/b.txt")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_tree() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("src/objects/blob.rs", "blob")?
+            .file("src/main.rs", "main")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let tree = Tree::current()?.unwrap();
+
+        let objects = tree.find_tree("src/objects")?.unwrap();
+        assert_eq!(
+            vec!["blob.rs"],
+            objects
+                .entries()
+                .iter()
+                .map(|e| e.name())
+                .collect::<Vec<_>>()
+        );
+        let src = tree.find_tree("src")?.unwrap();
+        assert_eq!(objects, src.find_tree("objects")?.unwrap());
+        assert_eq!(&tree, tree.find_tree("")?.unwrap());
+
+        assert!(tree.find_tree("src/missing")?.is_none());
+        assert!(tree.find_tree("a.txt")?.is_none());
 
         Ok(())
     }
@@ -402,4 +676,107 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_from_paths() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("a/a.txt", "b")?
+            .file("a/b/c.txt", "c")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let tree = Tree::current()?.unwrap();
+
+        let files = tree.files();
+        assert_eq!(
+            vec![
+                PathBuf::from("a/a.txt"),
+                PathBuf::from("a/b/c.txt"),
+                PathBuf::from("a.txt")
+            ],
+            files.keys().cloned().collect::<Vec<_>>()
+        );
+
+        let rebuilt = Tree::from_paths(&files)?;
+        assert_eq!(tree.hash(), rebuilt.hash());
+        assert_eq!(tree, Tree::load(rebuilt.hash())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .file("dir/c.txt", "c")?
+            .file("same/d.txt", "d")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let old_tree = Tree::current()?.unwrap();
+
+        repo.file("a.txt", "changed")?
+            .remove_file("b.txt")?
+            .file("dir/e.txt", "e")?
+            .file("f.txt", "f")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let new_tree = Tree::current()?.unwrap();
+
+        let old_a = old_tree.find("a.txt")?.blob().unwrap().hash();
+        let new_a = new_tree.find("a.txt")?.blob().unwrap().hash();
+        let expected = vec![
+            TreeChange::Modified {
+                path: PathBuf::from("a.txt"),
+                old_hash: *old_a,
+                new_hash: *new_a,
+            },
+            TreeChange::Deleted {
+                path: PathBuf::from("b.txt"),
+                hash: *old_tree.find("b.txt")?.blob().unwrap().hash(),
+            },
+            TreeChange::Added {
+                path: PathBuf::from("dir/e.txt"),
+                hash: *new_tree.find("dir/e.txt")?.blob().unwrap().hash(),
+            },
+            TreeChange::Added {
+                path: PathBuf::from("f.txt"),
+                hash: *new_tree.find("f.txt")?.blob().unwrap().hash(),
+            },
+        ];
+        assert_eq!(expected, old_tree.diff(&new_tree));
+        assert!(new_tree.diff(&new_tree).is_empty());
+
+        let reversed = new_tree.diff(&old_tree);
+        assert_eq!(4, reversed.len());
+        assert!(
+            matches!(&reversed[1], TreeChange::Added { path, .. } if path == Path::new("b.txt"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_file_replaced_by_directory() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a", "file")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let old_tree = Tree::current()?.unwrap();
+
+        repo.remove_file("a")?
+            .file("a/b.txt", "nested")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let new_tree = Tree::current()?.unwrap();
+
+        let changes = old_tree.diff(&new_tree);
+        assert_eq!(2, changes.len());
+        assert!(matches!(&changes[0], TreeChange::Deleted { path, .. } if path == Path::new("a")));
+        assert!(
+            matches!(&changes[1], TreeChange::Added { path, .. } if path == Path::new("a/b.txt"))
+        );
+
+        Ok(())
+    }
 }
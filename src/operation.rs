@@ -0,0 +1,292 @@
+use std::{fmt::Display, fs};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    hash::Hash,
+    merge::unresolved_conflicts,
+    objects::tree::Tree,
+    paths::{
+        bisect_bad_path, bisect_good_path, bisect_start_path, merge_head_path, merge_msg_path,
+        orig_head_path, rebase_state_path,
+    },
+};
+
+/// A multi-step operation that stopped on conflicts and is waiting for them
+/// to be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Merge,
+    Rebase,
+}
+
+impl Operation {
+    pub fn in_progress() -> Option<Self> {
+        if merge_head_path().exists() {
+            Some(Operation::Merge)
+        } else if rebase_state_path().exists() {
+            Some(Operation::Rebase)
+        } else {
+            None
+        }
+    }
+
+    /// Errors if another operation is waiting on conflict resolution.
+    pub fn ensure_none() -> Result<()> {
+        if let Some(operation) = Operation::in_progress() {
+            bail!(
+                "A {operation} is in progress. Run \"rygit {operation} --continue\" or \"rygit {operation} --abort\""
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors if any file in `tree` still has conflict markers.
+pub fn ensure_resolved(tree: &Tree) -> Result<()> {
+    let conflicts = unresolved_conflicts(tree)?;
+    if !conflicts.is_empty() {
+        let conflicts: Vec<_> = conflicts.iter().map(|p| p.display().to_string()).collect();
+        bail!(
+            "Conflict markers remain in: {}. Resolve them and stage the files first",
+            conflicts.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Merge => write!(f, "merge"),
+            Operation::Rebase => write!(f, "rebase"),
+        }
+    }
+}
+
+/// A conflicted merge, stored in `MERGE_HEAD`, `MERGE_MSG` and `ORIG_HEAD`.
+/// The conflicted files are recorded as stages in the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeState {
+    pub orig_head: Hash,
+    pub merge_head: Hash,
+    pub message: String,
+}
+
+impl MergeState {
+    pub fn save(&self) -> Result<()> {
+        fs::write(orig_head_path(), self.orig_head.to_hex())
+            .and_then(|_| fs::write(merge_head_path(), self.merge_head.to_hex()))
+            .and_then(|_| fs::write(merge_msg_path(), &self.message))
+            .context("Unable to save merge state")
+    }
+
+    pub fn load() -> Result<Self> {
+        let read_hash = |path| -> Result<Hash> {
+            let hex = fs::read_to_string(path)?;
+            Hash::from_hex(hex.trim())
+        };
+        let orig_head =
+            read_hash(orig_head_path()).context("Unable to load merge state. Invalid ORIG_HEAD")?;
+        let merge_head = read_hash(merge_head_path())
+            .context("Unable to load merge state. Invalid MERGE_HEAD")?;
+        let message = fs::read_to_string(merge_msg_path()).unwrap_or_default();
+
+        Ok(Self {
+            orig_head,
+            merge_head,
+            message,
+        })
+    }
+
+    /// Removes the merge state. `ORIG_HEAD` is kept so the merge can still be
+    /// referred to afterwards.
+    pub fn clear() -> Result<()> {
+        for path in [merge_head_path(), merge_msg_path()] {
+            if path.exists() {
+                fs::remove_file(&path).context("Unable to clear merge state")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A conflicted rebase, stored in `rebase-state` as `key value` lines. `onto`
+/// is the last commit replayed so far, `current` the commit that conflicted
+/// and `todo` the commits still to replay, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebaseState {
+    pub branch: String,
+    pub orig_head: Hash,
+    pub onto: Hash,
+    pub current: Option<Hash>,
+    pub todo: Vec<Hash>,
+}
+
+impl RebaseState {
+    pub fn save(&self) -> Result<()> {
+        let mut contents = format!(
+            "branch {}\norig-head {}\nonto {}\n",
+            self.branch,
+            self.orig_head.to_hex(),
+            self.onto.to_hex()
+        );
+        if let Some(current) = &self.current {
+            contents.push_str(&format!("current {}\n", current.to_hex()));
+        }
+        for hash in &self.todo {
+            contents.push_str(&format!("todo {}\n", hash.to_hex()));
+        }
+
+        fs::write(orig_head_path(), self.orig_head.to_hex())
+            .and_then(|_| fs::write(rebase_state_path(), contents))
+            .context("Unable to save rebase state")
+    }
+
+    pub fn load() -> Result<Self> {
+        let contents =
+            fs::read_to_string(rebase_state_path()).context("Unable to load rebase state")?;
+        let (mut branch, mut orig_head, mut onto, mut current, mut todo) =
+            (None, None, None, None, vec![]);
+        for line in contents.lines() {
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("Unable to load rebase state. Invalid line \"{line}\""))?;
+            match key {
+                "branch" => branch = Some(value.to_string()),
+                "orig-head" => orig_head = Some(Hash::from_hex(value)?),
+                "onto" => onto = Some(Hash::from_hex(value)?),
+                "current" => current = Some(Hash::from_hex(value)?),
+                "todo" => todo.push(Hash::from_hex(value)?),
+                _ => bail!("Unable to load rebase state. Unknown key \"{key}\""),
+            }
+        }
+
+        Ok(Self {
+            branch: branch.context("Unable to load rebase state. Branch missing")?,
+            orig_head: orig_head.context("Unable to load rebase state. Original head missing")?,
+            onto: onto.context("Unable to load rebase state. Onto missing")?,
+            current,
+            todo,
+        })
+    }
+
+    pub fn clear() -> Result<()> {
+        let path = rebase_state_path();
+        if path.exists() {
+            fs::remove_file(&path).context("Unable to clear rebase state")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A bisect session, stored in `BISECT_START` (the branch, or the commit
+/// when HEAD was detached, to return to), `BISECT_BAD` and `BISECT_GOOD`
+/// (one commit per line).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BisectState {
+    pub start: String,
+    pub bad: Option<Hash>,
+    pub good: Vec<Hash>,
+}
+
+impl BisectState {
+    pub fn in_progress() -> bool {
+        bisect_start_path().exists()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let good: String = self.good.iter().map(|hash| format!("{hash}\n")).collect();
+        fs::write(bisect_start_path(), &self.start)
+            .and_then(|_| fs::write(bisect_good_path(), good))
+            .and_then(|_| match &self.bad {
+                Some(bad) => fs::write(bisect_bad_path(), bad.to_hex()),
+                None => Ok(()),
+            })
+            .context("Unable to save bisect state")
+    }
+
+    pub fn load() -> Result<Self> {
+        let start = fs::read_to_string(bisect_start_path())
+            .context("Unable to load bisect state. Run \"rygit bisect start\" first")?;
+        let bad = match fs::read_to_string(bisect_bad_path()) {
+            Ok(hex) => Some(
+                Hash::from_hex(hex.trim())
+                    .context("Unable to load bisect state. Invalid BISECT_BAD")?,
+            ),
+            Err(_) => None,
+        };
+        let good = fs::read_to_string(bisect_good_path())
+            .unwrap_or_default()
+            .lines()
+            .map(|line| {
+                Hash::from_hex(line.trim())
+                    .context("Unable to load bisect state. Invalid BISECT_GOOD")
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            start: start.trim().to_string(),
+            bad,
+            good,
+        })
+    }
+
+    pub fn clear() -> Result<()> {
+        for path in [bisect_start_path(), bisect_bad_path(), bisect_good_path()] {
+            if path.exists() {
+                fs::remove_file(&path).context("Unable to clear bisect state")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{objects::commit::Commit, revparse, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_rebase_state_round_trip() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let first = revparse::resolve("HEAD~")?;
+        let second = revparse::resolve("HEAD")?;
+        assert_eq!(None, Operation::in_progress());
+
+        let state = RebaseState {
+            branch: "master".to_string(),
+            orig_head: second,
+            onto: first,
+            current: Some(second),
+            todo: vec![first, second],
+        };
+        state.save()?;
+        assert_eq!(Some(Operation::Rebase), Operation::in_progress());
+        assert!(Operation::ensure_none().is_err());
+        assert_eq!(state, RebaseState::load()?);
+        assert_eq!(
+            second,
+            *Commit::load(&revparse::resolve("ORIG_HEAD")?)?.hash()
+        );
+
+        RebaseState::clear()?;
+        assert_eq!(None, Operation::in_progress());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use strum::EnumString;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Silences informational output for the rest of the process. Errors and the
+/// output a command exists to produce are still printed.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// How a command renders its results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputFormat {
+    /// Text meant to be read by people.
+    #[default]
+    Human,
+    /// Structured JSON for tools and editor integrations.
+    Json,
+}
+
+/// Prints an informational line to stdout unless `--quiet` was given.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use info;
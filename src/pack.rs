@@ -0,0 +1,523 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use flate2::{Crc, bufread::ZlibDecoder};
+
+use crate::{
+    compression::compress,
+    hash::Hash,
+    objects::{ObjectKind, read_object, serialize_object, write_object},
+    paths::packs_path,
+};
+
+// pack format (version 2, objects are never stored as deltas):
+// PACK <version: u32> <object count: u32>
+// <object header: type and size varint><zlib compressed body>...
+// <pack checksum: SHA-1 of everything above>
+//
+// index format (version 2):
+// \xfftOc <version: u32> <fanout: 256 x u32>
+// <hashes: n x 20 bytes> <crc32s: n x u32> <offsets: n x u32> <large offsets: m x u64>
+// <pack checksum> <index checksum: SHA-1 of everything above>
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const INDEX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+const VERSION: u32 = 2;
+const PACK_HEADER_LEN: usize = 12;
+const INDEX_HEADER_LEN: usize = 8 + 256 * 4;
+const CHECKSUM_LEN: usize = 20;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PackEntry {
+    pub hash: Hash,
+    pub kind: ObjectKind,
+    pub size: usize,
+    pub packed_size: usize,
+    pub offset: u64,
+}
+
+struct IndexEntry {
+    hash: Hash,
+    crc32: u32,
+    offset: u64,
+}
+
+pub struct PackIndex {
+    entries: Vec<IndexEntry>,
+    pack_checksum: Hash,
+}
+
+impl PackIndex {
+    pub fn load(index_path: impl AsRef<Path>) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        let data = fs::read(index_path)
+            .with_context(|| format!("Unable to read pack index {}", index_path.display()))?;
+        if data.len() < INDEX_HEADER_LEN + 2 * CHECKSUM_LEN {
+            bail!("Invalid pack index. File is truncated");
+        }
+        if &data[0..4] != INDEX_SIGNATURE {
+            bail!("Invalid pack index. Missing index signature");
+        }
+        let version = read_u32(&data, 4);
+        if version != VERSION {
+            bail!("Unsupported pack index version {version}");
+        }
+
+        let checksum_start = data.len() - CHECKSUM_LEN;
+        if Hash::of(&data[..checksum_start]).as_bytes() != &data[checksum_start..] {
+            bail!("Invalid pack index. Index checksum mismatch");
+        }
+
+        let mut previous = 0;
+        for bucket in 0..256 {
+            let count = read_u32(&data, 8 + bucket * 4);
+            if count < previous {
+                bail!("Invalid pack index. Fanout table is not sorted");
+            }
+            previous = count;
+        }
+        let count = previous as usize;
+
+        let hashes_start = INDEX_HEADER_LEN;
+        let crcs_start = hashes_start + count * 20;
+        let offsets_start = crcs_start + count * 4;
+        let large_offsets_start = offsets_start + count * 4;
+        if large_offsets_start + 2 * CHECKSUM_LEN > data.len() {
+            bail!("Invalid pack index. File is truncated");
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let hash_start = hashes_start + i * 20;
+            let hash = Hash::new(data[hash_start..hash_start + 20].try_into().unwrap());
+            let crc32 = read_u32(&data, crcs_start + i * 4);
+            let offset = read_u32(&data, offsets_start + i * 4);
+            let offset = if offset & LARGE_OFFSET_FLAG == 0 {
+                offset as u64
+            } else {
+                let large_offset_start =
+                    large_offsets_start + (offset & !LARGE_OFFSET_FLAG) as usize * 8;
+                if large_offset_start + 8 + 2 * CHECKSUM_LEN > data.len() {
+                    bail!("Invalid pack index. Large offset for {hash} is out of bounds");
+                }
+                read_u64(&data, large_offset_start)
+            };
+
+            entries.push(IndexEntry {
+                hash,
+                crc32,
+                offset,
+            });
+        }
+
+        let pack_checksum_start = checksum_start - CHECKSUM_LEN;
+        let pack_checksum = Hash::new(
+            data[pack_checksum_start..checksum_start]
+                .try_into()
+                .unwrap(),
+        );
+
+        Ok(Self {
+            entries,
+            pack_checksum,
+        })
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Writes the given objects into a new pack under `objects/pack` and returns
+/// the path of its index.
+pub fn write_pack(hashes: &[Hash]) -> Result<PathBuf> {
+    let (pack, entries) = build_pack(hashes).context("Unable to write pack")?;
+    let pack_checksum = Hash::new(pack[pack.len() - CHECKSUM_LEN..].try_into().unwrap());
+    let index = serialize_index(&entries, &pack_checksum);
+
+    let packs_path = packs_path();
+    fs::create_dir_all(&packs_path)
+        .context("Unable to write pack. Unable to create pack directory")?;
+    let pack_path = packs_path.join(format!("pack-{pack_checksum}.pack"));
+    fs::write(&pack_path, pack).context("Unable to write pack. Unable to write pack file")?;
+    let index_path = pack_path.with_extension("idx");
+    fs::write(&index_path, index).context("Unable to write pack. Unable to write index file")?;
+
+    Ok(index_path)
+}
+
+/// Serializes the objects in `hashes` as a pack without writing an index,
+/// for carrying them somewhere else.
+pub fn serialize_pack(hashes: &[Hash]) -> Result<Vec<u8>> {
+    let (pack, _) = build_pack(hashes)?;
+
+    Ok(pack)
+}
+
+fn build_pack(hashes: &[Hash]) -> Result<(Vec<u8>, Vec<IndexEntry>)> {
+    let mut hashes = hashes.to_vec();
+    hashes.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    hashes.dedup();
+
+    let mut pack = vec![];
+    pack.extend_from_slice(PACK_SIGNATURE);
+    pack.extend_from_slice(&VERSION.to_be_bytes());
+    pack.extend_from_slice(&(hashes.len() as u32).to_be_bytes());
+
+    let mut entries = Vec::with_capacity(hashes.len());
+    for hash in hashes {
+        let (kind, body) = read_object(&hash)?;
+        let start = pack.len();
+        pack.extend_from_slice(&encode_entry_header(kind, body.len()));
+        pack.extend_from_slice(&compress(&body)?);
+
+        let mut crc = Crc::new();
+        crc.update(&pack[start..]);
+        entries.push(IndexEntry {
+            hash,
+            crc32: crc.sum(),
+            offset: start as u64,
+        });
+    }
+
+    let pack_checksum = Hash::of(&pack);
+    pack.extend_from_slice(pack_checksum.as_bytes());
+
+    Ok((pack, entries))
+}
+
+/// Stores every object in a serialized pack as a loose object, returning
+/// their hashes in pack order. The pack is read front to back, so it needs
+/// no index.
+pub fn unpack(pack: &[u8]) -> Result<Vec<Hash>> {
+    if pack.len() < PACK_HEADER_LEN + CHECKSUM_LEN || &pack[0..4] != PACK_SIGNATURE {
+        bail!("Invalid pack. Missing pack signature");
+    }
+    let version = read_u32(pack, 4);
+    if version != VERSION {
+        bail!("Unsupported pack version {version}");
+    }
+    let data_end = pack.len() - CHECKSUM_LEN;
+    if Hash::of(&pack[..data_end]).as_bytes() != &pack[data_end..] {
+        bail!("Invalid pack. Pack checksum mismatch");
+    }
+
+    let count = read_u32(pack, 8) as usize;
+    let mut hashes = Vec::with_capacity(count);
+    let mut position = PACK_HEADER_LEN;
+    for _ in 0..count {
+        if position >= data_end {
+            bail!(
+                "Invalid pack. Expected {count} objects but found {}",
+                hashes.len()
+            );
+        }
+        let (kind, size, header_len) = decode_entry_header(&pack[position..data_end])
+            .with_context(|| format!("Invalid object header at offset {position}"))?;
+        let mut decoder = ZlibDecoder::new(&pack[position + header_len..data_end]);
+        let mut body = vec![];
+        decoder
+            .read_to_end(&mut body)
+            .with_context(|| format!("Unable to decompress object at offset {position}"))?;
+        if body.len() != size {
+            bail!(
+                "Object at offset {position} declares {size} bytes but decompresses to {}",
+                body.len()
+            );
+        }
+
+        hashes.push(write_object(kind, &body)?);
+        position += header_len + decoder.total_in() as usize;
+    }
+    if position != data_end {
+        bail!("Invalid pack. Unexpected data after the last object");
+    }
+
+    Ok(hashes)
+}
+
+/// Lists the index files of every pack in the repository.
+pub fn pack_indexes() -> Result<Vec<PathBuf>> {
+    let packs_path = packs_path();
+    if !packs_path.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut indexes = vec![];
+    for entry in fs::read_dir(&packs_path).context("Unable to read pack directory")? {
+        let path = entry.context("Unable to read pack directory")?.path();
+        if path.extension().is_some_and(|e| e == "idx") {
+            indexes.push(path);
+        }
+    }
+    indexes.sort();
+
+    Ok(indexes)
+}
+
+/// Checks a pack against its index, returning the contained objects in pack
+/// order. Every object must sit at a valid offset, match its recorded CRC,
+/// decompress to its declared size, and hash to its indexed name.
+pub fn verify_pack(index_path: impl AsRef<Path>) -> Result<Vec<PackEntry>> {
+    let index_path = index_path.as_ref();
+    let index = PackIndex::load(index_path)?;
+    let pack_path = index_path.with_extension("pack");
+    let pack = fs::read(&pack_path)
+        .with_context(|| format!("Unable to read pack {}", pack_path.display()))?;
+
+    if pack.len() < PACK_HEADER_LEN + CHECKSUM_LEN || &pack[0..4] != PACK_SIGNATURE {
+        bail!("Invalid pack. Missing pack signature");
+    }
+    let version = read_u32(&pack, 4);
+    if version != VERSION {
+        bail!("Unsupported pack version {version}");
+    }
+    let count = read_u32(&pack, 8) as usize;
+    if count != index.object_count() {
+        bail!(
+            "Pack contains {count} objects but its index lists {}",
+            index.object_count()
+        );
+    }
+
+    let data_end = pack.len() - CHECKSUM_LEN;
+    if Hash::of(&pack[..data_end]).as_bytes() != &pack[data_end..] {
+        bail!("Invalid pack. Pack checksum mismatch");
+    }
+    if index.pack_checksum.as_bytes() != &pack[data_end..] {
+        bail!("Pack checksum does not match the checksum recorded in its index");
+    }
+
+    let mut index_entries: Vec<_> = index.entries.iter().collect();
+    index_entries.sort_by_key(|e| e.offset);
+
+    let mut entries = Vec::with_capacity(index_entries.len());
+    for (i, entry) in index_entries.iter().enumerate() {
+        let hash = entry.hash;
+        let start = entry.offset as usize;
+        let end = index_entries
+            .get(i + 1)
+            .map(|e| e.offset as usize)
+            .unwrap_or(data_end);
+        if start < PACK_HEADER_LEN || start >= end || end > data_end {
+            bail!("Invalid offset {start} for object {hash}");
+        }
+
+        let packed = &pack[start..end];
+        let mut crc = Crc::new();
+        crc.update(packed);
+        if crc.sum() != entry.crc32 {
+            bail!("CRC mismatch for object {hash} at offset {start}");
+        }
+
+        let (kind, size, header_len) = decode_entry_header(packed)
+            .with_context(|| format!("Invalid object header for {hash} at offset {start}"))?;
+        let mut decoder = ZlibDecoder::new(&packed[header_len..]);
+        let mut body = vec![];
+        decoder
+            .read_to_end(&mut body)
+            .with_context(|| format!("Unable to decompress object {hash} at offset {start}"))?;
+        if decoder.total_in() as usize != packed.len() - header_len {
+            bail!("Object {hash} at offset {start} does not end where the next object begins");
+        }
+        if body.len() != size {
+            bail!(
+                "Object {hash} declares {size} bytes but decompresses to {}",
+                body.len()
+            );
+        }
+        if Hash::of(&serialize_object(kind, &body)) != hash {
+            bail!("Object at offset {start} does not hash to {hash}");
+        }
+
+        entries.push(PackEntry {
+            hash,
+            kind,
+            size,
+            packed_size: packed.len(),
+            offset: entry.offset,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn serialize_index(entries: &[IndexEntry], pack_checksum: &Hash) -> Vec<u8> {
+    let mut index = vec![];
+    index.extend_from_slice(INDEX_SIGNATURE);
+    index.extend_from_slice(&VERSION.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+    for entry in entries {
+        fanout[entry.hash.as_bytes()[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        index.extend_from_slice(&total.to_be_bytes());
+    }
+
+    for entry in entries {
+        index.extend_from_slice(entry.hash.as_bytes());
+    }
+    for entry in entries {
+        index.extend_from_slice(&entry.crc32.to_be_bytes());
+    }
+
+    let mut large_offsets = vec![];
+    for entry in entries {
+        let offset = if entry.offset < LARGE_OFFSET_FLAG as u64 {
+            entry.offset as u32
+        } else {
+            large_offsets.push(entry.offset);
+            LARGE_OFFSET_FLAG | (large_offsets.len() as u32 - 1)
+        };
+        index.extend_from_slice(&offset.to_be_bytes());
+    }
+    for offset in large_offsets {
+        index.extend_from_slice(&offset.to_be_bytes());
+    }
+
+    index.extend_from_slice(pack_checksum.as_bytes());
+    let index_checksum = Hash::of(&index);
+    index.extend_from_slice(index_checksum.as_bytes());
+
+    index
+}
+
+// object header format:
+// first byte: <continuation bit><3 bit type><low 4 bits of size>
+// following bytes: <continuation bit><next 7 bits of size>
+fn encode_entry_header(kind: ObjectKind, size: usize) -> Vec<u8> {
+    let type_code = match kind {
+        ObjectKind::Commit => 1,
+        ObjectKind::Tree => 2,
+        ObjectKind::Blob => 3,
+    };
+
+    let mut header = vec![];
+    let mut byte = (type_code << 4) | (size & 0x0f) as u8;
+    let mut size = size >> 4;
+    while size > 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+
+    header
+}
+
+fn decode_entry_header(data: &[u8]) -> Result<(ObjectKind, usize, usize)> {
+    let first = *data.first().context("Missing object header")?;
+    let kind = match (first >> 4) & 0x07 {
+        1 => ObjectKind::Commit,
+        2 => ObjectKind::Tree,
+        3 => ObjectKind::Blob,
+        code => bail!("Unsupported packed object type {code}"),
+    };
+
+    let mut size = (first & 0x0f) as usize;
+    let mut shift = 4;
+    let mut len = 1;
+    let mut byte = first;
+    while byte & 0x80 != 0 {
+        byte = *data.get(len).context("Truncated object header")?;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        len += 1;
+    }
+
+    Ok((kind, size, len))
+}
+
+fn read_u32(data: &[u8], position: usize) -> u32 {
+    u32::from_be_bytes(data[position..position + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], position: usize) -> u64 {
+    u64::from_be_bytes(data[position..position + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{objects::loose_object_paths, test_utils::TestRepo};
+
+    use super::*;
+
+    fn loose_object_hashes() -> Result<Vec<Hash>> {
+        loose_object_paths()?
+            .iter()
+            .map(Hash::from_object_path)
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_pack_lists_every_object() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("subdir/b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let hashes = loose_object_hashes()?;
+
+        let index_path = write_pack(&hashes)?;
+        let entries = verify_pack(&index_path)?;
+
+        let packed: HashSet<_> = entries.iter().map(|e| e.hash).collect();
+        let expected: HashSet<_> = hashes.into_iter().collect();
+        assert_eq!(expected, packed);
+        assert_eq!(
+            2,
+            entries
+                .iter()
+                .filter(|e| e.kind == ObjectKind::Commit)
+                .count()
+        );
+        assert_eq!(vec![index_path], pack_indexes()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_pack_detects_corruption() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let index_path = write_pack(&loose_object_hashes()?)?;
+
+        let pack_path = index_path.with_extension("pack");
+        let mut pack = fs::read(&pack_path)?;
+        pack[PACK_HEADER_LEN + 2] ^= 0xff;
+        fs::write(&pack_path, pack)?;
+
+        assert!(verify_pack(&index_path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_header_round_trip() -> Result<()> {
+        for size in [0, 15, 16, 1000, 1 << 20] {
+            let header = encode_entry_header(ObjectKind::Tree, size);
+            let (kind, decoded_size, len) = decode_entry_header(&header)?;
+            assert_eq!(ObjectKind::Tree, kind);
+            assert_eq!(size, decoded_size);
+            assert_eq!(header.len(), len);
+        }
+
+        Ok(())
+    }
+}
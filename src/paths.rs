@@ -1,23 +1,62 @@
 use std::{
-    env,
-    fs::File,
-    io::Read,
+    env, fs,
     path::{Path, PathBuf},
-    sync::OnceLock,
+    sync::RwLock,
 };
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 
-static REPOSITORY_ROOT_PATH: OnceLock<PathBuf> = OnceLock::new();
+use crate::{config::Config, error::RygitError};
+
+static REPOSITORY_ROOT_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+static RYGIT_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+static COMMON_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+#[cfg(test)]
+static GLOBAL_CONFIG_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Marks the line of a linked work tree's `.rygit` file that names its
+/// metadata directory.
+pub const LINKED_RYGIT_DIR_PREFIX: &str = "rygitdir: ";
+/// The directory in the common metadata holding each linked work tree's own
+/// metadata.
+pub const WORKTREES_DIR: &str = "worktrees";
 
 pub fn repository_root_path() -> PathBuf {
-    REPOSITORY_ROOT_PATH
-        .get_or_init(|| {
-            let current_dir = env::current_dir().unwrap();
-            discover_repository_root_from(current_dir)
-                .expect("Failed to find repository root. Make sure you're in a rygit repository.")
-        })
-        .clone()
+    find_repository_root_path()
+        .expect("Failed to find repository root. Make sure you're in a rygit repository.")
+}
+
+/// Like [`repository_root_path`], but errors with
+/// [`RygitError::NotARepository`] instead of panicking outside a repository.
+pub fn find_repository_root_path() -> Result<PathBuf> {
+    if let Some(path) = REPOSITORY_ROOT_PATH.read().unwrap().as_ref() {
+        return Ok(path.clone());
+    }
+
+    let current_dir = env::current_dir().context("Unable to determine current directory")?;
+    let path = match rygit_dir_override() {
+        Some(_) => current_dir,
+        None => {
+            let path = discover_repository_root_from(current_dir)?;
+            if is_bare_repository_dir(&path) {
+                set_rygit_dir(path.clone());
+            } else if path.join(".rygit").is_file() {
+                set_rygit_dir(read_linked_rygit_dir(&path)?);
+            }
+            path
+        }
+    };
+    *REPOSITORY_ROOT_PATH.write().unwrap() = Some(path.clone());
+    Ok(path)
+}
+
+/// Forgets the cached repository root so the next lookup rediscovers it from
+/// the current directory. Tests and library users move between repositories
+/// within a single process.
+pub fn reset_repository_root_path() {
+    *REPOSITORY_ROOT_PATH.write().unwrap() = None;
+    *RYGIT_DIR_OVERRIDE.write().unwrap() = None;
+    *COMMON_PATH.write().unwrap() = None;
 }
 
 pub fn discover_repository_root_from(path: impl AsRef<Path>) -> Result<PathBuf> {
@@ -25,51 +64,183 @@ pub fn discover_repository_root_from(path: impl AsRef<Path>) -> Result<PathBuf>
 
     loop {
         let rygit_path = path.join(".rygit");
-        if rygit_path.exists() && rygit_path.is_dir() {
+        if rygit_path.is_dir() || rygit_path.is_file() || is_bare_repository_dir(path) {
             return Ok(path.to_path_buf());
         } else {
             match path.parent() {
                 Some(parent) => path = parent,
-                None => bail!("Not in a rygit repository (or any parent directories)"),
+                None => return Err(RygitError::NotARepository.into()),
             }
         }
     }
 }
 
+/// Whether `path` holds the metadata of a bare repository directly, rather
+/// than in `.rygit`.
+fn is_bare_repository_dir(path: &Path) -> bool {
+    !path.join(".rygit").is_dir()
+        && path.join("HEAD").is_file()
+        && Config::load_from(path.join("config"))
+            .and_then(|config| config.get_bool("core.bare"))
+            .is_ok_and(|bare| bare == Some(true))
+}
+
+/// The metadata directory of the linked work tree at `path`, named in its
+/// `.rygit` file.
+fn read_linked_rygit_dir(path: &Path) -> Result<PathBuf> {
+    let contents = fs::read_to_string(path.join(".rygit"))
+        .with_context(|| format!("Unable to read {}", path.join(".rygit").display()))?;
+    let Some(rygit_dir) = contents.trim().strip_prefix(LINKED_RYGIT_DIR_PREFIX) else {
+        bail!("Invalid .rygit file in {}", path.display());
+    };
+
+    Ok(PathBuf::from(rygit_dir))
+}
+
+/// Whether the current repository is bare, without a work tree.
+pub fn is_bare() -> Result<bool> {
+    Ok(Config::load()?.get_bool("core.bare")?.unwrap_or(false))
+}
+
+/// Errors with [`RygitError::BareRepository`] in a bare repository.
+pub fn ensure_work_tree() -> Result<()> {
+    if is_bare()? {
+        return Err(RygitError::BareRepository.into());
+    }
+
+    Ok(())
+}
+
+/// Keeps the repository metadata at `path` instead of `.rygit` in the work
+/// tree. The work tree is then the current directory.
+pub fn set_rygit_dir(path: PathBuf) {
+    *RYGIT_DIR_OVERRIDE.write().unwrap() = Some(path);
+    *COMMON_PATH.write().unwrap() = None;
+}
+
+pub fn rygit_dir_override() -> Option<PathBuf> {
+    RYGIT_DIR_OVERRIDE.read().unwrap().clone()
+}
+
+/// The metadata directory of the current work tree, holding its `HEAD` and
+/// index. See [`common_path`] for what all work trees share.
 pub fn rygit_path() -> PathBuf {
-    repository_root_path().join(".rygit")
+    if let Some(rygit_dir) = rygit_dir_override() {
+        return rygit_dir;
+    }
+    // Finding the root may discover a bare repository or a linked work tree
+    let root = repository_root_path();
+    rygit_dir_override().unwrap_or_else(|| root.join(".rygit"))
+}
+
+/// The metadata shared by every work tree of the repository: objects, refs
+/// and config. A linked work tree names it in its `commondir` file;
+/// otherwise it's [`rygit_path`] itself.
+pub fn common_path() -> PathBuf {
+    if let Some(path) = COMMON_PATH.read().unwrap().as_ref() {
+        return path.clone();
+    }
+
+    let rygit_path = rygit_path();
+    let path = match fs::read_to_string(rygit_path.join("commondir")) {
+        Ok(common_dir) => PathBuf::from(common_dir.trim()),
+        Err(_) => rygit_path,
+    };
+    *COMMON_PATH.write().unwrap() = Some(path.clone());
+    path
+}
+
+/// Whether `path` is repository metadata rather than part of the work tree:
+/// anything in the metadata directory, or the `.rygit` file of a linked work
+/// tree.
+pub fn is_metadata_path(path: &Path) -> bool {
+    path.starts_with(rygit_path()) || path == repository_root_path().join(".rygit")
 }
 
 pub fn objects_path() -> PathBuf {
-    rygit_path().join("objects")
+    common_path().join("objects")
+}
+
+pub fn packs_path() -> PathBuf {
+    objects_path().join("pack")
 }
 
 pub fn refs_path() -> PathBuf {
-    rygit_path().join("refs")
+    common_path().join("refs")
 }
 
 pub fn head_path() -> PathBuf {
     rygit_path().join("HEAD")
 }
 
+pub fn config_path() -> PathBuf {
+    common_path().join("config")
+}
+
+/// The user's config shared by every repository, `~/.rygitconfig` unless
+/// `RYGIT_CONFIG_GLOBAL` names another file. `None` without a home directory.
+pub fn global_config_path() -> Option<PathBuf> {
+    #[cfg(test)]
+    if let Some(path) = GLOBAL_CONFIG_OVERRIDE.read().unwrap().clone() {
+        return Some(path);
+    }
+    if let Some(path) = env::var_os("RYGIT_CONFIG_GLOBAL") {
+        return Some(PathBuf::from(path));
+    }
+
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".rygitconfig"))
+}
+
+/// Points the global config at `path`, so tests never read or write the
+/// developer's own.
+#[cfg(test)]
+pub fn set_global_config_path(path: PathBuf) {
+    *GLOBAL_CONFIG_OVERRIDE.write().unwrap() = Some(path);
+}
+
+pub fn shallow_path() -> PathBuf {
+    common_path().join("shallow")
+}
+
+pub fn orig_head_path() -> PathBuf {
+    rygit_path().join("ORIG_HEAD")
+}
+
+pub fn merge_head_path() -> PathBuf {
+    rygit_path().join("MERGE_HEAD")
+}
+
+pub fn merge_msg_path() -> PathBuf {
+    rygit_path().join("MERGE_MSG")
+}
+
+pub fn rebase_state_path() -> PathBuf {
+    rygit_path().join("rebase-state")
+}
+
+pub fn bisect_start_path() -> PathBuf {
+    rygit_path().join("BISECT_START")
+}
+
+pub fn bisect_bad_path() -> PathBuf {
+    rygit_path().join("BISECT_BAD")
+}
+
+pub fn bisect_good_path() -> PathBuf {
+    rygit_path().join("BISECT_GOOD")
+}
+
+pub fn sparse_checkout_path() -> PathBuf {
+    rygit_path().join("info").join("sparse-checkout")
+}
+
 pub fn index_path() -> PathBuf {
     rygit_path().join("index")
 }
 
+#[cfg(test)]
 pub fn head_ref_path() -> PathBuf {
-    let mut head_contents = vec![];
-    File::open(head_path())
-        .unwrap()
-        .read_to_end(&mut head_contents)
-        .unwrap();
-
-    if !head_contents.starts_with(b"ref: ") {
-        panic!("Invaild format for HEAD")
-    }
-
-    head_contents.drain(0..5).for_each(drop);
-    let head_contents: String = head_contents.into_iter().map(|c| c as char).collect();
-    rygit_path().join(head_contents.trim())
+    rygit_path().join(crate::refs::resolve_name("HEAD").unwrap())
 }
 
 #[cfg(test)]
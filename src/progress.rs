@@ -0,0 +1,85 @@
+use std::io::{self, IsTerminal, Write};
+
+use crate::output;
+
+/// Receives updates from long running operations as they process items.
+pub trait Progress {
+    /// Called once before the first item, with the number of items when it
+    /// is known up front.
+    fn start(&mut self, _total: Option<usize>) {}
+
+    /// Called after each processed item.
+    fn tick(&mut self);
+
+    /// Called once after the last item.
+    fn finish(&mut self) {}
+}
+
+/// Ignores every update.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn tick(&mut self) {}
+}
+
+/// Prints a `<label>: <processed>/<total>` counter to stderr, redrawn in
+/// place after each item.
+pub struct StderrProgress {
+    label: &'static str,
+    processed: usize,
+    total: Option<usize>,
+}
+
+impl StderrProgress {
+    pub fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            processed: 0,
+            total: None,
+        }
+    }
+
+    fn draw(&self) {
+        let counter = match self.total {
+            Some(total) => format!("{}/{total}", self.processed),
+            None => self.processed.to_string(),
+        };
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r{}: {counter}", self.label);
+        let _ = stderr.flush();
+    }
+}
+
+impl Progress for StderrProgress {
+    fn start(&mut self, total: Option<usize>) {
+        self.processed = 0;
+        self.total = total;
+        self.draw();
+    }
+
+    fn tick(&mut self) {
+        self.processed += 1;
+        self.draw();
+    }
+
+    fn finish(&mut self) {
+        self.draw();
+        eprintln!(", done.");
+    }
+}
+
+/// Whether a command shows progress. `--progress` forces it on, otherwise it
+/// is shown only when stderr is a terminal and neither `--no-progress` nor
+/// `--quiet` is given.
+pub fn enabled(progress: bool, no_progress: bool) -> bool {
+    progress || (!no_progress && !output::is_quiet() && io::stderr().is_terminal())
+}
+
+/// The reporter for a command, see [`enabled`].
+pub fn reporter(label: &'static str, progress: bool, no_progress: bool) -> Box<dyn Progress> {
+    if enabled(progress, no_progress) {
+        Box::new(StderrProgress::new(label))
+    } else {
+        Box::new(NoProgress)
+    }
+}
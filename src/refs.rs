@@ -0,0 +1,396 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use walkdir::WalkDir;
+
+use crate::{
+    hash::Hash,
+    lockfile::LockFile,
+    paths::{common_path, rygit_path},
+};
+
+// loose ref format:
+// <commit hash>
+//
+// A symbolic ref, such as `HEAD`, holds `ref: <name>` instead and stands for
+// the ref it names. A branch without commits has an empty file.
+//
+// packed-refs format:
+// <commit hash> <ref name>...
+//
+// A loose ref takes precedence over a packed one of the same name.
+const PACKED_REFS: &str = "packed-refs";
+const SYMBOLIC_PREFIX: &str = "ref: ";
+
+/// The commit the ref `name`, such as `refs/heads/master`, points to. `None`
+/// when it doesn't exist or has no commits yet, as a branch right after
+/// `init`.
+pub fn read_ref(name: &str) -> Result<Option<Hash>> {
+    read_ref_in(ref_dir(name), name)
+}
+
+/// The metadata directory holding `name`. Refs under `refs/` are shared by
+/// every work tree, while `HEAD` and the other pseudo refs belong to one.
+fn ref_dir(name: &str) -> PathBuf {
+    if name.starts_with("refs/") {
+        common_path()
+    } else {
+        rygit_path()
+    }
+}
+
+/// Like [`read_ref`], in the repository metadata directory `rygit_dir`.
+pub fn read_ref_in(rygit_dir: impl AsRef<Path>, name: &str) -> Result<Option<Hash>> {
+    let rygit_dir = rygit_dir.as_ref();
+    match read_loose_ref(&rygit_dir.join(name))? {
+        Some(hash) => Ok(hash),
+        None => Ok(read_packed_refs_in(rygit_dir)?.get(name).copied()),
+    }
+}
+
+/// The ref the symbolic ref `name` points to, or `None` when `name` isn't
+/// symbolic.
+pub fn read_symbolic(name: &str) -> Result<Option<String>> {
+    read_symbolic_in(ref_dir(name), name)
+}
+
+/// Like [`read_symbolic`], in the repository metadata directory `rygit_dir`.
+pub fn read_symbolic_in(rygit_dir: impl AsRef<Path>, name: &str) -> Result<Option<String>> {
+    let contents = match fs::read_to_string(rygit_dir.as_ref().join(name)) {
+        Ok(contents) => contents,
+        Err(error) if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::IsADirectory) => {
+            return Ok(None);
+        }
+        Err(error) => return Err(error).with_context(|| format!("Unable to read ref {name}")),
+    };
+
+    Ok(contents
+        .trim()
+        .strip_prefix(SYMBOLIC_PREFIX)
+        .map(|target| target.trim().to_string()))
+}
+
+/// Follows the chain of symbolic refs starting at `name` to the ref that
+/// holds a commit, e.g. `HEAD` to `refs/heads/master`. A ref that isn't
+/// symbolic resolves to itself.
+pub fn resolve_name(name: &str) -> Result<String> {
+    let mut chain = vec![name.to_string()];
+    while let Some(target) = read_symbolic(chain.last().unwrap())? {
+        if chain.contains(&target) {
+            chain.push(target);
+            bail!(
+                "Unable to resolve ref {name}. Symbolic refs form a loop: {}",
+                chain.join(" -> ")
+            );
+        }
+        chain.push(target);
+    }
+
+    Ok(chain.pop().unwrap())
+}
+
+/// The commit `name` points to after following symbolic refs. `None` when
+/// the ref it ends at doesn't exist or has no commits yet.
+pub fn resolve(name: &str) -> Result<Option<Hash>> {
+    read_ref(&resolve_name(name)?)
+}
+
+/// Whether the ref `name` exists, even without any commits.
+pub fn ref_exists(name: &str) -> Result<bool> {
+    let rygit_dir = ref_dir(name);
+    Ok(rygit_dir.join(name).is_file() || read_packed_refs_in(&rygit_dir)?.contains_key(name))
+}
+
+/// The refs in the directory `prefix`, such as `refs/heads/`, and the
+/// commits they point to, sorted by name. Refs without commits are left
+/// out.
+pub fn list_refs(prefix: &str) -> Result<Vec<(String, Hash)>> {
+    list_refs_in(common_path(), prefix)
+}
+
+/// Like [`list_refs`], in the repository metadata directory `rygit_dir`.
+pub fn list_refs_in(rygit_dir: impl AsRef<Path>, prefix: &str) -> Result<Vec<(String, Hash)>> {
+    let rygit_dir = rygit_dir.as_ref();
+    let mut refs: BTreeMap<_, _> = read_packed_refs_in(rygit_dir)?
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .collect();
+    for (name, hash) in loose_refs_in(rygit_dir, prefix)? {
+        match hash {
+            Some(hash) => refs.insert(name, hash),
+            None => refs.remove(&name),
+        };
+    }
+
+    Ok(refs.into_iter().collect())
+}
+
+/// Points the ref `name`, such as `refs/heads/master`, at `new`, provided it
+/// still points at `expected_old`. See [`update_ref_in`].
+pub fn update_ref(name: &str, new: &Hash, expected_old: Option<&Hash>) -> Result<()> {
+    update_ref_in(ref_dir(name), name, new, expected_old)
+}
+
+/// Points the ref `name` in `rygit_dir` at `new`, provided it still points
+/// at `expected_old`; `None` expects a ref that doesn't exist yet or has no
+/// commits. The ref is locked while it's checked and replaced atomically, so
+/// of two concurrent updates from the same commit only one succeeds.
+pub fn update_ref_in(
+    rygit_dir: impl AsRef<Path>,
+    name: &str,
+    new: &Hash,
+    expected_old: Option<&Hash>,
+) -> Result<()> {
+    let rygit_dir = rygit_dir.as_ref();
+    let path = rygit_dir.join(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Unable to update ref {name}"))?;
+    }
+    let mut ref_file =
+        LockFile::acquire(&path).with_context(|| format!("Unable to update ref {name}"))?;
+
+    let current = read_ref_in(rygit_dir, name)?;
+    if current.as_ref() != expected_old {
+        let describe =
+            |hash: Option<&Hash>| hash.map_or("nothing".to_string(), |hash| hash.to_hex());
+        bail!(
+            "Unable to update ref {name}. It points at {}, expected {}",
+            describe(current.as_ref()),
+            describe(expected_old)
+        );
+    }
+
+    write!(ref_file, "{}", new.to_hex()).with_context(|| format!("Unable to update ref {name}"))?;
+    ref_file
+        .commit()
+        .with_context(|| format!("Unable to update ref {name}"))
+}
+
+/// Moves every loose ref with a commit into `packed-refs`, returning how
+/// many were packed. Refs without commits stay loose.
+pub fn pack_refs() -> Result<usize> {
+    let rygit_dir = common_path();
+    let mut packed_refs_file = LockFile::acquire(rygit_dir.join(PACKED_REFS))
+        .context("Unable to pack refs. Unable to lock packed-refs")?;
+    let mut packed = read_packed_refs_in(&rygit_dir)?;
+    let loose: Vec<_> = loose_refs_in(&rygit_dir, "refs/")?
+        .into_iter()
+        .filter_map(|(name, hash)| Some((name, hash?)))
+        .collect();
+    packed.extend(loose.iter().cloned());
+
+    for (name, hash) in packed.iter() {
+        writeln!(packed_refs_file, "{} {name}", hash.to_hex())
+            .context("Unable to pack refs. Unable to write packed-refs")?;
+    }
+    packed_refs_file
+        .commit()
+        .context("Unable to pack refs. Unable to write packed-refs")?;
+
+    for (name, _) in loose.iter() {
+        fs::remove_file(rygit_dir.join(name))
+            .with_context(|| format!("Unable to pack refs. Unable to remove {name}"))?;
+    }
+
+    Ok(loose.len())
+}
+
+/// The contents of a loose ref file: `None` when there is no such file, and
+/// `Some(None)` when it's empty.
+fn read_loose_ref(path: &Path) -> Result<Option<Option<Hash>>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::IsADirectory) => {
+            return Ok(None);
+        }
+        Err(error) => {
+            return Err(error).with_context(|| format!("Unable to read ref {}", path.display()));
+        }
+    };
+    if contents.trim().is_empty() {
+        return Ok(Some(None));
+    }
+
+    let hash = Hash::from_hex(contents.trim())
+        .with_context(|| format!("Invalid ref {}. Not a valid hash", path.display()))?;
+    Ok(Some(Some(hash)))
+}
+
+/// The loose refs in the directory `prefix` of `rygit_dir`, with `None`
+/// for those without commits. Symbolic refs are left out, as they only name
+/// another ref.
+fn loose_refs_in(rygit_dir: &Path, prefix: &str) -> Result<Vec<(String, Option<Hash>)>> {
+    let directory = rygit_dir.join(prefix);
+    if !directory.is_dir() {
+        return Ok(vec![]);
+    }
+
+    let mut refs = vec![];
+    for entry in WalkDir::new(&directory).min_depth(1).sort_by_file_name() {
+        let entry = entry.context("Unable to list refs")?;
+        let path = entry.path();
+        let is_lock = path
+            .extension()
+            .is_some_and(|extension| extension == "lock");
+        if !entry.file_type().is_file() || is_lock {
+            continue;
+        }
+
+        let name = format!(
+            "{prefix}{}",
+            path.strip_prefix(&directory)?.to_string_lossy()
+        );
+        if read_symbolic_in(rygit_dir, &name)?.is_some() {
+            continue;
+        }
+        if let Some(hash) = read_loose_ref(path)? {
+            refs.push((name, hash));
+        }
+    }
+
+    Ok(refs)
+}
+
+fn read_packed_refs_in(rygit_dir: &Path) -> Result<BTreeMap<String, Hash>> {
+    let contents = match fs::read_to_string(rygit_dir.join(PACKED_REFS)) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(error) => return Err(error).context("Unable to read packed-refs"),
+    };
+
+    let mut refs = BTreeMap::new();
+    for line in contents.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (hash, name) = line
+            .split_once(' ')
+            .with_context(|| format!("Invalid packed-refs line \"{line}\""))?;
+        let hash = Hash::from_hex(hash)
+            .with_context(|| format!("Invalid packed-refs line \"{line}\". Invalid hash"))?;
+        refs.insert(name.to_string(), hash);
+    }
+
+    Ok(refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::{branch::Branch, paths::head_path, revparse, tag::Tag, test_utils::TestRepo};
+
+    use super::*;
+
+    #[test]
+    fn test_update_ref_compare_and_swap() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let first = revparse::resolve("HEAD~1")?;
+        let second = revparse::resolve("HEAD")?;
+
+        update_ref("refs/heads/topic", &first, None)?;
+        assert!(update_ref("refs/heads/topic", &second, None).is_err());
+        update_ref("refs/heads/topic", &second, Some(&first))?;
+        assert_eq!(Some(second), read_ref("refs/heads/topic")?);
+
+        // Someone else moved the ref since we read it
+        let error = update_ref("refs/heads/topic", &first, Some(&first)).unwrap_err();
+        assert!(error.to_string().contains("expected"));
+        assert_eq!(Some(second), read_ref("refs/heads/topic")?);
+        assert!(!rygit_path().join("refs/heads/topic.lock").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_refs() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature/one")?;
+        Tag::create("v1", None)?;
+        let initial = revparse::resolve("HEAD")?;
+
+        assert_eq!(3, pack_refs()?);
+        assert!(!rygit_path().join("refs/heads/master").exists());
+        assert!(!rygit_path().join("refs/heads/feature/one").exists());
+        assert!(!rygit_path().join("refs/tags/v1").exists());
+
+        let branches: Vec<_> = Branch::list()?
+            .iter()
+            .map(|branch| branch.name().to_string())
+            .collect();
+        assert_eq!(vec!["feature/one", "master"], branches);
+        assert_eq!("v1", Tag::list()?[0].name());
+        assert_eq!(initial, revparse::resolve("feature/one")?);
+        assert_eq!(initial, revparse::resolve("v1")?);
+
+        // A loose ref written after packing takes precedence
+        repo.file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let second = revparse::resolve("HEAD")?;
+        assert_ne!(initial, second);
+        assert_eq!(Some(second), read_ref("refs/heads/master")?);
+        assert_eq!(Some(initial), read_ref("refs/heads/feature/one")?);
+        assert_eq!(Some(&second), Branch::find_by_name("master")?.commit_hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symbolic_refs() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("feature")?;
+        let feature = revparse::resolve("feature")?;
+        fs::create_dir_all(rygit_path().join("refs/remotes/origin"))?;
+        fs::write(
+            rygit_path().join("refs/remotes/origin/HEAD"),
+            "ref: refs/heads/feature\n",
+        )?;
+
+        assert_eq!(
+            Some("refs/heads/feature".to_string()),
+            read_symbolic("refs/remotes/origin/HEAD")?
+        );
+        assert_eq!(None, read_symbolic("refs/heads/feature")?);
+        assert_eq!("refs/heads/master", resolve_name("HEAD")?);
+        assert_eq!(Some(feature), resolve("refs/remotes/origin/HEAD")?);
+        assert_eq!(feature, revparse::resolve("origin")?);
+        assert!(list_refs("refs/remotes/")?.is_empty());
+        assert!(Branch::list_remotes()?.is_empty());
+        pack_refs()?;
+        let packed = fs::read_to_string(rygit_path().join(PACKED_REFS))?;
+        assert!(!packed.contains("refs/remotes/origin/HEAD"));
+        assert_eq!(Some(feature), resolve("refs/remotes/origin/HEAD")?);
+
+        fs::write(rygit_path().join("LOOP"), "ref: LOOP")?;
+        let error = resolve("LOOP").unwrap_err();
+        assert_eq!(
+            "Unable to resolve ref LOOP. Symbolic refs form a loop: LOOP -> LOOP",
+            error.to_string()
+        );
+
+        // Detached, HEAD holds a commit itself
+        fs::write(head_path(), feature.to_hex())?;
+        assert_eq!("HEAD", resolve_name("HEAD")?);
+        assert_eq!(Some(feature), resolve("HEAD")?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,493 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    branch::Branch,
+    config::Config,
+    hash::Hash,
+    objects::{commit::Commit, locate, read_object_in},
+    paths::objects_path,
+    refs::{list_refs_in, read_ref, read_ref_in, read_symbolic_in, update_ref, update_ref_in},
+    shallow,
+    transport::{copy_objects, copy_reachable_objects, missing_objects, referenced_objects},
+};
+
+pub struct Remote {
+    name: String,
+    url: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FetchedRef {
+    pub branch: String,
+    pub old_hash: Option<Hash>,
+    pub new_hash: Hash,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PushedRef {
+    pub branch: String,
+    pub old_hash: Option<Hash>,
+    pub new_hash: Hash,
+}
+
+impl Remote {
+    pub fn add(name: impl Into<String>, url: impl AsRef<Path>) -> Result<Self> {
+        let name = name.into();
+        let url = url.as_ref();
+        let mut config = Config::load()?;
+        if config.get(&format!("remote.{name}.url")).is_some() {
+            bail!("Remote \"{name}\" already exists");
+        }
+
+        let url = url
+            .canonicalize()
+            .with_context(|| format!("Unable to add remote. {} does not exist", url.display()))?;
+        let remote = Self { name, url };
+        remote.rygit_path()?;
+
+        config.set(
+            &format!("remote.{}.url", remote.name),
+            remote.url.to_string_lossy(),
+        )?;
+        config.save()?;
+
+        Ok(remote)
+    }
+
+    pub fn find(name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+        let config = Config::load()?;
+        let url = config
+            .get(&format!("remote.{name}.url"))
+            .with_context(|| format!("\"{name}\" is not a remote"))?;
+        let url = PathBuf::from(url);
+
+        Ok(Self { name, url })
+    }
+
+    pub fn list() -> Result<Vec<Remote>> {
+        let config = Config::load()?;
+        config
+            .subsections("remote")
+            .into_iter()
+            .filter(|name| config.get(&format!("remote.{name}.url")).is_some())
+            .map(Remote::find)
+            .collect()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn url(&self) -> &Path {
+        &self.url
+    }
+
+    /// Copies the objects reachable from every branch of the remote into the
+    /// local object store and points `refs/remotes/<remote>/<branch>` at the
+    /// remote branch tips. Local branches and the working tree are untouched.
+    pub fn fetch(&self) -> Result<Vec<FetchedRef>> {
+        self.fetch_with_depth(None)
+    }
+
+    /// Like [`Remote::fetch`], but with a `depth` copies only that many
+    /// commits of each branch's history. The oldest copied commits are
+    /// recorded in the shallow file so history walks stop at them.
+    pub fn fetch_with_depth(&self, depth: Option<usize>) -> Result<Vec<FetchedRef>> {
+        let remote_rygit_path = self.rygit_path()?;
+        let remote_branches: Vec<_> = list_refs_in(&remote_rygit_path, "refs/heads/")
+            .context("Unable to fetch. Unable to read remote branches")?
+            .into_iter()
+            .map(|(name, hash)| (name["refs/heads/".len()..].to_string(), hash))
+            .collect();
+
+        let tips: Vec<_> = remote_branches.iter().map(|(_, hash)| *hash).collect();
+        let remote_objects_path = remote_rygit_path.join("objects");
+        let boundary = match depth {
+            Some(depth) => shallow_boundary(&remote_objects_path, &tips, depth)
+                .context("Unable to fetch. Unable to read remote history")?,
+            None => HashSet::new(),
+        };
+        copy_reachable_objects(&remote_objects_path, objects_path(), &tips, &boundary)
+            .context("Unable to fetch. Unable to copy objects")?;
+        if !boundary.is_empty() {
+            shallow::add(&boundary).context("Unable to fetch")?;
+        }
+
+        let mut fetched = vec![];
+        for (branch, new_hash) in remote_branches {
+            let tracking_ref = self.tracking_ref(&branch);
+            let old_hash = read_ref(&tracking_ref).context("Unable to fetch")?;
+            if old_hash != Some(new_hash) {
+                update_ref(&tracking_ref, &new_hash, old_hash.as_ref())
+                    .context("Unable to fetch")?;
+            }
+
+            fetched.push(FetchedRef {
+                branch,
+                old_hash,
+                new_hash,
+            });
+        }
+
+        Ok(fetched)
+    }
+
+    /// Copies the objects reachable from `branch` into the remote's object
+    /// store and moves the remote branch of the same name to it. Unless
+    /// `force` is set the update must be a fast-forward: the remote branch
+    /// tip has to be an ancestor of the local one. The remote's working tree
+    /// is not updated, so pushing to the branch checked out in a non-bare
+    /// remote is refused.
+    pub fn push(&self, branch: &Branch, force: bool) -> Result<PushedRef> {
+        let remote_rygit_path = self.rygit_path()?;
+        let remote_ref = format!("refs/heads/{}", branch.name());
+        let remote_is_bare = Config::load_from(remote_rygit_path.join("config"))
+            .and_then(|config| config.get_bool("core.bare"))
+            .context("Unable to push. Unable to read the remote's config")?
+            .unwrap_or(false);
+        let remote_head = read_symbolic_in(&remote_rygit_path, "HEAD")
+            .context("Unable to push. Unable to read the remote's HEAD")?;
+        if !remote_is_bare && remote_head.as_deref() == Some(remote_ref.as_str()) {
+            bail!(
+                "Unable to push {}. The branch is checked out in the remote repository and its working tree would not match",
+                branch.name()
+            );
+        }
+
+        let old_hash = read_ref_in(&remote_rygit_path, &remote_ref).context("Unable to push")?;
+        let new_hash = branch.tip().context("Unable to push")?;
+
+        if let Some(old_hash) = old_hash
+            && !force
+        {
+            // The remote tip can only be an ancestor if we already have it
+            let is_fast_forward =
+                locate(&old_hash)?.is_some() && Commit::is_ancestor(&old_hash, &new_hash)?;
+            if !is_fast_forward {
+                bail!(
+                    "Unable to push {}. Updates were rejected because the remote branch has commits that are not in the local branch. Fetch and merge them first, or use --force to overwrite them",
+                    branch.name()
+                );
+            }
+        }
+
+        let remote_objects_path = remote_rygit_path.join("objects");
+        let missing = missing_objects(&remote_objects_path, &[new_hash])
+            .context("Unable to push. Unable to find the objects to send")?;
+        copy_objects(objects_path(), &remote_objects_path, &missing)
+            .context("Unable to push. Unable to copy objects")?;
+        if old_hash != Some(new_hash) {
+            update_ref_in(
+                &remote_rygit_path,
+                &remote_ref,
+                &new_hash,
+                old_hash.as_ref(),
+            )
+            .context("Unable to push")?;
+        }
+
+        let tracking_ref = self.tracking_ref(branch.name());
+        let tracking_hash = read_ref(&tracking_ref).context("Unable to push")?;
+        if tracking_hash != Some(new_hash) {
+            update_ref(&tracking_ref, &new_hash, tracking_hash.as_ref())
+                .context("Unable to push")?;
+        }
+
+        Ok(PushedRef {
+            branch: branch.name().to_string(),
+            old_hash,
+            new_hash,
+        })
+    }
+
+    /// The branch the remote's HEAD points to, if it's on a branch.
+    pub fn default_branch(&self) -> Result<Option<String>> {
+        let head = read_symbolic_in(self.rygit_path()?, "HEAD")
+            .context("Unable to read the remote's HEAD")?;
+
+        Ok(head.and_then(|head| head.strip_prefix("refs/heads/").map(str::to_string)))
+    }
+
+    fn tracking_ref(&self, branch: &str) -> String {
+        format!("refs/remotes/{}/{branch}", self.name)
+    }
+
+    /// The remote's metadata directory: `.rygit` in its work tree, or the
+    /// repository itself when it is bare.
+    fn rygit_path(&self) -> Result<PathBuf> {
+        let rygit_path = self.url.join(".rygit");
+        if rygit_path.is_dir() {
+            return Ok(rygit_path);
+        }
+        if self.url.join("HEAD").is_file() && self.url.join("refs").is_dir() {
+            return Ok(self.url.clone());
+        }
+
+        bail!("{} is not a rygit repository", self.url.display());
+    }
+}
+
+/// The commits `depth` commits into the history of `tips` that have
+/// parents. A shallow copy stops at them.
+fn shallow_boundary(
+    source_objects_path: &Path,
+    tips: &[Hash],
+    depth: usize,
+) -> Result<HashSet<Hash>> {
+    if depth == 0 {
+        bail!("Invalid depth 0. Depth must be at least 1");
+    }
+
+    let mut visited = HashSet::new();
+    let mut level = tips.to_vec();
+    let mut boundary = HashSet::new();
+    for current_depth in 1..=depth {
+        let mut next_level = vec![];
+        for hash in level {
+            if !visited.insert(hash) {
+                continue;
+            }
+            let (kind, body) = read_object_in(source_objects_path, &hash)?;
+            // A commit's tree comes before its parents
+            let parents: Vec<_> = referenced_objects(kind, &body)?
+                .into_iter()
+                .skip(1)
+                .collect();
+            if current_depth == depth && !parents.is_empty() {
+                boundary.insert(hash);
+            }
+            next_level.extend(parents);
+        }
+        level = next_level;
+    }
+
+    Ok(boundary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{
+        commands,
+        objects::locate_in,
+        paths::{head_ref_path, refs_path},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    fn head_hash() -> Result<Hash> {
+        Hash::from_hex(fs::read_to_string(head_ref_path())?.trim())
+    }
+
+    #[test]
+    fn test_add_and_find() -> Result<()> {
+        let origin = TestRepo::new()?;
+        let repo = TestRepo::new()?;
+        let remote = Remote::add("origin", origin.path())?;
+        assert_eq!(origin.path(), remote.url());
+
+        let remote = Remote::find("origin")?;
+        assert_eq!("origin", remote.name());
+        assert_eq!(1, Remote::list()?.len());
+        assert!(Remote::add("origin", origin.path()).is_err());
+        assert!(Remote::add("other", repo.path().join("missing")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+
+        let repo = TestRepo::new()?;
+        let remote = Remote::add("origin", origin.path())?;
+        let fetched = remote.fetch()?;
+        assert_eq!(1, fetched.len());
+        assert_eq!("master", fetched[0].branch);
+        assert_eq!(None, fetched[0].old_hash);
+        let first_hash = fetched[0].new_hash;
+
+        origin
+            .enter()?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?
+            .branch("feature")?;
+        let second_hash = head_hash()?;
+
+        repo.enter()?;
+        assert!(locate(&second_hash)?.is_none());
+        let fetched = remote.fetch()?;
+        assert_eq!(
+            vec![
+                FetchedRef {
+                    branch: "feature".to_string(),
+                    old_hash: None,
+                    new_hash: second_hash,
+                },
+                FetchedRef {
+                    branch: "master".to_string(),
+                    old_hash: Some(first_hash),
+                    new_hash: second_hash,
+                },
+            ],
+            fetched
+        );
+
+        let tracking_ref = fs::read_to_string(refs_path().join("remotes/origin/master"))?;
+        assert_eq!(second_hash, Hash::from_hex(&tracking_ref)?);
+        let commit = Commit::load(&second_hash)?;
+        assert_eq!(2, commit.tree()?.entries().len());
+        assert!(fs::read_to_string(head_ref_path())?.is_empty());
+        assert!(!repo.path().join("b.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_fast_forward() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("other")?
+            .switch("other")?;
+        let first_hash = head_hash()?;
+
+        let repo = TestRepo::new()?;
+        let remote = Remote::add("origin", origin.path())?;
+        remote.fetch()?;
+        fs::write(head_ref_path(), first_hash.to_hex())?;
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let second_hash = head_hash()?;
+
+        let pushed = remote.push(&Branch::current()?, false)?;
+        assert_eq!(Some(first_hash), pushed.old_hash);
+        assert_eq!(second_hash, pushed.new_hash);
+        let tracking_ref = fs::read_to_string(refs_path().join("remotes/origin/master"))?;
+        assert_eq!(second_hash, Hash::from_hex(&tracking_ref)?);
+
+        let origin_master = fs::read_to_string(origin.path().join(".rygit/refs/heads/master"))?;
+        assert_eq!(second_hash, Hash::from_hex(&origin_master)?);
+        origin.enter()?;
+        assert_eq!(2, Commit::load(&second_hash)?.tree()?.entries().len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_to_bare_repository() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let bare = TempDir::new()?;
+        commands::init::run(bare.path(), true)?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let hash = head_hash()?;
+
+        let remote = Remote::add("origin", bare.path())?;
+        remote.push(&Branch::current()?, false)?;
+        let pushed = fs::read_to_string(bare.path().join("refs/heads/master"))?;
+        assert_eq!(hash, Hash::from_hex(&pushed)?);
+        assert!(locate_in(bare.path().join("objects"), &hash)?.is_some());
+        assert!(!bare.path().join("a.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_to_checked_out_branch() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let origin_hash = head_hash()?;
+
+        let repo = TestRepo::new()?;
+        let remote = Remote::add("origin", origin.path())?;
+        remote.fetch()?;
+        fs::write(head_ref_path(), origin_hash.to_hex())?;
+        repo.file("b.txt", "b")?
+            .stage(".")?
+            .commit("Local commit")?;
+
+        let error = remote.push(&Branch::current()?, false).unwrap_err();
+        assert!(format!("{error:#}").contains("checked out in the remote repository"));
+        origin.enter()?;
+        assert_eq!(origin_hash, head_hash()?);
+
+        // Any other branch can still be pushed
+        repo.enter()?.branch("feature")?.switch("feature")?;
+        remote.push(&Branch::current()?, false)?;
+        let pushed = fs::read_to_string(origin.path().join(".rygit/refs/heads/feature"))?;
+        assert_eq!(head_hash()?, Hash::from_hex(&pushed)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_rejects_non_fast_forward() -> Result<()> {
+        let origin = TestRepo::new()?;
+        origin
+            .file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let first_hash = head_hash()?;
+
+        let repo = TestRepo::new()?;
+        let remote = Remote::add("origin", origin.path())?;
+        remote.fetch()?;
+        fs::write(head_ref_path(), first_hash.to_hex())?;
+        repo.file("a.txt", "a")?
+            .file("local.txt", "local")?
+            .stage(".")?
+            .commit("Local commit")?;
+        let local_hash = head_hash()?;
+
+        origin
+            .enter()?
+            .file("remote.txt", "remote")?
+            .stage(".")?
+            .commit("Remote commit")?;
+        let remote_hash = head_hash()?;
+        // Pushing to the checked out branch is refused either way
+        origin.branch("other")?.switch("other")?;
+        let origin_master = origin.path().join(".rygit/refs/heads/master");
+
+        repo.enter()?;
+        assert!(remote.push(&Branch::current()?, false).is_err());
+        assert_eq!(
+            remote_hash,
+            Hash::from_hex(&fs::read_to_string(&origin_master)?)?
+        );
+
+        let pushed = remote.push(&Branch::current()?, true)?;
+        assert_eq!(Some(remote_hash), pushed.old_hash);
+        assert_eq!(
+            local_hash,
+            Hash::from_hex(&fs::read_to_string(&origin_master)?)?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    branch::Branch,
+    commands,
+    index::Index,
+    lockfile::RepositoryLock,
+    objects::{commit::Commit, signature::Signature},
+    paths::{ensure_work_tree, find_repository_root_path, reset_repository_root_path},
+    refs, revparse,
+};
+
+/// A repository opened for use as a library.
+///
+/// Like the command line, rygit works on one repository per process, found
+/// from the current directory. Opening a repository makes its work tree the
+/// current directory.
+#[derive(Debug)]
+pub struct Repository {
+    root: PathBuf,
+}
+
+impl Repository {
+    /// Creates a repository in `path` and opens it.
+    pub fn init(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        commands::init::init(path, false)?;
+        Self::open(path)
+    }
+
+    /// Opens the repository containing `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        env::set_current_dir(path)
+            .with_context(|| format!("Unable to open repository at {}", path.display()))?;
+        reset_repository_root_path();
+        let root = find_repository_root_path()
+            .with_context(|| format!("Unable to open repository at {}", path.display()))?;
+
+        Ok(Self { root })
+    }
+
+    /// The root of the work tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn index(&self) -> Result<Index> {
+        Index::load()
+    }
+
+    /// Stages `path`, a file or directory relative to the work tree root.
+    pub fn add(&self, path: impl AsRef<Path>) -> Result<()> {
+        ensure_work_tree()?;
+        let _lock = RepositoryLock::acquire()?;
+        let path = self.root.join(path);
+        let mut index = Index::load()
+            .with_context(|| format!("Unable to add {}. Unable to load index", path.display()))?;
+        index.add(path)
+    }
+
+    /// Commits the staged files to the current branch.
+    pub fn commit(&self, message: impl Into<String>, author: Signature) -> Result<Commit> {
+        ensure_work_tree().context("Unable to commit")?;
+        let _lock = RepositoryLock::acquire()?;
+        let index = Index::load()?;
+        Commit::create(&index, message, author.clone(), author)
+    }
+
+    /// The commit the current branch points to, if it has any.
+    pub fn head(&self) -> Result<Option<Commit>> {
+        refs::resolve("HEAD")
+            .context("Unable to read head ref")?
+            .map(|hash| Commit::load(&hash))
+            .transpose()
+    }
+
+    /// Resolves a revision such as `HEAD~2` or a branch name to its commit.
+    pub fn find_commit(&self, revision: &str) -> Result<Commit> {
+        Commit::load(&revparse::resolve(revision)?)
+    }
+
+    pub fn current_branch(&self) -> Result<Branch> {
+        Branch::current()
+    }
+}
@@ -1,29 +1,54 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
-use strum::Display;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
 use walkdir::WalkDir;
 
 use crate::{
     index::Index,
     objects::{blob::Blob, tree::Tree},
-    paths::{repository_root_path, rygit_path},
+    paths::{is_metadata_path, repository_root_path},
+    sparse::SparseCheckout,
 };
 
-#[derive(Debug, PartialEq, Eq, Display)]
+#[derive(Debug, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FileStatus {
     Deleted,
     Modified,
     Added,
+    /// A tracked file replaced by a directory or symlink.
+    #[strum(serialize = "typechange")]
+    #[serde(rename = "typechange")]
+    TypeChanged,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StatusEntry {
     pub path: PathBuf,
     pub status: FileStatus,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Which untracked files status reports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum UntrackedMode {
+    /// None, skipping the scan of the working tree.
+    No,
+    /// Untracked files, with directories holding no tracked files listed as
+    /// the directory alone.
+    #[default]
+    Normal,
+    /// Every untracked file.
+    All,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RepositoryStatus {
     staged_changes: Vec<StatusEntry>,
     unstaged_changes: Vec<StatusEntry>,
@@ -31,7 +56,7 @@ pub struct RepositoryStatus {
 }
 
 impl RepositoryStatus {
-    pub fn load() -> Result<Self> {
+    pub fn load(untracked_mode: UntrackedMode) -> Result<Self> {
         let committed_tree = Tree::current()?;
         let committed_tree_files = if let Some(committed_tree) = committed_tree {
             committed_tree.entries_flattened()
@@ -39,26 +64,40 @@ impl RepositoryStatus {
             HashMap::new()
         };
 
-        let rygit_path = rygit_path();
-        let working_tree_file_paths: Vec<_> = WalkDir::new(repository_root_path())
-            .min_depth(1)
-            .into_iter()
-            .filter_entry(|e| !e.path().starts_with(&rygit_path))
-            .collect::<Result<_, _>>()
-            .context("Unable to read repository contents")?;
-        let mut working_tree_files = HashMap::new();
-        for entry in working_tree_file_paths {
-            let entry_path = entry.path();
-            let entry_blob_hash = Blob::hash_for(entry_path)?;
-            working_tree_files.insert(entry_path.to_path_buf(), entry_blob_hash);
-        }
-
         let mut staged_files = HashMap::new();
+        // Files marked assume-unchanged are taken to match the index unseen
+        let mut assumed_unchanged = HashSet::new();
         let index = Index::load()?;
+        let repository_path = repository_root_path();
         for index_file in index.files() {
-            staged_files.insert(index_file.path().to_path_buf(), *index_file.hash());
+            let path = repository_path.join(index_file.path());
+            if index_file.assume_unchanged() {
+                assumed_unchanged.insert(path.clone());
+            }
+            staged_files.insert(path, *index_file.hash());
+        }
+
+        // Only tracked files need hashing; the rest are untracked whatever
+        // their contents
+        let working_tree_file_paths = match untracked_mode {
+            UntrackedMode::No => staged_files
+                .keys()
+                .filter(|path| is_regular_file(path))
+                .cloned()
+                .collect(),
+            UntrackedMode::Normal | UntrackedMode::All => working_tree_file_paths()?,
+        };
+        let mut working_tree_files = HashMap::new();
+        for entry_path in working_tree_file_paths {
+            let entry_blob_hash = match staged_files.get(&entry_path) {
+                Some(hash) if assumed_unchanged.contains(&entry_path) => Some(*hash),
+                Some(_) => Some(Blob::hash_for(&entry_path)?),
+                None => None,
+            };
+            working_tree_files.insert(entry_path, entry_blob_hash);
         }
 
+        let sparse = SparseCheckout::load()?;
         let mut untracked_files = vec![];
         let mut unstaged_changes = vec![];
         let mut staged_changes = vec![];
@@ -90,10 +129,26 @@ impl RepositoryStatus {
                 });
             }
 
-            if !working_tree_files.contains_key(staged_file_path) {
+            // A file the sparse checkout leaves out is missing on purpose
+            let omitted = sparse.as_ref().is_some_and(|sparse| {
+                staged_file_path
+                    .strip_prefix(&repository_path)
+                    .is_ok_and(|path| !sparse.includes(path))
+            });
+            if !working_tree_files.contains_key(staged_file_path)
+                && !omitted
+                && !assumed_unchanged.contains(staged_file_path)
+            {
+                // Trees only record regular files, so anything else still
+                // at the path is a change of type rather than a deletion
+                let status = if staged_file_path.symlink_metadata().is_ok() {
+                    FileStatus::TypeChanged
+                } else {
+                    FileStatus::Deleted
+                };
                 unstaged_changes.push(StatusEntry {
                     path: staged_file_path.to_path_buf(),
-                    status: FileStatus::Deleted,
+                    status,
                 });
             }
         }
@@ -105,7 +160,7 @@ impl RepositoryStatus {
                 untracked_files.push(working_tree_file_path.clone());
             }
 
-            if staged_file_hash.is_some_and(|h| h != working_tree_file.1) {
+            if staged_file_hash.is_some_and(|h| Some(h) != working_tree_file.1.as_ref()) {
                 unstaged_changes.push(StatusEntry {
                     path: working_tree_file_path.to_path_buf(),
                     status: FileStatus::Modified,
@@ -115,7 +170,11 @@ impl RepositoryStatus {
 
         staged_changes.sort_by(|a, b| a.path.cmp(&b.path));
         unstaged_changes.sort_by(|a, b| a.path.cmp(&b.path));
+        if untracked_mode == UntrackedMode::Normal {
+            untracked_files = collapse_untracked_directories(untracked_files, staged_files.keys());
+        }
         untracked_files.sort();
+        untracked_files.dedup();
 
         let status = Self {
             staged_changes,
@@ -138,11 +197,60 @@ impl RepositoryStatus {
     }
 }
 
+/// Lists every file in the working tree, outside `.rygit`.
+fn working_tree_file_paths() -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in WalkDir::new(repository_root_path())
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|e| !is_metadata_path(e.path()))
+    {
+        let entry = entry.context("Unable to read repository contents")?;
+        if entry.file_type().is_file() {
+            paths.push(entry.into_path());
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Whether `path` is a regular file, not following a symlink at it.
+fn is_regular_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+/// Replaces untracked files with their outermost ancestor directory that has
+/// no tracked files in it.
+fn collapse_untracked_directories<'a>(
+    untracked_files: Vec<PathBuf>,
+    tracked_files: impl Iterator<Item = &'a PathBuf>,
+) -> Vec<PathBuf> {
+    let repository_root = repository_root_path();
+    let tracked_directories: HashSet<_> = tracked_files
+        .flat_map(|path| path.ancestors().skip(1))
+        .collect();
+
+    untracked_files
+        .into_iter()
+        .map(|path| {
+            let outermost_untracked = path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != repository_root && !tracked_directories.contains(dir))
+                .last();
+            match outermost_untracked {
+                Some(dir) => dir.to_path_buf(),
+                None => path,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use anyhow::Result;
 
-    use crate::test_utils::TestRepo;
+    use crate::{config::Config, test_utils::TestRepo, worktree};
 
     use super::*;
 
@@ -156,13 +264,13 @@ mod test {
                 .commit("Initial commit")?;
         }
 
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
         assert_eq!(1, status.untracked_files.len());
         let untracked_file = status.untracked_files.first().unwrap();
         assert_eq!(repo.path().join("a.txt"), untracked_file.as_path());
 
         let _repo = repo.stage("a.txt")?;
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
         assert_eq!(0, status.untracked_files.len());
 
         Ok(())
@@ -187,7 +295,7 @@ mod test {
         }
         repo.file("a.txt", "a")?.stage(".")?;
 
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
         assert_eq!(1, status.staged_changes.len());
         let staged_file = status.staged_changes.first().unwrap();
         let expected = StatusEntry {
@@ -197,7 +305,7 @@ mod test {
         assert_eq!(&expected, staged_file);
 
         repo.commit("Commit 1")?.file("a.txt", "b")?.stage(".")?;
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
         assert_eq!(1, status.staged_changes.len());
         let staged_file = status.staged_changes.first().unwrap();
         let expected = StatusEntry {
@@ -207,7 +315,7 @@ mod test {
         assert_eq!(&expected, staged_file);
 
         repo.commit("Commit 2")?.remove_file("a.txt")?.stage(".")?;
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
         assert_eq!(1, status.staged_changes.len());
         let staged_file = status.staged_changes.first().unwrap();
         let expected = StatusEntry {
@@ -241,7 +349,7 @@ mod test {
             .remove_file("b.txt")?
             .file("c.txt", "c")?;
 
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
         let expected = vec![
             StatusEntry {
                 path: repo.path().join("a.txt"),
@@ -257,10 +365,43 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_autocrlf_clean() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let mut config = Config::load()?;
+        config.set("core.autocrlf", "true")?;
+        config.save()?;
+        repo.file("a.txt", "a\r\nb\r\n")?
+            .stage(".")?
+            .commit("Add a")?;
+
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        assert!(status.staged_changes.is_empty());
+        assert!(status.unstaged_changes.is_empty());
+
+        // The stored LF content, as checked out
+        worktree::checkout(&Tree::current()?.unwrap())?;
+        repo.file("a.txt", "a\nb\n")?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        assert!(status.unstaged_changes.is_empty());
+
+        repo.file("a.txt", "a\r\nc\r\n")?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        assert_eq!(
+            vec![StatusEntry {
+                path: repo.path().join("a.txt"),
+                status: FileStatus::Modified,
+            }],
+            status.unstaged_changes
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_clean_repo() -> Result<()> {
         let _repo = TestRepo::new()?;
-        let status = RepositoryStatus::load()?;
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
 
         assert!(status.staged_changes.is_empty());
         assert!(status.unstaged_changes.is_empty());
@@ -268,4 +409,79 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_untracked_modes() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("src/main.rs", "main")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?
+            .file("b.txt", "b")?
+            .file("src/lib.rs", "lib")?
+            .file("docs/guide/intro.md", "intro")?
+            .file("docs/readme.md", "readme")?;
+
+        let status = RepositoryStatus::load(UntrackedMode::No)?;
+        assert!(status.untracked_files.is_empty());
+        assert_eq!(1, status.unstaged_changes.len());
+
+        let status = RepositoryStatus::load(UntrackedMode::Normal)?;
+        assert_eq!(
+            vec![
+                repo.path().join("b.txt"),
+                repo.path().join("docs"),
+                repo.path().join("src/lib.rs"),
+            ],
+            status.untracked_files
+        );
+        assert_eq!(1, status.unstaged_changes.len());
+
+        let status = RepositoryStatus::load(UntrackedMode::All)?;
+        assert_eq!(
+            vec![
+                repo.path().join("b.txt"),
+                repo.path().join("docs/guide/intro.md"),
+                repo.path().join("docs/readme.md"),
+                repo.path().join("src/lib.rs"),
+            ],
+            status.untracked_files
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_type_changes() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .remove_file("a.txt")?
+            .remove_file("b.txt")?
+            .file("b.txt/c.txt", "c")?;
+        std::os::unix::fs::symlink(repo.path().join("b.txt/c.txt"), repo.path().join("a.txt"))?;
+
+        let expected = vec![
+            StatusEntry {
+                path: repo.path().join("a.txt"),
+                status: FileStatus::TypeChanged,
+            },
+            StatusEntry {
+                path: repo.path().join("b.txt"),
+                status: FileStatus::TypeChanged,
+            },
+        ];
+        for mode in [UntrackedMode::Normal, UntrackedMode::No] {
+            let status = RepositoryStatus::load(mode)?;
+            assert_eq!(expected, status.unstaged_changes);
+            assert!(status.staged_changes.is_empty());
+        }
+        assert_eq!("typechange", FileStatus::TypeChanged.to_string());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate};
+use regex::Regex;
+
+use crate::{
+    hash::Hash,
+    objects::commit::{Commit, CommitCache},
+    revparse,
+};
+
+/// Lists the commits selected by `spec`: everything reachable from a single
+/// revision, or for `A..B` everything reachable from `B` but not from `A`.
+/// An omitted side of a range defaults to `HEAD`. Only commits within
+/// `limits` are listed.
+pub fn rev_list(spec: &str, limits: &Limits) -> Result<Vec<Hash>> {
+    let (include, exclude) = parse_range(spec)?;
+    let commits = limits
+        .walk(&include, &exclude)
+        .with_context(|| format!("Unable to list revisions for \"{spec}\""))?;
+
+    Ok(commits.iter().map(|commit| *commit.hash()).collect())
+}
+
+/// Resolves `spec` to the commits to walk from and the commits whose history
+/// is excluded, see [`rev_list`].
+fn parse_range(spec: &str) -> Result<(Vec<Hash>, Vec<Hash>)> {
+    match spec.split_once("..") {
+        Some((exclude, include)) => {
+            let exclude = if exclude.is_empty() { "HEAD" } else { exclude };
+            let include = if include.is_empty() { "HEAD" } else { include };
+            Ok((
+                vec![revparse::resolve(include)?],
+                vec![revparse::resolve(exclude)?],
+            ))
+        }
+        None => Ok((vec![revparse::resolve(spec)?], vec![])),
+    }
+}
+
+/// Lists the commits reachable from `include` but not from `exclude`,
+/// children before their parents, see [`topological_order`].
+pub fn walk(include: &[Hash], exclude: &[Hash]) -> Result<Vec<Hash>> {
+    Ok(
+        walk_parents(&mut CommitCache::new()?, include, exclude, false)?
+            .iter()
+            .map(|commit| *commit.hash())
+            .collect(),
+    )
+}
+
+/// Like [`walk`], but returns the commits and, with `first_parent`, follows
+/// only the first parent of merges. Commits are walked by hash and loaded
+/// through `cache`, so each is read from disk at most once.
+fn walk_parents(
+    cache: &mut CommitCache,
+    include: &[Hash],
+    exclude: &[Hash],
+    first_parent: bool,
+) -> Result<Vec<Commit>> {
+    let mut excluded = HashSet::new();
+    for hash in exclude {
+        excluded.extend(cache.ancestors(hash)?);
+    }
+
+    let mut commits = HashMap::new();
+    let mut pending: Vec<_> = include.to_vec();
+    while let Some(hash) = pending.pop() {
+        if excluded.contains(&hash) || commits.contains_key(&hash) {
+            continue;
+        }
+        let commit = cache.load(&hash)?;
+        if first_parent {
+            pending.extend(commit.parent_hashes().first());
+        } else {
+            pending.extend(commit.parent_hashes());
+        }
+        commits.insert(hash, commit.clone());
+    }
+
+    let ordered = topological_order(&commits);
+    Ok(ordered
+        .iter()
+        .filter_map(|hash| commits.remove(hash))
+        .collect())
+}
+
+/// Orders `commits` with Kahn's algorithm so every commit comes before its
+/// parents, however their timestamps are skewed. Among commits whose
+/// children have all been listed, the most recently authored comes first.
+fn topological_order(commits: &HashMap<Hash, Commit>) -> Vec<Hash> {
+    let mut child_counts: HashMap<Hash, usize> = HashMap::new();
+    for commit in commits.values() {
+        for parent_hash in commit.parent_hashes() {
+            if commits.contains_key(parent_hash) {
+                *child_counts.entry(*parent_hash).or_default() += 1;
+            }
+        }
+    }
+
+    let mut ready: Vec<_> = commits
+        .keys()
+        .filter(|hash| !child_counts.contains_key(hash))
+        .copied()
+        .collect();
+    let mut ordered = vec![];
+    while !ready.is_empty() {
+        ready.sort_by_key(|hash| commits[hash].author().timestamp().timestamp());
+        let hash = ready.pop().unwrap();
+        for parent_hash in commits[&hash].parent_hashes() {
+            if let Some(count) = child_counts.get_mut(parent_hash) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(*parent_hash);
+                }
+            }
+        }
+        ordered.push(hash);
+    }
+
+    ordered
+}
+
+/// Limits on which commits of a history walk are listed.
+#[derive(Debug, Default, Clone)]
+pub struct Limits {
+    /// Stops after listing this many commits.
+    pub max_count: Option<usize>,
+    /// Stops at the first commit authored before this date.
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Skips commits authored after this date.
+    pub until: Option<DateTime<FixedOffset>>,
+    /// Lists only commits whose `Name <email>` author matches.
+    pub author: Option<Regex>,
+    /// Lists only commits whose message matches.
+    pub grep: Option<Regex>,
+    /// Follows only the first parent of merges, leaving out the history they
+    /// merged in.
+    pub first_parent: bool,
+}
+
+impl Limits {
+    /// Lists the commits reachable from `include` but not from `exclude`
+    /// within the limits, newest first.
+    pub fn walk(&self, include: &[Hash], exclude: &[Hash]) -> Result<Vec<Commit>> {
+        let commits = walk_parents(
+            &mut CommitCache::new()?,
+            include,
+            exclude,
+            self.first_parent,
+        )?;
+        Ok(self.apply(commits))
+    }
+
+    /// Whether a walk listing commits newest first should stop at `commit`,
+    /// having already listed `listed` commits.
+    pub fn stops_at(&self, commit: &Commit, listed: usize) -> bool {
+        self.max_count.is_some_and(|max_count| listed >= max_count)
+            || self
+                .since
+                .is_some_and(|since| *commit.author().timestamp() < since)
+    }
+
+    /// Whether `commit` is listed when the walk reaches it.
+    pub fn includes(&self, commit: &Commit) -> bool {
+        let author = commit.author();
+        self.until.is_none_or(|until| *author.timestamp() <= until)
+            && self.author.as_ref().is_none_or(|pattern| {
+                pattern.is_match(&format!("{} <{}>", author.name(), author.email()))
+            })
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(commit.message()))
+    }
+
+    /// Applies the limits to `commits`, ordered newest first.
+    pub fn apply(&self, commits: impl IntoIterator<Item = Commit>) -> Vec<Commit> {
+        let mut listed = vec![];
+        for commit in commits {
+            if self.stops_at(&commit, listed.len()) {
+                break;
+            }
+            if self.includes(&commit) {
+                listed.push(commit);
+            }
+        }
+
+        listed
+    }
+}
+
+/// Parses a `--since`/`--until` date: RFC 3339, or `YYYY-MM-DD` for
+/// midnight local time.
+pub fn parse_date(date: &str) -> Result<DateTime<FixedOffset>> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date_time);
+    }
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|date_time| date_time.fixed_offset())
+        .with_context(|| format!("Invalid date \"{date}\". Expected YYYY-MM-DD or RFC 3339"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{
+        index::Index,
+        objects::signature::Signature,
+        paths::{objects_path, rygit_path},
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_rev_list() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let master = revparse::resolve("master")?;
+        let master_parent = revparse::resolve("master~1")?;
+        assert_eq!(
+            vec![master, master_parent],
+            rev_list("master", &Limits::default())?
+        );
+
+        repo.branch("feature")?
+            .switch("feature")?
+            .file("c.txt", "c")?
+            .stage(".")?
+            .commit("Feature commit")?
+            .file("d.txt", "d")?
+            .stage(".")?
+            .commit("Another feature commit")?;
+        let feature = revparse::resolve("feature")?;
+        let feature_parent = revparse::resolve("feature~1")?;
+
+        assert_eq!(4, rev_list("feature", &Limits::default())?.len());
+        assert_eq!(
+            vec![feature, feature_parent],
+            rev_list("master..feature", &Limits::default())?
+        );
+        assert_eq!(
+            vec![feature, feature_parent],
+            rev_list("master..", &Limits::default())?
+        );
+        assert!(rev_list("feature..master", &Limits::default())?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits() -> Result<()> {
+        let repo = TestRepo::new()?;
+        for (name, date) in [
+            ("a.txt", "2024-01-01T12:00:00+00:00"),
+            ("b.txt", "2024-02-01T12:00:00+00:00"),
+            ("c.txt", "2024-03-01T12:00:00+00:00"),
+            ("d.txt", "2024-04-01T12:00:00+00:00"),
+        ] {
+            repo.file(name, name)?.stage(".")?;
+            let author = Signature::new("Larry Sellers", "lsellers@test.com")
+                .with_timestamp(parse_date(date)?);
+            Commit::create(&Index::load()?, name, author.clone(), author)?;
+        }
+        let commits: Vec<_> = rev_list("HEAD", &Limits::default())?
+            .iter()
+            .map(Commit::load)
+            .collect::<Result<_>>()?;
+
+        let limits = Limits {
+            since: Some(parse_date("2024-01-15T00:00:00+00:00")?),
+            until: Some(parse_date("2024-03-15T00:00:00+00:00")?),
+            ..Limits::default()
+        };
+        let messages: Vec<_> = limits
+            .apply(commits)
+            .iter()
+            .map(|commit| commit.message().to_string())
+            .collect();
+        assert_eq!(vec!["c.txt", "b.txt"], messages);
+
+        let limits = Limits {
+            max_count: Some(1),
+            since: Some(parse_date("2024-01-15T00:00:00+00:00")?),
+            ..Limits::default()
+        };
+        let commits: Vec<_> = rev_list("HEAD", &Limits::default())?
+            .iter()
+            .map(Commit::load)
+            .collect::<Result<_>>()?;
+        assert_eq!(1, limits.apply(commits).len());
+
+        assert!(parse_date("2024-01-15").is_ok());
+        assert!(parse_date("last tuesday").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_walk_with_skewed_timestamps() -> Result<()> {
+        let repo = TestRepo::new()?;
+        let commit_at = |name: &str, date: &str| -> Result<Hash> {
+            repo.file(name, name)?.stage(".")?;
+            let author = Signature::new("Larry Sellers", "lsellers@test.com")
+                .with_timestamp(parse_date(date)?);
+            Ok(*Commit::create(&Index::load()?, name, author.clone(), author)?.hash())
+        };
+        let parent = commit_at("a.txt", "2024-03-01T12:00:00+00:00")?;
+        repo.branch("side")?;
+        let child = commit_at("b.txt", "2024-01-01T12:00:00+00:00")?;
+        repo.switch("side")?;
+        let side = commit_at("c.txt", "2024-02-01T12:00:00+00:00")?;
+
+        assert_eq!(vec![side, child, parent], walk(&[child, side], &[])?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_walk_loads_each_commit_once() -> Result<()> {
+        let repo = TestRepo::new()?;
+        for i in 0..20 {
+            repo.file("a.txt", &i.to_string())?
+                .stage(".")?
+                .commit(format!("Commit {i}"))?;
+        }
+        let head = revparse::resolve("HEAD")?;
+        let base = revparse::resolve("HEAD~10")?;
+
+        let mut cache = CommitCache::new()?;
+        assert_eq!(10, walk_parents(&mut cache, &[head], &[base], false)?.len());
+        assert_eq!(20, cache.len());
+        assert_eq!(Some(base), cache.merge_base(&head, &base)?);
+        assert_eq!(20, walk_parents(&mut cache, &[head], &[], false)?.len());
+        assert_eq!(20, cache.len());
+
+        // Nothing is read from disk once every commit is cached
+        fs::rename(objects_path(), rygit_path().join("objects.moved"))?;
+        assert_eq!(
+            20,
+            walk_parents(&mut cache, &[head, base], &[], false)?.len()
+        );
+        assert!(walk_parents(&mut CommitCache::new()?, &[head], &[], false).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    error::RygitError,
+    hash::Hash,
+    objects::{ObjectKind, commit::Commit, loose_object_paths, read_object},
+    refs::{self, ref_exists},
+};
+
+const MIN_ABBREVIATED_HASH_LENGTH: usize = 4;
+
+/// Resolves a revision to a commit hash.
+///
+/// A revision is a base name followed by any number of `~<n>` (n-th first
+/// parent ancestor) and `^<n>` (n-th parent) suffixes, where `n` defaults to
+/// 1 and `^0` is the commit itself. The base name is tried, in order, as
+/// `HEAD`, a branch, a tag, a remote-tracking branch and a full or abbreviated
+/// commit hash.
+pub fn resolve(revision: &str) -> Result<Hash> {
+    let base_end = revision.find(['~', '^']).unwrap_or(revision.len());
+    let (base, mut suffixes) = revision.split_at(base_end);
+    let mut hash =
+        resolve_base(base).with_context(|| format!("Unable to resolve revision \"{revision}\""))?;
+
+    while let Some(operator) = suffixes.chars().next() {
+        suffixes = &suffixes[1..];
+        let digits_end = suffixes
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(suffixes.len());
+        let (digits, rest) = suffixes.split_at(digits_end);
+        suffixes = rest;
+        let n: usize = if digits.is_empty() {
+            1
+        } else {
+            digits
+                .parse()
+                .with_context(|| format!("Invalid revision \"{revision}\""))?
+        };
+
+        match operator {
+            '~' => {
+                for _ in 0..n {
+                    hash = nth_parent(&hash, 1, revision)?;
+                }
+            }
+            '^' if n > 0 => hash = nth_parent(&hash, n, revision)?,
+            '^' => {}
+            _ => bail!("Invalid revision \"{revision}\""),
+        }
+    }
+
+    Ok(hash)
+}
+
+fn nth_parent(hash: &Hash, n: usize, revision: &str) -> Result<Hash> {
+    let commit = Commit::load(hash)?;
+    commit.parent_hashes().get(n - 1).copied().with_context(|| {
+        format!("Unable to resolve revision \"{revision}\". {hash} has no parent {n}")
+    })
+}
+
+fn resolve_base(base: &str) -> Result<Hash> {
+    if base.is_empty() || base == "HEAD" || base == "@" {
+        return resolve_head();
+    }
+
+    for ref_name in [
+        format!("refs/heads/{base}"),
+        format!("refs/tags/{base}"),
+        format!("refs/remotes/{base}"),
+        format!("refs/remotes/{base}/HEAD"),
+        base.to_string(),
+    ] {
+        if ref_exists(&ref_name)? {
+            return refs::resolve(&ref_name)?
+                .with_context(|| format!("{base} does not have any commits yet"));
+        }
+    }
+
+    resolve_abbreviated_hash(base)
+}
+
+fn resolve_head() -> Result<Hash> {
+    refs::resolve("HEAD")
+        .context("Unable to read HEAD")?
+        .context("HEAD does not point to a commit yet")
+}
+
+fn resolve_abbreviated_hash(prefix: &str) -> Result<Hash> {
+    if prefix.len() < MIN_ABBREVIATED_HASH_LENGTH || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(RygitError::UnknownRevision(prefix.to_string()).into());
+    }
+    let prefix = prefix.to_lowercase();
+
+    let mut matches = vec![];
+    for object_path in loose_object_paths()? {
+        let hash = Hash::from_object_path(&object_path)?;
+        if hash.to_hex().starts_with(&prefix) {
+            matches.push(hash);
+        }
+    }
+
+    match matches.as_slice() {
+        [] => Err(RygitError::UnknownRevision(prefix).into()),
+        [hash] => {
+            let (kind, _) = read_object(hash)?;
+            if kind != ObjectKind::Commit {
+                return Err(RygitError::UnexpectedObjectKind {
+                    hash: *hash,
+                    expected: ObjectKind::Commit,
+                    actual: kind,
+                }
+                .into());
+            }
+            Ok(*hash)
+        }
+        _ => bail!(
+            "Ambiguous revision \"{prefix}\". It matches {} objects",
+            matches.len()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::{Ok, Result};
+
+    use crate::{paths::head_ref_path, test_utils::TestRepo};
+
+    use super::*;
+
+    fn head_hash() -> Result<Hash> {
+        Hash::from_hex(&fs::read_to_string(head_ref_path())?)
+    }
+
+    #[test]
+    fn test_resolve() -> Result<()> {
+        let repo = TestRepo::new()?;
+        assert!(resolve("HEAD").is_err());
+
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let first = head_hash()?;
+        repo.branch("test")?
+            .file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let second = head_hash()?;
+        repo.file("c.txt", "c")?
+            .stage(".")?
+            .commit("Third commit")?;
+        let third = head_hash()?;
+
+        assert_eq!(third, resolve("HEAD")?);
+        assert_eq!(third, resolve("master")?);
+        assert_eq!(first, resolve("test")?);
+        assert_eq!(second, resolve("HEAD~1")?);
+        assert_eq!(second, resolve("HEAD^")?);
+        assert_eq!(first, resolve("HEAD~2")?);
+        assert_eq!(first, resolve("master^~")?);
+        assert_eq!(third, resolve("HEAD^0")?);
+        assert_eq!(third, resolve(&third.to_hex()[..10])?);
+        assert_eq!(second, resolve(&format!("{}~1", &third.to_hex()[..10]))?);
+        assert_eq!(second, resolve(&second.to_hex())?);
+        assert!(resolve("HEAD~3").is_err());
+        assert!(resolve("missing").is_err());
+
+        Ok(())
+    }
+}
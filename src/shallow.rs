@@ -0,0 +1,35 @@
+use std::{collections::HashSet, fs, io::ErrorKind};
+
+use anyhow::{Context, Result};
+
+use crate::{hash::Hash, paths::shallow_path};
+
+// shallow format:
+// <commit hash>...
+//
+// The commits of a shallow clone whose parents were left out, one per line.
+// History walks treat them as root commits.
+
+/// The commits at the shallow boundary, empty for a complete history.
+pub fn read() -> Result<HashSet<Hash>> {
+    let contents = match fs::read_to_string(shallow_path()) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(error) => return Err(error).context("Unable to read shallow file"),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Hash::from_hex(line.trim()).context("Invalid shallow file"))
+        .collect()
+}
+
+/// Adds `commits` to the shallow boundary.
+pub fn add(commits: &HashSet<Hash>) -> Result<()> {
+    let mut boundary: Vec<_> = read()?.union(commits).map(|hash| hash.to_hex()).collect();
+    boundary.sort();
+    let contents: String = boundary.iter().map(|hash| format!("{hash}\n")).collect();
+
+    fs::write(shallow_path(), contents).context("Unable to write shallow file")
+}
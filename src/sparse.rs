@@ -0,0 +1,101 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{attributes::glob_match, paths::sparse_checkout_path};
+
+// sparse-checkout format:
+// <pattern>...
+//
+// One pattern per line, blank lines and `#` comments are skipped. Patterns
+// match like attribute patterns, against the file or any directory above it,
+// so `docs` or `docs/` takes in everything under `docs`.
+//
+// Files that don't match any pattern are left out of the working tree. They
+// stay in the index and in commits, and their absence isn't a deletion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseCheckout {
+    patterns: Vec<String>,
+}
+
+impl SparseCheckout {
+    /// The patterns in effect, or `None` when every file is checked out.
+    pub fn load() -> Result<Option<Self>> {
+        let contents = match fs::read_to_string(sparse_checkout_path()) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error).context("Unable to read sparse-checkout file"),
+        };
+
+        Ok(Some(Self::parse(&contents)))
+    }
+
+    /// Records `patterns` as the ones to check out.
+    pub fn set(patterns: &[String]) -> Result<Self> {
+        if patterns.is_empty() {
+            bail!("Unable to set sparse checkout. No patterns given");
+        }
+
+        let path = sparse_checkout_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .context("Unable to set sparse checkout. Unable to create info directory")?;
+        }
+        let contents: String = patterns
+            .iter()
+            .map(|pattern| format!("{pattern}\n"))
+            .collect();
+        fs::write(path, &contents)
+            .context("Unable to set sparse checkout. Unable to write sparse-checkout file")?;
+
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let patterns = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.trim_end_matches('/').to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+
+        Self { patterns }
+    }
+
+    /// Whether `path`, relative to the repository root, belongs in the
+    /// working tree.
+    pub fn includes(&self, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| {
+                let ancestor = ancestor.to_string_lossy();
+                let name = ancestor.rsplit('/').next().unwrap_or(&ancestor);
+                self.patterns
+                    .iter()
+                    .any(|pattern| match pattern.strip_prefix('/') {
+                        Some(anchored) => glob_match(anchored, &ancestor),
+                        None if pattern.contains('/') => glob_match(pattern, &ancestor),
+                        None => glob_match(pattern, name),
+                    })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes() {
+        let sparse = SparseCheckout::parse("# docs only\ndocs/\n/src/*.rs\n*.md\n");
+
+        assert!(sparse.includes(Path::new("docs/a.txt")));
+        assert!(sparse.includes(Path::new("docs/nested/b.txt")));
+        assert!(sparse.includes(Path::new("src/main.rs")));
+        assert!(sparse.includes(Path::new("other/README.md")));
+        assert!(!sparse.includes(Path::new("src/nested/lib.rs")));
+        assert!(!sparse.includes(Path::new("other/a.txt")));
+        assert!(!sparse.includes(Path::new("a.txt")));
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    hash::Hash,
+    refs::{list_refs, ref_exists, update_ref},
+    revparse,
+};
+
+/// A lightweight tag: a ref under `refs/tags` naming a commit. Unlike a
+/// branch it never moves.
+pub struct Tag {
+    name: String,
+    commit_hash: Hash,
+}
+
+impl Tag {
+    /// Tags `revision`, or the current commit when omitted.
+    pub fn create(name: impl Into<String>, revision: Option<&str>) -> Result<Self> {
+        let name = name.into();
+        if ref_exists(&format!("refs/tags/{name}"))? {
+            bail!("Tag \"{name}\" already exists");
+        }
+
+        let commit_hash = revparse::resolve(revision.unwrap_or("HEAD"))
+            .context("Unable to create tag. Invalid revision")?;
+        update_ref(&format!("refs/tags/{name}"), &commit_hash, None)
+            .context("Unable to create tag")?;
+
+        Ok(Self { name, commit_hash })
+    }
+
+    pub fn list() -> Result<Vec<Tag>> {
+        let tags = list_refs("refs/tags/")
+            .context("Unable to list tags")?
+            .into_iter()
+            .map(|(name, commit_hash)| Self {
+                name: name["refs/tags/".len()..].to_string(),
+                commit_hash,
+            })
+            .collect();
+
+        Ok(tags)
+    }
+
+    /// Groups tag names by the commit they point at.
+    pub fn by_commit() -> Result<HashMap<Hash, Vec<String>>> {
+        let mut tags: HashMap<Hash, Vec<String>> = HashMap::new();
+        for tag in Tag::list()? {
+            tags.entry(tag.commit_hash).or_default().push(tag.name);
+        }
+
+        Ok(tags)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn commit_hash(&self) -> &Hash {
+        &self.commit_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+
+    use crate::test_utils::TestRepo;
+
+    use super::*;
+
+    #[test]
+    fn test_create_and_list() -> Result<()> {
+        let repo = TestRepo::new()?;
+        assert!(Tag::create("v1", None).is_err());
+
+        repo.file("a.txt", "a")?
+            .stage(".")?
+            .commit("Initial commit")?;
+        let first = revparse::resolve("HEAD")?;
+        repo.file("b.txt", "b")?
+            .stage(".")?
+            .commit("Second commit")?;
+
+        Tag::create("v1.0", Some("HEAD~1"))?;
+        Tag::create("release/v2.0", None)?;
+        assert!(Tag::create("v1.0", None).is_err());
+
+        let tags = Tag::list()?;
+        let names: Vec<_> = tags.iter().map(|t| t.name()).collect();
+        assert_eq!(vec!["release/v2.0", "v1.0"], names);
+        assert_eq!(&first, tags[1].commit_hash());
+        assert_eq!(first, revparse::resolve("v1.0")?);
+
+        Ok(())
+    }
+}
@@ -1,34 +1,98 @@
 use std::{
+    cell::RefCell,
     env,
     fs::{self, File},
     io::Write,
     path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
 };
 
 use anyhow::Result;
 use tempfile::TempDir;
 
-use crate::{branch::Branch, commands};
+use crate::{
+    branch::Branch,
+    commands::{self, commit::CommitOptions},
+    paths::{reset_repository_root_path, set_global_config_path},
+    progress::{NoProgress, Progress},
+};
+
+// Repositories are located through the process-wide current directory, so
+// tests that drive a repository must not run concurrently. The lock is
+// re-entrant per thread so a single test can create several repositories.
+static REPOSITORY_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    static REPOSITORY_GUARD: RefCell<(usize, Option<MutexGuard<'static, ()>>)> =
+        const { RefCell::new((0, None)) };
+}
+
+struct RepositoryLock;
+
+impl RepositoryLock {
+    fn acquire() -> Self {
+        REPOSITORY_GUARD.with(|guard| {
+            let mut guard = guard.borrow_mut();
+            if guard.0 == 0 {
+                let lock = REPOSITORY_LOCK
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                guard.1 = Some(lock);
+            }
+            guard.0 += 1;
+        });
+
+        Self
+    }
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        REPOSITORY_GUARD.with(|guard| {
+            let mut guard = guard.borrow_mut();
+            guard.0 -= 1;
+            if guard.0 == 0 {
+                guard.1 = None;
+            }
+        });
+    }
+}
 
 pub struct TestRepo {
     _temp_dir: TempDir,
+    _global_config_dir: TempDir,
     path: PathBuf,
+    _lock: RepositoryLock,
 }
 
 impl TestRepo {
     pub fn new() -> Result<Self> {
+        let lock = RepositoryLock::acquire();
         let temp_dir = TempDir::new()?;
         let path = temp_dir.path().canonicalize()?;
+        let global_config_dir = TempDir::new()?;
+        set_global_config_path(global_config_dir.path().join(".rygitconfig"));
         env::set_current_dir(&path)?;
-        commands::init::run(&path)?;
+        reset_repository_root_path();
+        commands::init::run(&path, false)?;
 
         let test_repo = Self {
             _temp_dir: temp_dir,
+            _global_config_dir: global_config_dir,
             path,
+            _lock: lock,
         };
         Ok(test_repo)
     }
 
+    /// Makes this repository the one that subsequent operations act on.
+    pub fn enter(&self) -> Result<&Self> {
+        env::set_current_dir(&self.path)?;
+        reset_repository_root_path();
+
+        Ok(self)
+    }
+
     pub fn file(&self, relative_path: impl AsRef<Path>, contents: &str) -> Result<&Self> {
         let file_path = self.path.join(relative_path.as_ref());
         println!("creating file {}", file_path.display());
@@ -54,18 +118,21 @@ impl TestRepo {
         if path.is_relative() {
             path = self.path.join(path).canonicalize()?;
         }
-        commands::add::run(path)?;
+        commands::add::run(path, &mut NoProgress)?;
 
         Ok(self)
     }
 
     pub fn commit(&self, message: impl Into<String>) -> Result<&Self> {
-        commands::commit::run(message)?;
+        let options = CommitOptions {
+            ..CommitOptions::default()
+        };
+        commands::commit::run(message, options)?;
         Ok(self)
     }
 
     pub fn branch(&self, name: impl Into<String>) -> Result<&Self> {
-        Branch::create(name)?;
+        Branch::create(name, None)?;
         Ok(self)
     }
 
@@ -78,3 +145,25 @@ impl TestRepo {
         &self.path
     }
 }
+
+/// Records the progress updates it receives.
+#[derive(Debug, Default)]
+pub struct CountingProgress {
+    pub total: Option<usize>,
+    pub ticks: usize,
+    pub finished: bool,
+}
+
+impl Progress for CountingProgress {
+    fn start(&mut self, total: Option<usize>) {
+        self.total = total;
+    }
+
+    fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+    }
+}
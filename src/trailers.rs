@@ -0,0 +1,76 @@
+/// The trailers of `message` as key/value pairs, in order. Trailers are the
+/// `Token: value` lines, such as `Signed-off-by`, that make up the last
+/// paragraph of a commit message. A message whose only paragraph looks like
+/// trailers is treated as having none.
+pub fn parse(message: &str) -> Vec<(String, String)> {
+    trailer_block(message)
+        .map(|block| {
+            block
+                .lines()
+                .filter_map(|line| line.split_once(": "))
+                .map(|(key, value)| (key.to_string(), value.trim().to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Appends `trailer` to the trailer block of `message`, unless the block
+/// already contains it. Without a trailer block, the trailer starts a new
+/// paragraph.
+pub fn append(message: &str, trailer: &str) -> String {
+    let message = message.trim_end();
+    match trailer_block(message) {
+        Some(block) if block.lines().any(|line| line == trailer) => message.to_string(),
+        Some(_) => format!("{message}\n{trailer}"),
+        None if message.is_empty() => trailer.to_string(),
+        None => format!("{message}\n\n{trailer}"),
+    }
+}
+
+/// Whether `line` looks like a `Token: value` trailer.
+pub fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(token, _)| {
+        !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+fn trailer_block(message: &str) -> Option<&str> {
+    let message = message.trim_end();
+    let (_, last_paragraph) = message.rsplit_once("\n\n")?;
+    (!last_paragraph.is_empty() && last_paragraph.lines().all(is_trailer)).then_some(last_paragraph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            vec![
+                ("Fixes".to_string(), "#12".to_string()),
+                (
+                    "Reviewed-by".to_string(),
+                    "Someone <s@test.com>".to_string()
+                ),
+            ],
+            parse("Subject\n\nBody\n\nFixes: #12\nReviewed-by: Someone <s@test.com>\n")
+        );
+        assert!(parse("Subject: with a colon").is_empty());
+        assert!(parse("Subject\n\nNot: a trailer\nbecause of this line").is_empty());
+    }
+
+    #[test]
+    fn test_append() {
+        assert_eq!("Subject\n\nFixes: #12", append("Subject", "Fixes: #12"));
+        assert_eq!(
+            "Subject\n\nFixes: #12\nFixes: #13",
+            append("Subject\n\nFixes: #12", "Fixes: #13")
+        );
+        assert_eq!(
+            "Subject\n\nFixes: #12",
+            append("Subject\n\nFixes: #12\n", "Fixes: #12")
+        );
+        assert_eq!("Fixes: #12", append("", "Fixes: #12"));
+    }
+}
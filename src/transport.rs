@@ -0,0 +1,286 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    hash::Hash,
+    objects::{ObjectKind, locate_in, read_object_in},
+    paths::objects_path,
+    shallow,
+};
+
+/// What a copy did with each object it was given.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Transfer {
+    pub copied: usize,
+    /// Objects the destination already had.
+    pub skipped: usize,
+}
+
+/// Copies every object reachable from `tips` in `source_objects_path` that
+/// the destination doesn't already have, see [`enumerate_objects`].
+pub fn copy_reachable_objects(
+    source_objects_path: impl AsRef<Path>,
+    destination_objects_path: impl AsRef<Path>,
+    tips: &[Hash],
+    shallow: &HashSet<Hash>,
+) -> Result<Transfer> {
+    let source_objects_path = source_objects_path.as_ref();
+    let destination_objects_path = destination_objects_path.as_ref();
+    let hashes = enumerate_objects(
+        source_objects_path,
+        Some(destination_objects_path),
+        tips,
+        shallow,
+    )?;
+
+    copy_objects(source_objects_path, destination_objects_path, &hashes)
+}
+
+/// Lists the objects reachable from `tips` in this repository, stopping at
+/// the shallow boundary like a fetch would.
+pub fn reachable_objects(tips: &[Hash]) -> Result<Vec<Hash>> {
+    enumerate_objects(objects_path(), None, tips, &shallow::read()?)
+}
+
+/// The objects reachable from `wanted` in this repository that the object
+/// store at `destination_objects_path` lacks, which is what sending `wanted`
+/// there has to copy. An object the destination has is assumed to come with
+/// everything it references, so shared history is left out without being
+/// walked.
+pub fn missing_objects(
+    destination_objects_path: impl AsRef<Path>,
+    wanted: &[Hash],
+) -> Result<Vec<Hash>> {
+    enumerate_objects(
+        objects_path(),
+        Some(destination_objects_path.as_ref()),
+        wanted,
+        &shallow::read()?,
+    )
+}
+
+/// Lists the objects reachable from `tips` in `source_objects_path`. The
+/// walk doesn't follow the parents of `shallow` commits, and with a
+/// destination it stops at objects the destination already holds, since
+/// their history must be there too.
+pub fn enumerate_objects(
+    source_objects_path: impl AsRef<Path>,
+    destination_objects_path: Option<&Path>,
+    tips: &[Hash],
+    shallow: &HashSet<Hash>,
+) -> Result<Vec<Hash>> {
+    let source_objects_path = source_objects_path.as_ref();
+    let mut visited = HashSet::new();
+    let mut pending = tips.to_vec();
+    let mut hashes = vec![];
+    while let Some(hash) = pending.pop() {
+        if !visited.insert(hash) {
+            continue;
+        }
+        if let Some(destination_objects_path) = destination_objects_path
+            && locate_in(destination_objects_path, &hash)?.is_some()
+        {
+            continue;
+        }
+
+        let (kind, body) = read_object_in(source_objects_path, &hash)?;
+        let mut references = referenced_objects(kind, &body)?;
+        if shallow.contains(&hash) {
+            // Keep the commit's tree, which comes before its parents
+            references.truncate(1);
+        }
+        pending.extend(references);
+        hashes.push(hash);
+    }
+
+    Ok(hashes)
+}
+
+/// Copies the objects in `hashes` from one object store to another,
+/// skipping any the destination already has. Objects are copied as the files
+/// they're stored in, so they're never decompressed or recompressed, and in
+/// batches by fan-out directory, so each directory is created once.
+pub fn copy_objects(
+    source_objects_path: impl AsRef<Path>,
+    destination_objects_path: impl AsRef<Path>,
+    hashes: &[Hash],
+) -> Result<Transfer> {
+    let source_objects_path = source_objects_path.as_ref();
+    let destination_objects_path = destination_objects_path.as_ref();
+    let mut batches: BTreeMap<String, Vec<Hash>> = BTreeMap::new();
+    for hash in hashes {
+        batches
+            .entry(hash.to_hex()[0..2].to_string())
+            .or_default()
+            .push(*hash);
+    }
+
+    let mut transfer = Transfer::default();
+    for (fan_out, batch) in batches {
+        let destination_dir = destination_objects_path.join(fan_out);
+        fs::create_dir_all(&destination_dir).with_context(|| {
+            format!(
+                "Unable to copy objects. Unable to create {}",
+                destination_dir.display()
+            )
+        })?;
+        for hash in batch {
+            if locate_in(destination_objects_path, &hash)?.is_some() {
+                transfer.skipped += 1;
+                continue;
+            }
+
+            let source_path = locate_in(source_objects_path, &hash)?
+                .with_context(|| format!("Object {hash} not found"))?;
+            fs::copy(&source_path, destination_dir.join(&hash.to_hex()[2..]))
+                .with_context(|| format!("Unable to copy object {hash}"))?;
+            transfer.copied += 1;
+        }
+    }
+
+    Ok(transfer)
+}
+
+/// Lists the objects an object points at: a commit's tree and parents, or a
+/// tree's entries.
+pub fn referenced_objects(kind: ObjectKind, body: &[u8]) -> Result<Vec<Hash>> {
+    let mut references = vec![];
+    match kind {
+        ObjectKind::Blob => {}
+        ObjectKind::Commit => {
+            let body = String::from_utf8_lossy(body);
+            for line in body.lines() {
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(hash) = line
+                    .strip_prefix("tree ")
+                    .or_else(|| line.strip_prefix("parent "))
+                {
+                    references.push(Hash::from_hex(hash)?);
+                }
+            }
+        }
+        ObjectKind::Tree => {
+            // entry format: <mode> <name>\0<20 byte hash>
+            let mut position = 0;
+            while position < body.len() {
+                let name_end = body[position..]
+                    .iter()
+                    .position(|&c| c == b'\0')
+                    .context("Invalid tree entry")?
+                    + position;
+                let hash_bytes = body
+                    .get(name_end + 1..name_end + 21)
+                    .context("Invalid tree entry")?;
+                references.push(Hash::new(hash_bytes.try_into().unwrap()));
+                position = name_end + 21;
+            }
+        }
+    }
+
+    Ok(references)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Ok, Result};
+    use tempfile::TempDir;
+
+    use crate::{
+        objects::{commit::Commit, loose_object_paths},
+        refs::resolve,
+        test_utils::TestRepo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_copy_objects() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("dir/b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .file("a.txt", "changed")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let head = resolve("HEAD")?.unwrap();
+        let hashes = reachable_objects(&[head])?;
+        assert_eq!(loose_object_paths()?.len(), hashes.len());
+        let destination = TempDir::new()?;
+        let destination_objects = destination.path().join("objects");
+
+        let tree = *Commit::load(&head)?.tree_hash();
+        let transfer = copy_objects(objects_path(), &destination_objects, &[tree])?;
+        assert_eq!(
+            Transfer {
+                copied: 1,
+                skipped: 0
+            },
+            transfer
+        );
+
+        let transfer = copy_objects(objects_path(), &destination_objects, &hashes)?;
+        assert_eq!(
+            Transfer {
+                copied: hashes.len() - 1,
+                skipped: 1
+            },
+            transfer
+        );
+        for hash in &hashes {
+            assert!(locate_in(&destination_objects, hash)?.is_some());
+        }
+
+        let transfer = copy_reachable_objects(
+            objects_path(),
+            &destination_objects,
+            &[head],
+            &HashSet::new(),
+        )?;
+        assert_eq!(Transfer::default(), transfer);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_objects() -> Result<()> {
+        let repo = TestRepo::new()?;
+        repo.file("a.txt", "a")?
+            .file("dir/b.txt", "b")?
+            .stage(".")?
+            .commit("Initial commit")?
+            .branch("base")?
+            .file("dir/c.txt", "c")?
+            .stage(".")?
+            .commit("Second commit")?;
+        let destination = TempDir::new()?;
+        let base = resolve("refs/heads/base")?.unwrap();
+        copy_reachable_objects(objects_path(), destination.path(), &[base], &HashSet::new())?;
+        let before: HashSet<_> = reachable_objects(&[base])?.into_iter().collect();
+
+        repo.file("a.txt", "changed")?
+            .stage(".")?
+            .commit("Third commit")?;
+        let head = resolve("HEAD")?.unwrap();
+        let expected: HashSet<_> = reachable_objects(&[head])?
+            .into_iter()
+            .filter(|hash| !before.contains(hash))
+            .collect();
+
+        let missing = missing_objects(destination.path(), &[head])?;
+        assert_eq!(missing.len(), expected.len());
+        assert_eq!(expected, missing.into_iter().collect());
+        // Two commits and their root trees, the new dir/ tree, c.txt and a.txt
+        assert_eq!(7, expected.len());
+        assert!(missing_objects(destination.path(), &[base])?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{
+    attributes::{self, Attributes, AutoCrlf},
+    config::Config,
+    hash::Hash,
+    index::Index,
+    objects::{
+        blob::Blob,
+        tree::{Tree, TreeLookup},
+    },
+    paths::{is_metadata_path, repository_root_path},
+    sparse::SparseCheckout,
+};
+
+/// Replaces the working tree with the files of `tree` and makes the index
+/// match it. Anything else in the working tree, tracked or not, is removed.
+/// Line endings are converted per the tree's attributes and `core.autocrlf`.
+/// Files a sparse checkout leaves out are only added to the index.
+pub fn checkout(tree: &Tree) -> Result<()> {
+    let repository_path = repository_root_path();
+    let files = tree.entries_flattened();
+    ensure_no_case_collisions(
+        files
+            .keys()
+            .map(|path| path.strip_prefix(&repository_path).unwrap_or(path)),
+    )
+    .context("Unable to check out")?;
+
+    clear()?;
+    let attributes = Attributes::from_tree(tree)?;
+    let autocrlf = AutoCrlf::load()?;
+    let sparse = SparseCheckout::load()?;
+    for (entry_path, entry_hash) in files {
+        let relative_path = entry_path.strip_prefix(&repository_path)?;
+        if sparse
+            .as_ref()
+            .is_some_and(|sparse| !sparse.includes(relative_path))
+        {
+            continue;
+        }
+        write_file(relative_path, &entry_hash, &attributes, autocrlf)?;
+    }
+
+    Index::load()?.reset_to(tree)
+}
+
+/// Errors if two of `paths` would be the same file on disk, which happens
+/// when they differ only in case and `core.ignorecase` says the filesystem
+/// doesn't tell them apart. Writing both would silently keep just one.
+pub fn ensure_no_case_collisions<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Result<()> {
+    if !Config::load()?
+        .get_bool("core.ignorecase")?
+        .unwrap_or(false)
+    {
+        return Ok(());
+    }
+
+    let mut paths: Vec<_> = paths.into_iter().collect();
+    paths.sort();
+    let mut seen = HashMap::new();
+    for path in paths {
+        let folded = path.to_string_lossy().to_lowercase();
+        if let Some(other) = seen.insert(folded, path) {
+            bail!(
+                "{} and {} differ only in case and would overwrite each other on this case-insensitive filesystem",
+                other.display(),
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Restores `paths` from `tree` into the working tree and the index, leaving
+/// everything else alone. A path naming a directory restores every file under
+/// it. Paths are relative to the repository root or absolute within it.
+/// Returns the restored files, relative to the repository root.
+pub fn restore(tree: &Tree, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let repository_path = repository_root_path();
+    let mut files = BTreeMap::new();
+    for path in paths {
+        let relative_path = path.strip_prefix(&repository_path).unwrap_or(path);
+        match tree.find(relative_path)? {
+            TreeLookup::Found(entry) => {
+                files.insert(relative_path.to_path_buf(), *entry.hash());
+            }
+            TreeLookup::FoundTree(subtree) => {
+                for (file_path, hash) in subtree.files() {
+                    files.insert(relative_path.join(file_path), hash);
+                }
+            }
+            TreeLookup::NotFound => bail!(
+                "Unable to restore. Pathspec \"{}\" did not match any file in {}",
+                relative_path.display(),
+                tree.hash()
+            ),
+        }
+    }
+
+    let attributes = Attributes::from_tree(tree)?;
+    let autocrlf = AutoCrlf::load()?;
+    let mut index = Index::load()?;
+    for (relative_path, hash) in &files {
+        write_file(relative_path, hash, &attributes, autocrlf)?;
+        index.add(repository_path.join(relative_path))?;
+    }
+
+    Ok(files.into_keys().collect())
+}
+
+/// Writes `files`, given by path relative to the repository root, into the
+/// working tree from their blobs, overwriting whatever is there. Line endings
+/// are converted per the working tree's attributes and `core.autocrlf`.
+pub fn write_files(files: &BTreeMap<PathBuf, Hash>) -> Result<()> {
+    let attributes = Attributes::load()?;
+    let autocrlf = AutoCrlf::load()?;
+    for (relative_path, hash) in files {
+        write_file(relative_path, hash, &attributes, autocrlf)?;
+    }
+
+    Ok(())
+}
+
+fn write_file(
+    relative_path: &Path,
+    hash: &Hash,
+    attributes: &Attributes,
+    autocrlf: AutoCrlf,
+) -> Result<()> {
+    let path = repository_root_path().join(relative_path);
+    let body = attributes::to_worktree(
+        attributes,
+        autocrlf,
+        relative_path,
+        Blob::load(hash).body()?,
+    )?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("unable to create file {}", path.display()))?;
+    }
+    fs::write(&path, body).with_context(|| format!("Unable to write file {}", path.display()))
+}
+
+/// Removes everything in the working tree except the `.rygit` directory.
+pub fn clear() -> Result<()> {
+    let directory_contents =
+        fs::read_dir(repository_root_path()).context("Unable to read repository contents")?;
+    for entry in directory_contents {
+        let entry = entry.context("Unable to read repository contents")?;
+        let path = entry.path();
+        if is_metadata_path(&path) {
+            continue;
+        }
+
+        if path.is_file() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Unable to remove file {}", path.display()))?;
+        } else if path.is_dir() {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("Unable to remove directory {}", path.display()))?;
+        }
+    }
+
+    Ok(())
+}
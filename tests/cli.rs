@@ -0,0 +1,466 @@
+use std::{fs, path::Path};
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+fn rygit(current_dir: &Path) -> Command {
+    let mut command = Command::cargo_bin("rygit").unwrap();
+    // Keep the developer's own global config out of the tests
+    command
+        .current_dir(current_dir)
+        .env("RYGIT_CONFIG_GLOBAL", current_dir.join("no-global-config"));
+    command
+}
+
+fn stdout(command: &mut Command) -> String {
+    let output = command.assert().success().get_output().stdout.clone();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_lifecycle() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path();
+
+    rygit(path)
+        .arg("init")
+        .assert()
+        .success()
+        .stdout("Repository initialized!\n");
+
+    fs::write(path.join("a.txt"), "a").unwrap();
+    rygit(path).args(["add", "a.txt"]).assert().success();
+    rygit(path)
+        .args(["commit", "-m", "Initial commit"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("[master (root-commit) "))
+        .stdout(predicate::str::contains("Initial commit"));
+
+    fs::write(path.join("b.txt"), "b").unwrap();
+    rygit(path).args(["add", "b.txt"]).assert().success();
+    rygit(path)
+        .args(["commit", "-m", "Second commit"])
+        .assert()
+        .success();
+
+    let log = stdout(rygit(path).args(["log", "--pretty", "oneline"]));
+    let subjects: Vec<_> = log
+        .lines()
+        .map(|line| line.split_once(' ').unwrap().1)
+        .collect();
+    assert_eq!(vec!["Second commit", "Initial commit"], subjects);
+
+    fs::write(path.join("a.txt"), "changed").unwrap();
+    fs::write(path.join("c.txt"), "c").unwrap();
+    let status = stdout(rygit(path).arg("status"));
+    assert!(status.starts_with("On branch master\n"));
+    assert!(status.contains("\tmodified: a.txt\n"));
+    assert!(status.contains("Untracked files:\n\tc.txt\n"));
+
+    rygit(path).args(["branch", "feature"]).assert().success();
+    rygit(path)
+        .arg("branch")
+        .assert()
+        .success()
+        .stdout("* master\n  feature\n");
+
+    rygit(path).args(["add", "."]).assert().success();
+    rygit(path)
+        .args(["commit", "-q", "-m", "Third commit"])
+        .assert()
+        .success();
+    rygit(path).args(["switch", "feature"]).assert().success();
+    rygit(path)
+        .args(["branch", "--show-current"])
+        .assert()
+        .success()
+        .stdout("feature\n");
+    assert_eq!("a", fs::read_to_string(path.join("a.txt")).unwrap());
+    assert!(!path.join("c.txt").exists());
+}
+
+#[test]
+fn test_log_reverse() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path();
+    rygit(path).arg("init").assert().success();
+    for (name, message) in [("a.txt", "First"), ("b.txt", "Second"), ("c.txt", "Third")] {
+        fs::write(path.join(name), name).unwrap();
+        rygit(path).args(["add", name]).assert().success();
+        rygit(path)
+            .args(["commit", "-q", "-m", message])
+            .assert()
+            .success();
+    }
+    let subjects = |args: &[&str]| -> Vec<String> {
+        stdout(
+            rygit(path)
+                .args(["log", "--pretty", "format:%s"])
+                .args(args),
+        )
+        .lines()
+        .map(str::to_string)
+        .collect()
+    };
+
+    assert_eq!(vec!["First", "Second", "Third"], subjects(&["--reverse"]));
+    assert_eq!(vec!["Second", "Third"], subjects(&["--reverse", "-n", "2"]));
+}
+
+#[test]
+fn test_log_without_commits() {
+    let dir = TempDir::new().unwrap();
+    rygit(dir.path()).arg("init").assert().success();
+
+    rygit(dir.path())
+        .arg("log")
+        .assert()
+        .success()
+        .stdout("Your current branch 'master' does not have any commits yet\n");
+    rygit(dir.path())
+        .args(["log", "--format", "json"])
+        .assert()
+        .success()
+        .stdout("[]\n");
+}
+
+#[test]
+fn test_status_without_commits() {
+    let dir = TempDir::new().unwrap();
+    rygit(dir.path()).arg("init").assert().success();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+
+    let status = stdout(rygit(dir.path()).arg("status"));
+    assert!(status.starts_with("On branch master\n\nNo commits yet\n"));
+    assert!(status.contains("Untracked files:\n\ta.txt\n"));
+}
+
+#[test]
+fn test_outside_repository() {
+    let dir = TempDir::new().unwrap();
+
+    rygit(dir.path())
+        .arg("log")
+        .assert()
+        .code(128)
+        .stdout("")
+        .stderr(predicate::str::contains("Not in a rygit repository"));
+}
+
+#[test]
+fn test_diff_no_index_outside_repository() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("old.txt"), "a\nb\n").unwrap();
+
+    rygit(dir.path())
+        .args(["diff", "--no-index", "old.txt", "-"])
+        .write_stdin("a\nc\n")
+        .assert()
+        .success()
+        .stdout("diff --git a/old.txt b/-\n--- a/old.txt\n+++ b/-\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n");
+}
+
+#[test]
+fn test_diff_find_renames() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path();
+    let content = "line 1\nline 2\nline 3\nline 4\n";
+    let kept = "kept 1\nkept 2\nkept 3\nkept 4\n";
+    rygit(path).arg("init").assert().success();
+    fs::write(path.join("old.txt"), content).unwrap();
+    fs::write(path.join("kept.txt"), kept).unwrap();
+    rygit(path).args(["add", "."]).assert().success();
+    rygit(path)
+        .args(["commit", "-m", "Initial commit"])
+        .assert()
+        .success();
+    fs::remove_file(path.join("old.txt")).unwrap();
+    fs::write(path.join("new.txt"), content.replace("line 4", "line four")).unwrap();
+    fs::write(path.join("kept.txt"), kept.replace("kept 4", "kept four")).unwrap();
+    fs::write(path.join("copy.txt"), kept).unwrap();
+    rygit(path).args(["add", "."]).assert().success();
+    rygit(path)
+        .args(["commit", "-m", "Rename and copy"])
+        .assert()
+        .success();
+
+    let plain = stdout(rygit(path).args(["diff", "HEAD~1", "HEAD"]));
+    assert!(!plain.contains("rename from"));
+
+    rygit(path)
+        .args(["diff", "--find-renames", "HEAD~1", "HEAD"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "diff --git a/old.txt b/new.txt\nsimilarity index 80%\nrename from old.txt\nrename to new.txt\n",
+        ));
+    rygit(path)
+        .args(["diff", "--find-renames=90", "HEAD~1", "HEAD"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("rename from").not());
+    rygit(path)
+        .args(["diff", "--find-copies", "HEAD~1", "HEAD"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "similarity index 100%\ncopy from kept.txt\ncopy to copy.txt\n",
+        ))
+        .stdout(predicate::str::contains(
+            "rename from old.txt\nrename to new.txt\n",
+        ));
+}
+
+#[test]
+fn test_exit_codes() {
+    let dir = TempDir::new().unwrap();
+
+    rygit(dir.path())
+        .arg("status")
+        .assert()
+        .code(128)
+        .stderr(predicate::str::contains("Not in a rygit repository"));
+
+    rygit(dir.path()).arg("init").assert().code(0);
+    rygit(dir.path()).arg("count-objects").assert().code(0);
+    rygit(dir.path())
+        .args(["log", "--pretty", "fancy"])
+        .assert()
+        .code(1);
+    rygit(dir.path())
+        .args(["describe", "missing"])
+        .assert()
+        .code(2);
+    rygit(dir.path())
+        .args([
+            "debug",
+            "dump-object",
+            "0123456789012345678901234567890123456789",
+        ])
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_change_directory() {
+    let repo = TempDir::new().unwrap();
+    let elsewhere = TempDir::new().unwrap();
+    let repo_path = repo.path().to_str().unwrap();
+
+    rygit(elsewhere.path())
+        .args(["-C", repo_path, "init"])
+        .assert()
+        .success();
+    assert!(repo.path().join(".rygit").is_dir());
+    assert!(!elsewhere.path().join(".rygit").exists());
+
+    fs::write(repo.path().join("a.txt"), "a").unwrap();
+    rygit(elsewhere.path())
+        .args(["-C", repo_path, "add", "a.txt"])
+        .assert()
+        .success();
+    rygit(elsewhere.path())
+        .args(["-C", repo_path, "commit", "-q", "-m", "Initial commit"])
+        .assert()
+        .success();
+    let head = fs::read_to_string(repo.path().join(".rygit/refs/heads/master")).unwrap();
+    assert_eq!(40, head.trim().len());
+
+    rygit(elsewhere.path())
+        .args(["-C", "missing", "status"])
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_git_dir() {
+    let work_tree = TempDir::new().unwrap();
+    let git_dir = TempDir::new().unwrap();
+    let rygit_with_git_dir = |args: &[&str]| {
+        let mut command = rygit(work_tree.path());
+        command.args(args).env("RYGIT_DIR", git_dir.path());
+        command
+    };
+
+    rygit_with_git_dir(&["init"]).assert().success();
+    fs::write(work_tree.path().join("a.txt"), "a").unwrap();
+    rygit_with_git_dir(&["add", "a.txt"]).assert().success();
+    rygit_with_git_dir(&["commit", "-q", "-m", "Initial commit"])
+        .assert()
+        .success();
+
+    let head = fs::read_to_string(git_dir.path().join("refs/heads/master")).unwrap();
+    assert_eq!(40, head.trim().len());
+    assert!(git_dir.path().join("objects").is_dir());
+    let work_tree_files: Vec<_> = fs::read_dir(work_tree.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(vec!["a.txt"], work_tree_files);
+
+    let rev_list = stdout(rygit(work_tree.path()).args([
+        "--git-dir",
+        git_dir.path().to_str().unwrap(),
+        "rev-list",
+        "HEAD",
+    ]));
+    assert_eq!(head.trim(), rev_list.trim());
+}
+
+#[test]
+fn test_locked_repository() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path();
+    rygit(path).arg("init").assert().success();
+    fs::write(path.join("a.txt"), "a").unwrap();
+
+    let lock_path = path.join(".rygit/rygit.lock");
+    fs::write(&lock_path, "").unwrap();
+    rygit(path)
+        .args(["add", "a.txt"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("Another rygit process is running"));
+    rygit(path).arg("status").assert().success();
+    assert!(lock_path.exists());
+
+    fs::remove_file(&lock_path).unwrap();
+    rygit(path).args(["add", "a.txt"]).assert().success();
+    assert!(!lock_path.exists());
+}
+
+#[test]
+fn test_quiet() {
+    let dir = TempDir::new().unwrap();
+
+    rygit(dir.path())
+        .args(["init", "--quiet"])
+        .assert()
+        .success()
+        .stdout("");
+    assert!(dir.path().join(".rygit").is_dir());
+
+    rygit(dir.path())
+        .args(["-q", "describe", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_empty().not());
+}
+
+#[test]
+fn test_global_config() {
+    let dir = TempDir::new().unwrap();
+    let home = TempDir::new().unwrap();
+    let global_config = home.path().join(".rygitconfig");
+    let rygit_with_global = |args: &[&str]| {
+        let mut command = rygit(dir.path());
+        command
+            .args(args)
+            .env("RYGIT_CONFIG_GLOBAL", &global_config);
+        command
+    };
+
+    rygit_with_global(&["config", "--global", "user.name", "Donny Kerabatsos"])
+        .assert()
+        .success();
+    rygit_with_global(&["config", "--global", "user.email", "donny@example.com"])
+        .assert()
+        .success();
+    assert!(global_config.is_file());
+
+    rygit_with_global(&["init"]).assert().success();
+    rygit_with_global(&["config", "user.email", "donny@work.example.com"])
+        .assert()
+        .success();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    rygit_with_global(&["add", "a.txt"]).assert().success();
+    rygit_with_global(&["commit", "-q", "-m", "Initial commit"])
+        .assert()
+        .success();
+
+    assert_eq!(
+        "Donny Kerabatsos donny@work.example.com\n",
+        stdout(&mut rygit_with_global(&[
+            "log",
+            "--pretty",
+            "format:%an %ae"
+        ]))
+    );
+    assert_eq!(
+        "donny@example.com\n",
+        stdout(&mut rygit_with_global(&[
+            "config",
+            "--global",
+            "user.email"
+        ]))
+    );
+}
+
+#[test]
+fn test_bundle() {
+    let origin = TempDir::new().unwrap();
+    let destination = TempDir::new().unwrap();
+    rygit(origin.path()).arg("init").assert().success();
+    fs::write(origin.path().join("a.txt"), "a").unwrap();
+    rygit(origin.path())
+        .args(["add", "a.txt"])
+        .assert()
+        .success();
+    rygit(origin.path())
+        .args(["commit", "-q", "-m", "Initial commit"])
+        .assert()
+        .success();
+    rygit(origin.path())
+        .args(["bundle", "create", "repo.bundle", "master"])
+        .assert()
+        .success();
+    let head = stdout(rygit(origin.path()).args(["rev-list", "HEAD"]));
+
+    rygit(destination.path()).arg("init").assert().success();
+    let bundle_path = origin.path().join("repo.bundle");
+    rygit(destination.path())
+        .args(["bundle", "unbundle", bundle_path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(format!("{} refs/heads/master\n", head.trim()));
+    rygit(destination.path())
+        .args(["debug", "dump-object", head.trim()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Initial commit"));
+}
+
+#[test]
+fn test_notes() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path();
+    rygit(path).arg("init").assert().success();
+    fs::write(path.join("a.txt"), "a").unwrap();
+    rygit(path).args(["add", "a.txt"]).assert().success();
+    rygit(path)
+        .args(["commit", "-q", "-m", "Initial commit"])
+        .assert()
+        .success();
+    let head = stdout(rygit(path).args(["rev-list", "HEAD"]));
+
+    rygit(path)
+        .args(["notes", "show"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No note found"));
+    rygit(path)
+        .args(["notes", "add", "-m", "Reviewed"])
+        .assert()
+        .success();
+    rygit(path)
+        .args(["notes", "show", "HEAD"])
+        .assert()
+        .success()
+        .stdout("Reviewed\n");
+    assert_eq!(head, stdout(rygit(path).args(["rev-list", "HEAD"])));
+    assert!(
+        stdout(rygit(path).arg("log")).ends_with("    Initial commit\n\nNotes:\n    Reviewed\n")
+    );
+}
@@ -0,0 +1,36 @@
+use std::fs;
+
+use rygit::{Repository, objects::signature::Signature};
+use tempfile::TempDir;
+
+#[test]
+fn test_init_add_commit() {
+    let dir = TempDir::new().unwrap();
+    let repository = Repository::init(dir.path()).unwrap();
+    assert!(repository.head().unwrap().is_none());
+
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/main.rs"), "main").unwrap();
+    repository.add("a.txt").unwrap();
+    repository.add("src").unwrap();
+    assert_eq!(2, repository.index().unwrap().files().len());
+
+    let author = Signature::new("Larry Sellers", "lsellers@test.com");
+    let commit = repository.commit("Initial commit", author).unwrap();
+
+    let head = repository.head().unwrap().unwrap();
+    assert_eq!(commit.hash(), head.hash());
+    assert_eq!("Initial commit", head.message());
+    assert_eq!("Larry Sellers", head.author().name());
+    assert!(head.parent_hashes().is_empty());
+    assert_eq!(
+        head.hash(),
+        repository.find_commit("master").unwrap().hash()
+    );
+    assert_eq!("master", repository.current_branch().unwrap().name());
+
+    let tree = head.tree().unwrap();
+    let names: Vec<_> = tree.entries().iter().map(|entry| entry.name()).collect();
+    assert_eq!(vec!["a.txt", "src"], names);
+}